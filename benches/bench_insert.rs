@@ -1,16 +1,18 @@
 //! Benchmarks for nDB.
 //!
 //! Measures throughput and latency across all layers:
-//! - Insert (in-memory, lazy, immediate)
+//! - Insert (in-memory, lazy, immediate, batch)
 //! - Get by ID (Layer 1)
 //! - Find by field (Layer 2)
 //! - Query with JSON AST (Layer 3)
-//! - Indexed vs non-indexed queries
-//! - Compaction
+//! - Indexed vs non-indexed queries, text search, aggregation
+//! - Flush and compaction
 //! - Bulk operations
+//!
+//! Run with `cargo bench`; filter with e.g. `cargo bench -- query`.
 
 use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
-use ndb::{Database, Persistence};
+use ndb::{Aggregation, Database, Persistence};
 use serde_json::json;
 use tempfile::TempDir;
 
@@ -72,6 +74,20 @@ fn bench_insert_bulk(c: &mut Criterion) {
     group.finish();
 }
 
+fn bench_insert_batch(c: &mut Criterion) {
+    let mut group = c.benchmark_group("insert_bulk");
+    group.bench_function("batch_1000_persist", |b| {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("bench.jsonl");
+        let db = Database::open(&path).unwrap();
+        b.iter(|| {
+            let docs = (0..1000).map(|i| json!({"i": i, "data": "benchmark"})).collect();
+            black_box(db.insert_batch(docs).unwrap());
+        });
+    });
+    group.finish();
+}
+
 // ─── Layer 1: Get by ID ──────────────────────────────────────────────
 
 fn bench_get(c: &mut Criterion) {
@@ -207,6 +223,60 @@ fn bench_query_comparison(c: &mut Criterion) {
     group.finish();
 }
 
+fn bench_query_indexed(c: &mut Criterion) {
+    let mut group = c.benchmark_group("query");
+    group.bench_function("hash_index_eq_10k", |b| {
+        let db = Database::open_in_memory().unwrap();
+        for i in 0..10000 {
+            db.insert(json!({"status": if i % 100 == 0 { "target" } else { "other" }, "i": i})).unwrap();
+        }
+        db.create_index("status").unwrap();
+        b.iter(|| {
+            let results = db.query(json!({"status": "target", "i": {"$gte": 5000}}));
+            black_box(results);
+        });
+    });
+    group.finish();
+}
+
+fn bench_query_text(c: &mut Criterion) {
+    let mut group = c.benchmark_group("query");
+    let words = ["rust", "json", "log", "index", "query", "memory", "disk", "node"];
+    for indexed in [false, true] {
+        let db = Database::open_in_memory().unwrap();
+        for i in 0..10000 {
+            let body = format!("{} {} {}", words[i % 8], words[i % 7], words[i % 5]);
+            db.insert(json!({"body": body})).unwrap();
+        }
+        if indexed {
+            db.create_text_index("body").unwrap();
+        }
+        let name = if indexed { "text_index_10k" } else { "text_scan_10k" };
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                let results = db.query(json!({"body": {"$text": "rust disk"}}));
+                black_box(results);
+            });
+        });
+    }
+    group.finish();
+}
+
+fn bench_aggregate(c: &mut Criterion) {
+    let mut group = c.benchmark_group("aggregate");
+    let db = Database::open_in_memory().unwrap();
+    for i in 0..10000 {
+        db.insert(json!({"source": format!("s{}", i % 20), "score": i})).unwrap();
+    }
+    group.bench_function("distinct_10k", |b| {
+        b.iter(|| black_box(db.aggregate(json!({}), "source", Aggregation::Distinct)));
+    });
+    group.bench_function("avg_10k", |b| {
+        b.iter(|| black_box(db.aggregate(json!({}), "score", Aggregation::Avg)));
+    });
+    group.finish();
+}
+
 // ─── Iteration ───────────────────────────────────────────────────────
 
 fn bench_iter(c: &mut Criterion) {
@@ -261,6 +331,24 @@ fn bench_delete(c: &mut Criterion) {
     group.finish();
 }
 
+// ─── Flush ───────────────────────────────────────────────────────────
+
+fn bench_flush(c: &mut Criterion) {
+    let mut group = c.benchmark_group("flush");
+    group.bench_function("after_100_inserts", |b| {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("flush.jsonl");
+        let db = Database::open(&path).unwrap();
+        b.iter(|| {
+            for i in 0..100 {
+                db.insert(json!({"i": i})).unwrap();
+            }
+            db.flush().unwrap();
+        });
+    });
+    group.finish();
+}
+
 // ─── Compaction ──────────────────────────────────────────────────────
 
 fn bench_compact(c: &mut Criterion) {
@@ -293,6 +381,7 @@ criterion_group!(
     bench_insert_lazy,
     bench_insert_immediate,
     bench_insert_bulk,
+    bench_insert_batch,
     bench_get,
     bench_get_large_db,
     bench_find_no_index,
@@ -301,9 +390,13 @@ criterion_group!(
     bench_query_simple_eq,
     bench_query_and_or,
     bench_query_comparison,
+    bench_query_indexed,
+    bench_query_text,
+    bench_aggregate,
     bench_iter,
     bench_update,
     bench_delete,
+    bench_flush,
     bench_compact,
 );
 criterion_main!(benches);
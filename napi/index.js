@@ -10,6 +10,7 @@
 
 const { existsSync } = require('fs');
 const { join, dirname } = require('path');
+const { Writable } = require('stream');
 
 // Determine the correct native binary name based on platform
 function getNativeBinaryName() {
@@ -99,6 +100,44 @@ try {
   throw new Error(`Failed to load native module from ${nativePath}: ${e.message}`);
 }
 
+// ─── Error Codes ─────────────────────────────────────────────────────
+// Native errors start with their code in brackets, e.g.
+// "[NDB_NOT_FOUND] Get failed: not found: abc". Move it to `err.code`.
+
+// Mirrors `Error::is_retryable` in the Rust crate
+const RETRYABLE_CODES = new Set(['NDB_THROTTLED', 'NDB_VERSION_CONFLICT', 'NDB_DATABASE_LOCKED']);
+
+function withCode(e) {
+  const match = e instanceof Error && /^\[(NDB_[A-Z_]+)\] /.exec(e.message);
+  if (match) {
+    e.message = e.message.slice(match[0].length);
+    if (typeof e.stack === 'string') e.stack = e.stack.replace(match[0], '');
+    e.code = match[1];
+    e.retryable = RETRYABLE_CODES.has(match[1]);
+  }
+  return e;
+}
+
+function callNative(fn) {
+  try {
+    const result = fn();
+    return result instanceof Promise ? result.catch((e) => { throw withCode(e); }) : result;
+  } catch (e) {
+    throw withCode(e);
+  }
+}
+
+// Native database whose method errors carry `code`
+function wrapNative(native) {
+  return new Proxy(native, {
+    get(target, name) {
+      const value = target[name];
+      if (typeof value !== 'function') return value;
+      return (...args) => callNative(() => value.apply(target, args));
+    },
+  });
+}
+
 // ─── High-Level JS Wrapper ──────────────────────────────────────────
 // The native module works with JSON strings for documents.
 // This wrapper provides the ergonomic JS API that auto-serializes.
@@ -114,24 +153,32 @@ try {
  */
 class Database {
   constructor(path) {
-    this._native = new nativeBinding.Database(path);
+    this._native = wrapNative(callNative(() => new nativeBinding.Database(path)));
   }
 
   /**
    * Open or create a database with optional persistence config.
    * @param {string} path - Path to the database file.
    * @param {object} [options] - Persistence options.
-   * @param {string} [options.persistence] - "lazy" | "immediate" | "scheduled"
+   * @param {string} [options.persistence] - "lazy" | "immediate" | "scheduled" | "group"
+   * @param {boolean} [options.strict] - Throw on unreadable log lines instead of skipping them. Default: false.
    * @param {number} [options.interval] - Seconds between flushes (scheduled mode).
+   * @param {number} [options.groupCommitMs] - Maximum milliseconds between syncs (group mode). Default: 10.
    * @param {number} [options.trash_ttl] - Auto-empty trash TTL in seconds. Default: no auto-empty.
    * @param {number} [options.trash_purge_interval] - Background interval in seconds to check for expired trash. Default: 3600 (1 hour).
+   * @param {object} [options.schema] - Typed field map validated on every write, e.g. { year: { type: 'integer', required: true } }.
+   * @param {number} [options.maxDocBytes] - Reject documents whose JSON is larger than this. Default: unlimited.
+   * @param {number} [options.maxIdLength] - Reject IDs (prefix included) longer than this. Default: unlimited.
+   * @param {number} [options.maxWritesPerSec] - Throttle writes above this many documents per second. Default: unlimited.
+   * @param {number} [options.maxLogBytes] - Reject writes that would grow the log file past this many bytes. Default: unlimited.
+   * @param {number} [options.idempotencyWindow] - Seconds idempotency keys are remembered. Default: 3600.
    * @returns {Database}
    */
   static open(path, options) {
     const db = new Database(path);
     // If options provided, reopen with options via native open
     if (options) {
-      db._native = nativeBinding.Database.open(path, options);
+      db._native = wrapNative(callNative(() => nativeBinding.Database.open(path, options)));
     }
     return db;
   }
@@ -142,17 +189,42 @@ class Database {
    */
   static openInMemory() {
     const db = Object.create(Database.prototype);
-    db._native = nativeBinding.Database.openInMemory();
+    db._native = wrapNative(callNative(() => nativeBinding.Database.openInMemory()));
+    return db;
+  }
+
+  /**
+   * Open a detached in-memory view of the database as of log record `seq`.
+   * @param {string} path - Path to the JSONL file.
+   * @param {number} seq - Number of log records to replay.
+   * @returns {Database}
+   */
+  static openAt(path, seq) {
+    const db = Object.create(Database.prototype);
+    db._native = wrapNative(callNative(() => nativeBinding.Database.openAt(path, seq)));
     return db;
   }
 
   /**
    * Insert a document. Returns the generated NanoID.
    * @param {object} doc - Document to insert.
+   * @param {object} [options]
+   * @param {string} [options.durability] - "synced" or "buffered": override the persistence mode for this call.
    * @returns {string} Generated _id.
    */
-  insert(doc) {
-    return this._native.insert(JSON.stringify(doc));
+  insert(doc, options) {
+    return this._native.insert(JSON.stringify(doc), options && options.durability);
+  }
+
+  /**
+   * Insert a document once per idempotency key. A retry with the same key
+   * returns the first insert's _id and writes nothing.
+   * @param {string} key - Client request key, e.g. a UUID per logical write.
+   * @param {object} doc - Document to insert.
+   * @returns {string} _id of the first insert with this key.
+   */
+  insertIdempotent(key, doc) {
+    return this._native.insertIdempotent(key, JSON.stringify(doc));
   }
 
   /**
@@ -165,6 +237,74 @@ class Database {
     return this._native.insertWithPrefix(prefix, JSON.stringify(doc));
   }
 
+  /**
+   * Handle on a tenant namespace. Its documents get IDs `{name}_{random}`;
+   * queries, counts and deleteAll only see that namespace.
+   * @param {string} name - 1 to 64 ASCII letters or digits.
+   * @returns {Namespace}
+   */
+  namespace(name) {
+    return new Namespace(this._native, name);
+  }
+
+  /**
+   * Insert several documents atomically (all or nothing on crash recovery).
+   * @param {object[]} docs - Documents to insert.
+   * @param {object} [options]
+   * @param {string} [options.durability] - "synced" or "buffered": override the persistence mode for this call.
+   * @returns {string[]} Generated _ids, in input order.
+   */
+  insertBatch(docs, options) {
+    return this._native.insertBatch(JSON.stringify(docs), options && options.durability);
+  }
+
+  /**
+   * Insert several documents off the main thread.
+   * @param {object[]} docs - Documents to insert.
+   * @param {object} [options]
+   * @param {string} [options.durability] - "synced" or "buffered": override the persistence mode for this call.
+   * @returns {Promise<string[]>} Generated _ids, in input order.
+   */
+  async insertBatchAsync(docs, options) {
+    return this._native.insertBatchAsync(JSON.stringify(docs), options && options.durability);
+  }
+
+  /**
+   * Writable object stream for bulk loads. Documents are inserted in
+   * batches off the main thread; `write()` returns false once `batchSize`
+   * documents are waiting, so `pipe`/`pipeline` pause the producer.
+   *
+   * ```js
+   * await pipeline(source, db.createInsertStream({ batchSize: 5000 }));
+   * ```
+   *
+   * @param {object} [options]
+   * @param {number} [options.batchSize=1000] - Documents per batch, and the buffer size.
+   * @param {string} [options.durability] - Per-batch override, e.g. "buffered" for a backfill.
+   * @returns {Writable} Stream with an `inserted` count.
+   */
+  createInsertStream(options = {}) {
+    const db = this;
+    const batchSize = options.batchSize || 1000;
+    const stream = new Writable({
+      objectMode: true,
+      highWaterMark: batchSize,
+      async writev(chunks, callback) {
+        try {
+          for (let i = 0; i < chunks.length; i += batchSize) {
+            const docs = chunks.slice(i, i + batchSize).map((c) => c.chunk);
+            stream.inserted += (await db.insertBatchAsync(docs, { durability: options.durability })).length;
+          }
+          callback();
+        } catch (e) {
+          callback(e);
+        }
+      }
+    });
+    stream.inserted = 0;
+    return stream;
+  }
+
   /**
    * Get a document by ID.
    * @param {string} id - Document ID.
@@ -184,6 +324,27 @@ class Database {
     this._native.update(id, JSON.stringify(doc));
   }
 
+  /**
+   * Update a document only if its `_version` is still `expected`.
+   * Throws "version conflict ..." if another write got there first.
+   * @param {string} id - Document ID.
+   * @param {object} doc - New document content.
+   * @param {number} expected - Version the update is based on.
+   * @returns {number} The new version.
+   */
+  updateIfVersion(id, doc, expected) {
+    return this._native.updateIfVersion(id, JSON.stringify(doc), expected);
+  }
+
+  /**
+   * Current `_version` of a document.
+   * @param {string} id - Document ID.
+   * @returns {number}
+   */
+  version(id) {
+    return this._native.version(id);
+  }
+
   /**
    * Delete a document by ID (soft delete).
    * @param {string} id - Document ID.
@@ -192,6 +353,25 @@ class Database {
     this._native.delete(id);
   }
 
+  /**
+   * Delete a document once per idempotency key. A retry with the same key
+   * succeeds without deleting anything.
+   * @param {string} key - Client request key.
+   * @param {string} id - Document ID.
+   */
+  deleteIdempotent(key, id) {
+    this._native.deleteIdempotent(key, id);
+  }
+
+  /**
+   * Delete a document only if its `_version` is still `expected`.
+   * @param {string} id - Document ID.
+   * @param {number} expected - Version the delete is based on.
+   */
+  deleteIfVersion(id, expected) {
+    this._native.deleteIfVersion(id, expected);
+  }
+
   /**
    * Append a value to an array field.
    * @param {string} id - Document ID.
@@ -278,10 +458,15 @@ class Database {
   /**
    * Execute a JSON AST query.
    * @param {object} ast - Query AST.
+   * @param {object} [options]
+   * @param {Operation} [options.operation] - Cancels the scan.
    * @returns {object[]}
    */
-  async query(ast) {
-    return JSON.parse(await this._native.query(JSON.stringify(ast)));
+  async query(ast, options = {}) {
+    return JSON.parse(await this._native.query(
+      JSON.stringify(ast),
+      options.operation && options.operation._native
+    ));
   }
 
   /**
@@ -292,11 +477,31 @@ class Database {
    * @param {number} [options.offset] - Skip first N results.
    * @param {string} [options.sortBy] - Field to sort by.
    * @param {string} [options.sortDir] - "asc" or "desc".
+   * @param {Operation} [options.operation] - Cancels the scan.
    * @returns {object[]}
    */
   async queryWith(ast, options) {
     const opts = options || {};
     return JSON.parse(await this._native.queryWith(
+      JSON.stringify(ast),
+      opts.limit,
+      opts.offset,
+      opts.sortBy,
+      opts.sortDir,
+      opts.operation && opts.operation._native
+    ));
+  }
+
+  /**
+   * Run a query like `queryWith` and report how it ran: whether an index
+   * was used, how many documents were scanned and matched, and per-phase timings.
+   * @param {object} ast - Query AST.
+   * @param {object} [options] - { limit, offset, sortBy, sortDir }.
+   * @returns {{results: object[], explanation: object}}
+   */
+  queryExplain(ast, options) {
+    const opts = options || {};
+    return JSON.parse(this._native.queryExplain(
       JSON.stringify(ast),
       opts.limit,
       opts.offset,
@@ -305,6 +510,47 @@ class Database {
     ));
   }
 
+  /**
+   * Execute several queries in one pass over the data.
+   * @param {Array<{ast: object, limit?: number, offset?: number, sortBy?: string, sortDir?: string}>} queries
+   * @returns {Promise<object[][]>} One result array per query, in order.
+   */
+  async queryBatch(queries) {
+    return JSON.parse(await this._native.queryBatch(JSON.stringify(queries)));
+  }
+
+  /**
+   * Execute a JSON AST query and group matches by a field value.
+   * @param {object} ast - JSON AST query.
+   * @param {string} groupBy - Field to group by (dot notation allowed).
+   * @param {number} groupSize - Max documents kept per group.
+   * @param {object} [options] - { limit, offset, sortBy, sortDir }; limit/offset page over groups.
+   * @returns {Promise<Array<{key: any, docs: object[]}>>}
+   */
+  async queryGrouped(ast, groupBy, groupSize, options) {
+    const opts = options || {};
+    return JSON.parse(await this._native.queryGrouped(
+      JSON.stringify(ast),
+      groupBy,
+      groupSize,
+      opts.limit,
+      opts.offset,
+      opts.sortBy,
+      opts.sortDir
+    ));
+  }
+
+  /**
+   * Aggregate a field over the documents matching a JSON AST query.
+   * @param {object} ast - JSON AST query (`{}` for all documents).
+   * @param {string} field - Field to aggregate (dot notation allowed).
+   * @param {string} kind - "count", "min", "max", "avg" or "distinct".
+   * @returns {Promise<number|string|null|Array<{value: any, count: number}>>}
+   */
+  async aggregate(ast, field, kind) {
+    return JSON.parse(await this._native.aggregate(JSON.stringify(ast), field, kind));
+  }
+
   /**
    * Create a hash index on a field.
    * @param {string} field - Field name.
@@ -321,6 +567,30 @@ class Database {
     this._native.createBtreeIndex(field);
   }
 
+  /**
+   * Create a full-text index on a field (for `$text` queries).
+   * @param {string} field - Field name.
+   */
+  createTextIndex(field) {
+    this._native.createTextIndex(field);
+  }
+
+  /**
+   * Build an index off the main thread. Rejects without installing anything
+   * if the operation is cancelled.
+   * @param {string} field - Field name.
+   * @param {object} [options]
+   * @param {boolean} [options.btree=false] - Build a BTree index instead of a hash index.
+   * @param {Operation} [options.operation] - Progress callback and cancellation.
+   */
+  async createIndexAsync(field, options = {}) {
+    await this._native.createIndexAsync(
+      field,
+      options.btree,
+      options.operation && options.operation._native
+    );
+  }
+
   /**
    * Drop an index.
    * @param {string} field - Field name.
@@ -340,9 +610,11 @@ class Database {
 
   /**
    * Compact the database.
+   * @param {object} [options]
+   * @param {Operation} [options.operation] - Progress callback and cancellation.
    */
-  async compact() {
-    await this._native.compact();
+  async compact(options = {}) {
+    await this._native.compact(options.operation && options.operation._native);
   }
 
   /**
@@ -352,6 +624,42 @@ class Database {
     this._native.flush();
   }
 
+  /**
+   * User metadata stored with the database in `meta.json`.
+   * @returns {object} Empty object if none was set.
+   */
+  metadata() {
+    return JSON.parse(this._native.metadata());
+  }
+
+  /**
+   * Replace the user metadata, e.g. `{ model: 'text-embedding-3-small' }`.
+   * Written to `meta.json` before this returns.
+   * @param {object} metadata - Plain JSON object.
+   */
+  setMetadata(metadata) {
+    this._native.setMetadata(JSON.stringify(metadata));
+  }
+
+  /**
+   * Switch persistence mode on the open database, e.g. to 'lazy' for a bulk
+   * load and back afterwards. Syncs everything written so far first.
+   * @param {string} mode - "lazy" | "immediate" | "scheduled" | "group"
+   * @param {object} [options]
+   * @param {number} [options.interval] - Seconds between flushes (scheduled mode). Default: 60.
+   * @param {number} [options.groupCommitMs] - Maximum milliseconds between syncs (group mode). Default: 10.
+   */
+  setPersistence(mode, options) {
+    this._native.setPersistence(mode, options);
+  }
+
+  /**
+   * Flush data to disk off the main thread.
+   */
+  async flushAsync() {
+    await this._native.flushAsync();
+  }
+
   /**
    * Restore a deleted document.
    * @param {string} id - Document ID.
@@ -360,6 +668,23 @@ class Database {
     this._native.restore(id);
   }
 
+  /**
+   * Number of records in the current log file.
+   * @returns {number}
+   */
+  logSeq() {
+    return this._native.logSeq();
+  }
+
+  /**
+   * Roll every document back to its state as of log record `seq`.
+   * @param {number} seq - Log sequence number to return to.
+   * @returns {number} Number of documents changed.
+   */
+  restoreTo(seq) {
+    return this._native.restoreTo(seq);
+  }
+
   /**
    * Get list of deleted document IDs.
    * @returns {string[]}
@@ -368,6 +693,84 @@ class Database {
     return this._native.deletedIds();
   }
 
+  /**
+   * Subscribe to the change feed. Called once per change, in write order.
+   * Events look like `{ seq, kind, id, doc }` where kind is one of
+   * "insert" | "update" | "delete" | "flush" | "compact".
+   * @param {function(object): void} callback - Receives each change event.
+   */
+  subscribe(callback) {
+    this._native.subscribe((event) => callback(JSON.parse(event)));
+  }
+
+  /**
+   * Sequence number of the most recent change (0 if nothing changed since open).
+   * @returns {number}
+   */
+  changeSeq() {
+    return this._native.changeSeq();
+  }
+
+  /**
+   * Snapshot of document counts, operation counters and latency histograms.
+   * Histogram buckets are `[upperBoundSeconds, cumulativeCount]` pairs.
+   * @returns {object}
+   */
+  metrics() {
+    return JSON.parse(this._native.metrics());
+  }
+
+  /**
+   * Metrics in the Prometheus text exposition format, for a scrape endpoint.
+   * @returns {string}
+   */
+  metricsPrometheus() {
+    return this._native.metricsPrometheus();
+  }
+
+  /**
+   * Drain warnings recorded since the last call: failures of best-effort
+   * side effects such as archiving a deleted document.
+   * @returns {Array<{kind: string, message: string, at: number}>}
+   */
+  takeWarnings() {
+    return JSON.parse(this._native.takeWarnings());
+  }
+
+  /**
+   * Estimated heap use in bytes: `{ documents, indexes, other }`.
+   * Walks every document, so poll it rather than calling it per request.
+   * @returns {object}
+   */
+  memoryUsage() {
+    return JSON.parse(this._native.memoryUsage());
+  }
+
+  /**
+   * Kind, entry count and estimated heap bytes of every index, sorted by field.
+   * @returns {{field: string, kind: string, entries: number, heapBytes: number}[]}
+   */
+  indexStatus() {
+    return JSON.parse(this._native.indexStatus());
+  }
+
+  /**
+   * Check the log and live state, collecting every problem found.
+   * @param {{quick?: boolean}} [options] - `quick` checks only the log structure.
+   * @returns {{records: number, documents: number, issues: {kind: string, line: number|null, id: string|null, detail: string}[]}}
+   */
+  verify(options = {}) {
+    return JSON.parse(this._native.verify(options.quick));
+  }
+
+  /**
+   * Cheap readiness check. Throws if a background fsync failed, the log can
+   * no longer be opened for appending, or `meta.json` no longer parses.
+   */
+  health() {
+    this._native.health();
+  }
+
   /**
    * Store a file in a bucket.
    * @param {string} bucket - Bucket name.
@@ -430,7 +833,102 @@ class Database {
   }
 }
 
+/**
+ * Progress callback and cancellation token for `compact`, `createIndexAsync`,
+ * `query` and `queryWith`.
+ *
+ * ```js
+ * const op = new Operation((p) => console.log(p.phase, p.done, '/', p.total));
+ * const done = db.compact({ operation: op });
+ * op.cancel(); // rejects `done`, the log is left as it was
+ * ```
+ */
+/**
+ * Documents of one tenant namespace, returned by `Database.namespace`.
+ */
+class Namespace {
+  constructor(native, name) {
+    this._native = native;
+    this.name = name;
+  }
+
+  /**
+   * Insert a document into the namespace.
+   * @param {object} doc - Document to insert.
+   * @returns {string} Generated _id, `{name}_{random}`.
+   */
+  insert(doc) {
+    return this._native.namespaceInsert(this.name, JSON.stringify(doc));
+  }
+
+  /**
+   * Get a document by ID. IDs of other namespaces throw NDB_NOT_FOUND.
+   * @param {string} id - Document _id.
+   * @returns {object}
+   */
+  get(id) {
+    return JSON.parse(this._native.namespaceGet(this.name, id));
+  }
+
+  /**
+   * Number of documents in the namespace.
+   * @returns {number}
+   */
+  len() {
+    return this._native.namespaceLen(this.name);
+  }
+
+  /**
+   * Execute a JSON AST query against this namespace only.
+   * @param {object} ast - Query AST.
+   * @param {object} [options] - { limit, offset, sortBy, sortDir }.
+   * @returns {object[]}
+   */
+  query(ast, options) {
+    const opts = options || {};
+    return JSON.parse(this._native.namespaceQuery(
+      this.name,
+      JSON.stringify(ast),
+      opts.limit,
+      opts.offset,
+      opts.sortBy,
+      opts.sortDir
+    ));
+  }
+
+  /**
+   * Delete every document in the namespace as one atomic batch.
+   * @returns {number} Documents deleted.
+   */
+  deleteAll() {
+    return this._native.namespaceDeleteAll(this.name);
+  }
+}
+
+class Operation {
+  /**
+   * @param {function(object): void} [onProgress] - Receives `{ operation, phase, done, total }`.
+   */
+  constructor(onProgress) {
+    this._native = new nativeBinding.Operation(
+      onProgress ? (progress) => onProgress(JSON.parse(progress)) : undefined
+    );
+  }
+
+  /** Stop the operation at its next check. */
+  cancel() {
+    this._native.cancel();
+  }
+
+  /** @returns {boolean} True once `cancel` was called. */
+  get cancelled() {
+    return this._native.cancelled;
+  }
+}
+
 // ─── Exports ─────────────────────────────────────────────────────────
 
 module.exports.Database = Database;
+module.exports.Operation = Operation;
+module.exports.Namespace = Namespace;
 module.exports.NATIVE_PATH = nativePath;
@@ -6,15 +6,63 @@
 //! Following the nVDB pattern: direct napi-rs per package, no shared bridge.
 
 use napi::bindgen_prelude::*;
+use napi::threadsafe_function::{ErrorStrategy, ThreadsafeFunction, ThreadsafeFunctionCallMode};
+use napi::JsFunction;
 use napi_derive::napi;
-use std::sync::{Arc, RwLock};
+use parking_lot::RwLock;
+use std::sync::Arc;
 
-use ndb::{Database as RustDatabase, Persistence, SortDir, QueryOptions};
+use ndb::{Aggregation, Database as RustDatabase, Durability, Limits, OperationHandle, Persistence, QueryGroup, QueryOptions, Schema, SortDir};
+
+/// Map an ndb error to a JS error whose message starts with its code in
+/// brackets. `index.js` moves the code to `err.code`; async tasks can only
+/// reject with a napi `Status`, so the message is the one channel that
+/// reaches JS from every binding.
+fn db_error(message: &'static str) -> impl FnOnce(ndb::Error) -> Error {
+    move |e| Error::from_reason(format!("[{}] {}: {}", e.code(), message, e))
+}
+
+// ─── Operations ────────────────────────────────────────────────
+
+/// Progress callback and cancellation token for `compact` and
+/// `createIndexAsync`. The callback receives one JSON string per report
+/// (`{ operation, phase, done, total }`) and does not keep the process alive.
+#[napi]
+pub struct Operation {
+    handle: OperationHandle,
+}
+
+#[napi]
+impl Operation {
+    #[napi(constructor)]
+    pub fn new(env: Env, on_progress: Option<JsFunction>) -> Result<Self> {
+        let mut handle = OperationHandle::new();
+        if let Some(callback) = on_progress {
+            let mut tsfn: ThreadsafeFunction<String, ErrorStrategy::Fatal> =
+                callback.create_threadsafe_function(0, |ctx| Ok(vec![ctx.value]))?;
+            tsfn.unref(&env)?;
+            handle = handle.on_progress(move |p| {
+                let json = serde_json::to_string(p).expect("progress serializes to JSON");
+                tsfn.call(json, ThreadsafeFunctionCallMode::NonBlocking);
+            });
+        }
+        Ok(Operation { handle })
+    }
+
+    /// Stop the operation at its next check. It then fails and changes nothing.
+    #[napi]
+    pub fn cancel(&self) { self.handle.cancel() }
+
+    /// True once `cancel` was called.
+    #[napi(getter)]
+    pub fn cancelled(&self) -> bool { self.handle.is_cancelled() }
+}
 
 // ─── Async Tasks ───────────────────────────────────────────────
 
 pub struct CompactTask {
     db: Arc<RustDatabase>,
+    op: OperationHandle,
 }
 
 #[napi]
@@ -22,16 +70,80 @@ impl Task for CompactTask {
     type Output = ();
     type JsValue = ();
     fn compute(&mut self) -> Result<Self::Output> {
-         self.db.compact().map_err(|e| Error::from_reason(format!("Compact failed: {}", e)))
+         self.db.compact_with(&self.op).map_err(db_error("Compact failed"))
     }
     fn resolve(&mut self, _env: Env, _output: Self::Output) -> Result<Self::JsValue> {
          Ok(())
     }
 }
 
+pub struct InsertBatchTask {
+    db: Arc<RustDatabase>,
+    docs: Vec<serde_json::Value>,
+    durability: Option<Durability>,
+}
+
+#[napi]
+impl Task for InsertBatchTask {
+    type Output = Vec<String>;
+    type JsValue = Vec<String>;
+    fn compute(&mut self) -> Result<Self::Output> {
+        let docs = std::mem::take(&mut self.docs);
+        match self.durability {
+            Some(durability) => self.db.insert_batch_with(docs, durability),
+            None => self.db.insert_batch(docs),
+        }
+        .map_err(db_error("Insert batch failed"))
+    }
+    fn resolve(&mut self, _env: Env, output: Self::Output) -> Result<Self::JsValue> {
+        Ok(output)
+    }
+}
+
+pub struct FlushTask {
+    db: Arc<RustDatabase>,
+}
+
+#[napi]
+impl Task for FlushTask {
+    type Output = ();
+    type JsValue = ();
+    fn compute(&mut self) -> Result<Self::Output> {
+        self.db.flush().map_err(db_error("Flush failed"))
+    }
+    fn resolve(&mut self, _env: Env, _output: Self::Output) -> Result<Self::JsValue> {
+        Ok(())
+    }
+}
+
+pub struct CreateIndexTask {
+    db: Arc<RustDatabase>,
+    field: String,
+    btree: bool,
+    op: OperationHandle,
+}
+
+#[napi]
+impl Task for CreateIndexTask {
+    type Output = ();
+    type JsValue = ();
+    fn compute(&mut self) -> Result<Self::Output> {
+        let built = if self.btree {
+            self.db.create_btree_index_with(&self.field, &self.op)
+        } else {
+            self.db.create_index_with(&self.field, &self.op)
+        };
+        built.map_err(db_error("Create index failed"))
+    }
+    fn resolve(&mut self, _env: Env, _output: Self::Output) -> Result<Self::JsValue> {
+        Ok(())
+    }
+}
+
 pub struct QueryTask {
     db: Arc<RustDatabase>,
     ast: serde_json::Value,
+    op: OperationHandle,
 }
 
 #[napi]
@@ -39,7 +151,9 @@ impl Task for QueryTask {
     type Output = Vec<serde_json::Value>;
     type JsValue = String;
     fn compute(&mut self) -> Result<Self::Output> {
-        Ok(self.db.query(self.ast.clone()))
+        self.db
+            .query_cancellable(self.ast.clone(), QueryOptions::default(), &self.op)
+            .map_err(db_error("Query failed"))
     }
     fn resolve(&mut self, _env: Env, output: Self::Output) -> Result<Self::JsValue> {
         serde_json::to_string(&output).map_err(|e| Error::from_reason(format!("Serialization failed: {}", e)))
@@ -50,6 +164,7 @@ pub struct QueryWithTask {
     db: Arc<RustDatabase>,
     ast: serde_json::Value,
     opts: QueryOptions,
+    op: OperationHandle,
 }
 
 #[napi]
@@ -57,7 +172,65 @@ impl Task for QueryWithTask {
     type Output = Vec<serde_json::Value>;
     type JsValue = String;
     fn compute(&mut self) -> Result<Self::Output> {
-        Ok(self.db.query_with(self.ast.clone(), self.opts.clone()))
+        self.db
+            .query_cancellable(self.ast.clone(), self.opts.clone(), &self.op)
+            .map_err(db_error("Query failed"))
+    }
+    fn resolve(&mut self, _env: Env, output: Self::Output) -> Result<Self::JsValue> {
+        serde_json::to_string(&output).map_err(|e| Error::from_reason(format!("Serialization failed: {}", e)))
+    }
+}
+
+pub struct QueryGroupedTask {
+    db: Arc<RustDatabase>,
+    ast: serde_json::Value,
+    group_by: String,
+    group_size: usize,
+    opts: QueryOptions,
+}
+
+#[napi]
+impl Task for QueryGroupedTask {
+    type Output = Vec<QueryGroup>;
+    type JsValue = String;
+    fn compute(&mut self) -> Result<Self::Output> {
+        Ok(self.db.query_grouped(self.ast.clone(), &self.group_by, self.group_size, self.opts.clone()))
+    }
+    fn resolve(&mut self, _env: Env, output: Self::Output) -> Result<Self::JsValue> {
+        serde_json::to_string(&output).map_err(|e| Error::from_reason(format!("Serialization failed: {}", e)))
+    }
+}
+
+pub struct AggregateTask {
+    db: Arc<RustDatabase>,
+    ast: serde_json::Value,
+    field: String,
+    agg: Aggregation,
+}
+
+#[napi]
+impl Task for AggregateTask {
+    type Output = serde_json::Value;
+    type JsValue = String;
+    fn compute(&mut self) -> Result<Self::Output> {
+        Ok(self.db.aggregate(self.ast.clone(), &self.field, self.agg))
+    }
+    fn resolve(&mut self, _env: Env, output: Self::Output) -> Result<Self::JsValue> {
+        serde_json::to_string(&output).map_err(|e| Error::from_reason(format!("Serialization failed: {}", e)))
+    }
+}
+
+pub struct QueryBatchTask {
+    db: Arc<RustDatabase>,
+    queries: Vec<(serde_json::Value, QueryOptions)>,
+}
+
+#[napi]
+impl Task for QueryBatchTask {
+    type Output = Vec<Vec<serde_json::Value>>;
+    type JsValue = String;
+    fn compute(&mut self) -> Result<Self::Output> {
+        Ok(self.db.query_batch(std::mem::take(&mut self.queries)))
     }
     fn resolve(&mut self, _env: Env, output: Self::Output) -> Result<Self::JsValue> {
         serde_json::to_string(&output).map_err(|e| Error::from_reason(format!("Serialization failed: {}", e)))
@@ -74,7 +247,7 @@ impl Task for ExportTask {
     type Output = ();
     type JsValue = ();
     fn compute(&mut self) -> Result<Self::Output> {
-        self.db.export_snapshot(&self.dest).map_err(|e| Error::from_reason(format!("Export failed: {}", e)))
+        self.db.export_snapshot(&self.dest).map_err(db_error("Export failed"))
     }
     fn resolve(&mut self, _env: Env, _output: Self::Output) -> Result<Self::JsValue> {
         Ok(())
@@ -96,7 +269,7 @@ pub struct Database {
 
 impl Database {
     fn inner(&self) -> Result<Arc<RustDatabase>> {
-        self.inner.read().unwrap().clone().ok_or_else(|| Error::from_reason("Database closed"))
+        self.inner.read().clone().ok_or_else(|| Error::from_reason("Database closed"))
     }
 }
 
@@ -110,7 +283,7 @@ impl Database {
     #[napi(constructor)]
     pub fn new(path: String) -> Result<Self> {
         let inner = RustDatabase::open(&path)
-            .map_err(|e| Error::from_reason(format!("Failed to open database: {}", e)))?;
+            .map_err(db_error("Failed to open database"))?;
         Ok(Self {
             inner: RwLock::new(Some(Arc::new(inner))),
         })
@@ -121,28 +294,17 @@ impl Database {
     /// ```js
     /// const db = Database.open('./my-data.jsonl', { persistence: 'immediate' });
     /// const db = Database.open('./my-data.jsonl', { persistence: 'scheduled', interval: 60 });
+    /// const db = Database.open('./my-data.jsonl', { persistence: 'group', groupCommitMs: 10 });
     /// ```
     #[napi]
     pub fn open(path: String, options: Option<DatabaseOptions>) -> Result<Self> {
-        let mut db = RustDatabase::open(&path)
-            .map_err(|e| Error::from_reason(format!("Failed to open database: {}", e)))?;
+        let strict = options.as_ref().and_then(|opts| opts.strict).unwrap_or(false);
+        let mut db = if strict { RustDatabase::open_strict(&path) } else { RustDatabase::open(&path) }
+            .map_err(db_error("Failed to open database"))?;
 
         if let Some(opts) = options {
             if let Some(ref mode) = opts.persistence {
-                match mode.as_str() {
-                    "immediate" | "Immediate" => {
-                        db = db.with_persistence(Persistence::Immediate);
-                    }
-                    "scheduled" | "Scheduled" => {
-                        let secs = opts.interval.unwrap_or(60) as u64;
-                        db = db.with_persistence(Persistence::Scheduled(
-                            std::time::Duration::from_secs(secs),
-                        ));
-                    }
-                    "lazy" | "Lazy" | _ => {
-                        db = db.with_persistence(Persistence::Lazy);
-                    }
-                }
+                db = db.with_persistence(persistence_mode(mode, &opts));
             }
             if let Some(ttl) = opts.trash_ttl {
                 let interval = opts.trash_purge_interval.unwrap_or(3600);
@@ -151,6 +313,23 @@ impl Database {
                     std::time::Duration::from_secs(interval as u64)
                 );
             }
+            if let Some(schema) = opts.schema {
+                let schema: Schema = serde_json::from_value(schema)
+                    .map_err(|e| Error::from_reason(format!("Invalid schema: {}", e)))?;
+                db = db.with_schema(schema);
+            }
+            if let Some(secs) = opts.idempotency_window {
+                db = db.with_idempotency_window(std::time::Duration::from_secs(secs as u64));
+            }
+            let limits = Limits {
+                max_doc_bytes: opts.max_doc_bytes.map(|n| n as usize),
+                max_id_len: opts.max_id_length.map(|n| n as usize),
+                max_writes_per_sec: opts.max_writes_per_sec,
+                max_log_bytes: opts.max_log_bytes.map(|n| n.max(0) as u64),
+            };
+            if limits != Limits::default() {
+                db = db.with_limits(limits);
+            }
         }
 
         Ok(Self {
@@ -166,17 +345,64 @@ impl Database {
     #[napi]
     pub fn open_in_memory() -> Result<Self> {
         let inner = RustDatabase::open_in_memory()
-            .map_err(|e| Error::from_reason(format!("Failed to create in-memory database: {}", e)))?;
+            .map_err(db_error("Failed to create in-memory database"))?;
         Ok(Self {
             inner: RwLock::new(Some(Arc::new(inner))),
         })
     }
 
+    /// Open a detached in-memory view of the database as of log record `seq`.
+    ///
+    /// ```js
+    /// const past = Database.openAt('./my-data.jsonl', 120);
+    /// ```
+    #[napi]
+    pub fn open_at(path: String, seq: i64) -> Result<Self> {
+        let inner = RustDatabase::open_at(&path, seq.max(0) as u64)
+            .map_err(db_error("Failed to open database"))?;
+        Ok(Self {
+            inner: RwLock::new(Some(Arc::new(inner))),
+        })
+    }
+
+    /// Switch persistence mode on the open database, e.g. to "lazy" for a
+    /// bulk load. Syncs everything written so far first. Only `interval` and
+    /// `groupCommitMs` are read from `options`.
+    ///
+    /// ```js
+    /// db.setPersistence('lazy');
+    /// db.setPersistence('group', { groupCommitMs: 5 });
+    /// ```
+    #[napi]
+    pub fn set_persistence(&self, mode: String, options: Option<DatabaseOptions>) -> Result<()> {
+        let opts = options.unwrap_or_default();
+        self.inner()?
+            .set_persistence(persistence_mode(&mode, &opts))
+            .map_err(db_error("Set persistence failed"))
+    }
+
+    /// User metadata stored in `meta.json`, as a JSON object string.
+    #[napi]
+    pub fn metadata(&self) -> Result<String> {
+        serde_json::to_string(&self.inner()?.metadata())
+            .map_err(|e| Error::from_reason(format!("Serialization failed: {}", e)))
+    }
+
+    /// Replace the user metadata with a JSON object string.
+    #[napi]
+    pub fn set_metadata(&self, json: String) -> Result<()> {
+        let metadata: serde_json::Value = serde_json::from_str(&json)
+            .map_err(|e| Error::from_reason(format!("Invalid JSON value: {}", e)))?;
+        self.inner()?
+            .set_metadata(metadata)
+            .map_err(db_error("Set metadata failed"))
+    }
+
     /// Close the database and instantly release any holds (OS locks, memory).
     /// Safe to call multiple times. Subsequent operations will throw "Database closed".
     #[napi]
     pub fn close(&self) -> Result<()> {
-        *self.inner.write().unwrap() = None;
+        *self.inner.write() = None;
         Ok(())
     }
 
@@ -188,11 +414,25 @@ impl Database {
     /// const id = db.insert({ title: 'Hello', tags: ['demo'] });
     /// ```
     #[napi]
-    pub fn insert(&self, doc: String) -> Result<String> {
+    pub fn insert(&self, doc: String, durability: Option<String>) -> Result<String> {
         let value: serde_json::Value = serde_json::from_str(&doc)
             .map_err(|e| Error::from_reason(format!("Invalid JSON document: {}", e)))?;
-        self.inner()?.insert(value)
-            .map_err(|e| Error::from_reason(format!("Insert failed: {}", e)))
+        let db = self.inner()?;
+        match durability_override(durability.as_deref())? {
+            Some(durability) => db.insert_with(value, durability),
+            None => db.insert(value),
+        }
+        .map_err(db_error("Insert failed"))
+    }
+
+    /// Insert a document once per idempotency key. A retry with the same key
+    /// returns the first insert's `_id` and writes nothing.
+    #[napi]
+    pub fn insert_idempotent(&self, key: String, doc: String) -> Result<String> {
+        let value: serde_json::Value = serde_json::from_str(&doc)
+            .map_err(|e| Error::from_reason(format!("Invalid JSON document: {}", e)))?;
+        self.inner()?.insert_idempotent(&key, value)
+            .map_err(db_error("Insert failed"))
     }
 
     /// Insert a document with a prefixed ID.
@@ -206,7 +446,111 @@ impl Database {
         let value: serde_json::Value = serde_json::from_str(&doc)
             .map_err(|e| Error::from_reason(format!("Invalid JSON document: {}", e)))?;
         self.inner()?.insert_with_prefix(&prefix, value)
-            .map_err(|e| Error::from_reason(format!("Insert with prefix failed: {}", e)))
+            .map_err(db_error("Insert with prefix failed"))
+    }
+
+    /// Insert a document into namespace `name`. The ID is `{name}_{random}`.
+    #[napi]
+    pub fn namespace_insert(&self, name: String, doc: String) -> Result<String> {
+        let value: serde_json::Value = serde_json::from_str(&doc)
+            .map_err(|e| Error::from_reason(format!("Invalid JSON document: {}", e)))?;
+        let db = self.inner()?;
+        db.namespace(&name)
+            .and_then(|ns| ns.insert(value))
+            .map_err(db_error("Insert failed"))
+    }
+
+    /// Get a document of namespace `name`. IDs of other namespaces are not found.
+    #[napi]
+    pub fn namespace_get(&self, name: String, id: String) -> Result<String> {
+        let db = self.inner()?;
+        db.namespace(&name)
+            .and_then(|ns| ns.get(&id))
+            .map_err(db_error("Get failed"))
+            .and_then(|v| {
+                serde_json::to_string(&v)
+                    .map_err(|e| Error::from_reason(format!("Serialization failed: {}", e)))
+            })
+    }
+
+    /// Number of documents in namespace `name`.
+    #[napi]
+    pub fn namespace_len(&self, name: String) -> Result<u32> {
+        let db = self.inner()?;
+        db.namespace(&name)
+            .map(|ns| ns.len() as u32)
+            .map_err(db_error("Namespace len failed"))
+    }
+
+    /// Execute a JSON AST query scoped to namespace `name`. Returns JSON array string.
+    #[napi]
+    pub fn namespace_query(
+        &self,
+        name: String,
+        ast: String,
+        limit: Option<u32>,
+        offset: Option<u32>,
+        sort_by: Option<String>,
+        sort_dir: Option<String>,
+    ) -> Result<String> {
+        let ast_value: serde_json::Value = serde_json::from_str(&ast)
+            .map_err(|e| Error::from_reason(format!("Invalid JSON AST: {}", e)))?;
+        let dir = match sort_dir.as_deref() {
+            Some("desc") | Some("DESC") => SortDir::Desc,
+            _ => SortDir::Asc,
+        };
+        let opts = QueryOptions {
+            limit: limit.map(|l| l as usize),
+            offset: offset.map(|o| o as usize),
+            sort_by: sort_by.map(|f| (f, dir)),
+        };
+
+        let db = self.inner()?;
+        let results = db
+            .namespace(&name)
+            .map(|ns| ns.query_with(&ast_value, &opts))
+            .map_err(db_error("Query failed"))?;
+        serde_json::to_string(&results)
+            .map_err(|e| Error::from_reason(format!("Serialization failed: {}", e)))
+    }
+
+    /// Delete every document of namespace `name` as one batch. Returns how many.
+    #[napi]
+    pub fn namespace_delete_all(&self, name: String) -> Result<u32> {
+        let db = self.inner()?;
+        db.namespace(&name)
+            .and_then(|ns| ns.delete_all())
+            .map(|n| n as u32)
+            .map_err(db_error("Namespace delete failed"))
+    }
+
+    /// Insert several documents atomically. Returns the generated IDs in order.
+    ///
+    /// ```js
+    /// const ids = db.insertBatch([{ a: 1 }, { b: 2 }]);
+    /// ```
+    #[napi]
+    pub fn insert_batch(&self, docs: String, durability: Option<String>) -> Result<Vec<String>> {
+        let values: Vec<serde_json::Value> = serde_json::from_str(&docs)
+            .map_err(|e| Error::from_reason(format!("Invalid JSON document array: {}", e)))?;
+        let db = self.inner()?;
+        match durability_override(durability.as_deref())? {
+            Some(durability) => db.insert_batch_with(values, durability),
+            None => db.insert_batch(values),
+        }
+        .map_err(db_error("Insert batch failed"))
+    }
+
+    /// `insertBatch` off the JS thread. Resolves to the generated IDs.
+    #[napi]
+    pub fn insert_batch_async(&self, docs: String, durability: Option<String>) -> Result<AsyncTask<InsertBatchTask>> {
+        let values: Vec<serde_json::Value> = serde_json::from_str(&docs)
+            .map_err(|e| Error::from_reason(format!("Invalid JSON document array: {}", e)))?;
+        Ok(AsyncTask::new(InsertBatchTask {
+            db: self.inner()?,
+            docs: values,
+            durability: durability_override(durability.as_deref())?,
+        }))
     }
 
     /// Get a document by ID. Returns the document as a JSON object.
@@ -217,7 +561,7 @@ impl Database {
     #[napi]
     pub fn get(&self, id: String) -> Result<String> {
         self.inner()?.get(&id)
-            .map_err(|e| Error::from_reason(format!("Get failed: {}", e)))
+            .map_err(db_error("Get failed"))
             .and_then(|v| {
                 serde_json::to_string(&v)
                     .map_err(|e| Error::from_reason(format!("Serialization failed: {}", e)))
@@ -234,7 +578,31 @@ impl Database {
         let value: serde_json::Value = serde_json::from_str(&doc)
             .map_err(|e| Error::from_reason(format!("Invalid JSON document: {}", e)))?;
         self.inner()?.update(&id, value)
-            .map_err(|e| Error::from_reason(format!("Update failed: {}", e)))
+            .map_err(db_error("Update failed"))
+    }
+
+    /// Update a document only if its `_version` is still `expected`.
+    /// Returns the new version; throws `version conflict ...` otherwise.
+    ///
+    /// ```js
+    /// const doc = db.get(id);
+    /// db.updateIfVersion(id, { ...doc, title: 'Updated' }, doc._version);
+    /// ```
+    #[napi]
+    pub fn update_if_version(&self, id: String, doc: String, expected: i64) -> Result<i64> {
+        let value: serde_json::Value = serde_json::from_str(&doc)
+            .map_err(|e| Error::from_reason(format!("Invalid JSON document: {}", e)))?;
+        self.inner()?.update_if_version(&id, value, expected.max(0) as u64)
+            .map(|v| v as i64)
+            .map_err(db_error("Update failed"))
+    }
+
+    /// Current `_version` of a document.
+    #[napi]
+    pub fn version(&self, id: String) -> Result<i64> {
+        self.inner()?.version(&id)
+            .map(|v| v as i64)
+            .map_err(db_error("Version failed"))
     }
 
     /// Append an element to an array field.
@@ -243,7 +611,7 @@ impl Database {
         let val: serde_json::Value = serde_json::from_str(&value)
             .map_err(|e| Error::from_reason(format!("Invalid JSON value: {}", e)))?;
         self.inner()?.array_push(&id, &field, val)
-            .map_err(|e| Error::from_reason(format!("Array push failed: {}", e)))
+            .map_err(db_error("Array push failed"))
     }
 
     /// Set a value at a dot-separated path within a document.
@@ -252,14 +620,14 @@ impl Database {
         let val: serde_json::Value = serde_json::from_str(&value)
             .map_err(|e| Error::from_reason(format!("Invalid JSON value: {}", e)))?;
         self.inner()?.set(&id, &path, val)
-            .map_err(|e| Error::from_reason(format!("Set failed: {}", e)))
+            .map_err(db_error("Set failed"))
     }
 
     /// Remove a field or array element at a dot-separated path.
     #[napi]
     pub fn remove(&self, id: String, path: String) -> Result<()> {
         self.inner()?.remove(&id, &path)
-            .map_err(|e| Error::from_reason(format!("Remove failed: {}", e)))
+            .map_err(db_error("Remove failed"))
     }
 
     /// Delete a document by ID (soft delete / tombstone).
@@ -270,7 +638,22 @@ impl Database {
     #[napi]
     pub fn delete(&self, id: String) -> Result<()> {
         self.inner()?.delete(&id)
-            .map_err(|e| Error::from_reason(format!("Delete failed: {}", e)))
+            .map_err(db_error("Delete failed"))
+    }
+
+    /// Delete a document once per idempotency key. A retry with the same
+    /// key succeeds without deleting anything.
+    #[napi]
+    pub fn delete_idempotent(&self, key: String, id: String) -> Result<()> {
+        self.inner()?.delete_idempotent(&key, &id)
+            .map_err(db_error("Delete failed"))
+    }
+
+    /// Delete a document only if its `_version` is still `expected`.
+    #[napi]
+    pub fn delete_if_version(&self, id: String, expected: i64) -> Result<()> {
+        self.inner()?.delete_if_version(&id, expected.max(0) as u64)
+            .map_err(db_error("Delete failed"))
     }
 
     // ─── Iteration & Counting ──────────────────────────────────────
@@ -346,10 +729,14 @@ impl Database {
     /// }));
     /// ```
     #[napi]
-    pub fn query(&self, ast: String) -> Result<AsyncTask<QueryTask>> {
+    pub fn query(&self, ast: String, op: Option<&Operation>) -> Result<AsyncTask<QueryTask>> {
         let ast_value: serde_json::Value = serde_json::from_str(&ast)
             .map_err(|e| Error::from_reason(format!("Invalid JSON AST: {}", e)))?;
-        Ok(AsyncTask::new(QueryTask { db: self.inner()?, ast: ast_value }))
+        Ok(AsyncTask::new(QueryTask {
+            db: self.inner()?,
+            ast: ast_value,
+            op: op.map(|op| op.handle.clone()).unwrap_or_default(),
+        }))
     }
 
     /// Execute a JSON AST query with options (limit, offset, sort).
@@ -369,6 +756,7 @@ impl Database {
         offset: Option<u32>,
         sort_by: Option<String>,
         sort_dir: Option<String>,
+        op: Option<&Operation>,
     ) -> Result<AsyncTask<QueryWithTask>> {
         let ast_value: serde_json::Value = serde_json::from_str(&ast)
             .map_err(|e| Error::from_reason(format!("Invalid JSON AST: {}", e)))?;
@@ -391,6 +779,143 @@ impl Database {
             db: self.inner()?,
             ast: ast_value,
             opts,
+            op: op.map(|op| op.handle.clone()).unwrap_or_default(),
+        }))
+    }
+
+    /// Run a query like `queryWith` and report how it ran. Returns a JSON
+    /// string of `{ results, explanation }`.
+    #[napi]
+    pub fn query_explain(
+        &self,
+        ast: String,
+        limit: Option<u32>,
+        offset: Option<u32>,
+        sort_by: Option<String>,
+        sort_dir: Option<String>,
+    ) -> Result<String> {
+        let ast_value: serde_json::Value = serde_json::from_str(&ast)
+            .map_err(|e| Error::from_reason(format!("Invalid JSON AST: {}", e)))?;
+        let dir = match sort_dir.as_deref() {
+            Some("desc") | Some("DESC") => SortDir::Desc,
+            _ => SortDir::Asc,
+        };
+        let opts = QueryOptions {
+            limit: limit.map(|l| l as usize),
+            offset: offset.map(|o| o as usize),
+            sort_by: sort_by.map(|f| (f, dir)),
+        };
+
+        let (results, plan) = self.inner()?.query_explain(ast_value, opts);
+        let explanation = serde_json::json!({
+            "indexed": plan.indexed,
+            "documents": plan.documents,
+            "scanned": plan.scanned,
+            "matched": plan.matched,
+            "returned": plan.returned,
+            "selectivity": plan.selectivity(),
+            "planUs": plan.plan_us,
+            "scanUs": plan.scan_us,
+            "optionsUs": plan.options_us,
+        });
+        serde_json::to_string(&serde_json::json!({ "results": results, "explanation": explanation }))
+            .map_err(|e| Error::from_reason(format!("Serialization failed: {}", e)))
+    }
+
+    /// Execute several queries in one pass. Takes a JSON string of
+    /// `[{ ast, limit?, offset?, sortBy?, sortDir? }]` and returns a JSON
+    /// string of one result array per query.
+    #[napi]
+    pub fn query_batch(&self, queries: String) -> Result<AsyncTask<QueryBatchTask>> {
+        let parsed: Vec<serde_json::Value> = serde_json::from_str(&queries)
+            .map_err(|e| Error::from_reason(format!("Invalid JSON: {}", e)))?;
+
+        let queries = parsed
+            .into_iter()
+            .map(|q| {
+                let dir = match q.get("sortDir").and_then(|v| v.as_str()) {
+                    Some("desc") | Some("DESC") => SortDir::Desc,
+                    _ => SortDir::Asc,
+                };
+                let opts = QueryOptions {
+                    limit: q.get("limit").and_then(|v| v.as_u64()).map(|l| l as usize),
+                    offset: q.get("offset").and_then(|v| v.as_u64()).map(|o| o as usize),
+                    sort_by: q
+                        .get("sortBy")
+                        .and_then(|v| v.as_str())
+                        .map(|f| (f.to_string(), dir)),
+                };
+                let ast = q.get("ast").cloned().unwrap_or_else(|| serde_json::json!({}));
+                (ast, opts)
+            })
+            .collect();
+
+        Ok(AsyncTask::new(QueryBatchTask {
+            db: self.inner()?,
+            queries,
+        }))
+    }
+
+    /// Execute a JSON AST query and group matches by a field value,
+    /// keeping the best `groupSize` documents per group.
+    /// Returns a JSON string of `[{ key, docs }]`.
+    #[napi]
+    #[allow(clippy::too_many_arguments)]
+    pub fn query_grouped(
+        &self,
+        ast: String,
+        group_by: String,
+        group_size: u32,
+        limit: Option<u32>,
+        offset: Option<u32>,
+        sort_by: Option<String>,
+        sort_dir: Option<String>,
+    ) -> Result<AsyncTask<QueryGroupedTask>> {
+        let ast_value: serde_json::Value = serde_json::from_str(&ast)
+            .map_err(|e| Error::from_reason(format!("Invalid JSON AST: {}", e)))?;
+
+        let dir = sort_dir
+            .as_deref()
+            .map(|d| match d {
+                "desc" | "DESC" => SortDir::Desc,
+                _ => SortDir::Asc,
+            })
+            .unwrap_or(SortDir::Asc);
+
+        let opts = QueryOptions {
+            limit: limit.map(|l| l as usize),
+            offset: offset.map(|o| o as usize),
+            sort_by: sort_by.map(|f| (f, dir)),
+        };
+
+        Ok(AsyncTask::new(QueryGroupedTask {
+            db: self.inner()?,
+            ast: ast_value,
+            group_by,
+            group_size: group_size as usize,
+            opts,
+        }))
+    }
+
+    /// Aggregate a field over the matches of a JSON AST query. `kind` is
+    /// `count`, `min`, `max`, `avg` or `distinct`. Returns a JSON string.
+    #[napi]
+    pub fn aggregate(&self, ast: String, field: String, kind: String) -> Result<AsyncTask<AggregateTask>> {
+        let ast_value: serde_json::Value = serde_json::from_str(&ast)
+            .map_err(|e| Error::from_reason(format!("Invalid JSON AST: {}", e)))?;
+        let agg = match kind.as_str() {
+            "count" => Aggregation::Count,
+            "min" => Aggregation::Min,
+            "max" => Aggregation::Max,
+            "avg" => Aggregation::Avg,
+            "distinct" => Aggregation::Distinct,
+            other => return Err(Error::from_reason(format!("Unknown aggregation: {}", other))),
+        };
+        Ok(AsyncTask::new(AggregateTask {
+            db: self.inner()?,
+            ast: ast_value,
+            field,
+            agg,
         }))
     }
 
@@ -400,21 +925,40 @@ impl Database {
     #[napi]
     pub fn create_index(&self, field: String) -> Result<()> {
         self.inner()?.create_index(&field)
-            .map_err(|e| Error::from_reason(format!("Create index failed: {}", e)))
+            .map_err(db_error("Create index failed"))
     }
 
     /// Create a BTree index on a field for range queries.
     #[napi]
     pub fn create_btree_index(&self, field: String) -> Result<()> {
         self.inner()?.create_btree_index(&field)
-            .map_err(|e| Error::from_reason(format!("Create BTree index failed: {}", e)))
+            .map_err(db_error("Create BTree index failed"))
+    }
+
+    /// Create a full-text index on a field for `$text` queries.
+    #[napi]
+    pub fn create_text_index(&self, field: String) -> Result<()> {
+        self.inner()?.create_text_index(&field)
+            .map_err(db_error("Create text index failed"))
+    }
+
+    /// Build a hash (or BTree) index off the JS thread, with optional
+    /// progress and cancellation through `op`.
+    #[napi]
+    pub fn create_index_async(&self, field: String, btree: Option<bool>, op: Option<&Operation>) -> Result<AsyncTask<CreateIndexTask>> {
+        Ok(AsyncTask::new(CreateIndexTask {
+            db: self.inner()?,
+            field,
+            btree: btree.unwrap_or(false),
+            op: op.map(|op| op.handle.clone()).unwrap_or_default(),
+        }))
     }
 
     /// Drop an index, freeing memory.
     #[napi]
     pub fn drop_index(&self, field: String) -> Result<()> {
         self.inner()?.drop_index(&field)
-            .map_err(|e| Error::from_reason(format!("Drop index failed: {}", e)))
+            .map_err(db_error("Drop index failed"))
     }
 
     /// Check if an index exists for a field.
@@ -423,30 +967,155 @@ impl Database {
 
     // ─── Compaction & Trash ────────────────────────────────────────
 
-    /// Compact the database asynchronously.
+    /// Compact the database asynchronously, with optional progress and
+    /// cancellation through `op`.
     #[napi]
-    pub fn compact(&self) -> Result<AsyncTask<CompactTask>> {
-        Ok(AsyncTask::new(CompactTask { db: self.inner()? }))
+    pub fn compact(&self, op: Option<&Operation>) -> Result<AsyncTask<CompactTask>> {
+        Ok(AsyncTask::new(CompactTask {
+            db: self.inner()?,
+            op: op.map(|op| op.handle.clone()).unwrap_or_default(),
+        }))
     }
 
     /// Flush data to disk.
     #[napi]
     pub fn flush(&self) -> Result<()> {
         self.inner()?.flush()
-            .map_err(|e| Error::from_reason(format!("Flush failed: {}", e)))
+            .map_err(db_error("Flush failed"))
+    }
+
+    /// `flush` off the JS thread, for logs on slow disks.
+    #[napi]
+    pub fn flush_async(&self) -> Result<AsyncTask<FlushTask>> {
+        Ok(AsyncTask::new(FlushTask { db: self.inner()? }))
     }
 
     /// Restore a deleted document from trash by ID.
     #[napi]
     pub fn restore(&self, id: String) -> Result<()> {
         self.inner()?.restore(&id)
-            .map_err(|e| Error::from_reason(format!("Restore failed: {}", e)))
+            .map_err(db_error("Restore failed"))
+    }
+
+    /// Number of records in the current log file.
+    #[napi]
+    pub fn log_seq(&self) -> Result<i64> {
+        self.inner()?.log_seq()
+            .map(|seq| seq as i64)
+            .map_err(db_error("Log seq failed"))
+    }
+
+    /// Roll every document back to its state as of log record `seq`.
+    /// Returns the number of documents changed.
+    #[napi]
+    pub fn restore_to(&self, seq: i64) -> Result<u32> {
+        self.inner()?.restore_to(seq.max(0) as u64)
+            .map(|n| n as u32)
+            .map_err(db_error("Restore failed"))
     }
 
     /// Get list of deleted document IDs.
     #[napi]
     pub fn deleted_ids(&self) -> Result<Vec<String>> { Ok(self.inner()?.deleted_ids()) }
 
+    // ─── Change Feed ───────────────────────────────────────────────
+
+    /// Subscribe to the change feed. The callback receives one JSON string per
+    /// change (`{ seq, kind, id?, doc? }`), in write order.
+    /// The subscription does not keep the Node.js process alive.
+    ///
+    /// ```js
+    /// db.subscribe((event) => cache.apply(JSON.parse(event)));
+    /// ```
+    #[napi]
+    pub fn subscribe(&self, env: Env, callback: JsFunction) -> Result<()> {
+        let mut tsfn: ThreadsafeFunction<String, ErrorStrategy::Fatal> =
+            callback.create_threadsafe_function(0, |ctx| Ok(vec![ctx.value]))?;
+        tsfn.unref(&env)?;
+        let rx = self.inner()?.subscribe();
+        std::thread::spawn(move || {
+            // Ends when the database is dropped and the sender side disconnects
+            for event in rx {
+                let json = serde_json::to_string(&event).expect("change events serialize to JSON");
+                tsfn.call(json, ThreadsafeFunctionCallMode::NonBlocking);
+            }
+        });
+        Ok(())
+    }
+
+    /// Sequence number of the most recent change.
+    #[napi]
+    pub fn change_seq(&self) -> Result<i64> { Ok(self.inner()?.change_seq() as i64) }
+
+    // ─── Metrics ───────────────────────────────────────────────────
+
+    /// Metrics snapshot as a JSON string.
+    #[napi]
+    pub fn metrics(&self) -> Result<String> {
+        serde_json::to_string(&self.inner()?.metrics())
+            .map_err(|e| Error::from_reason(format!("Metrics failed: {}", e)))
+    }
+
+    /// Metrics in the Prometheus text exposition format.
+    #[napi]
+    pub fn metrics_prometheus(&self) -> Result<String> {
+        Ok(self.inner()?.metrics().to_prometheus())
+    }
+
+    /// Warnings recorded since the last call (`[{ kind, message, at }]`) as a JSON string.
+    #[napi]
+    pub fn take_warnings(&self) -> Result<String> {
+        serde_json::to_string(&self.inner()?.take_warnings())
+            .map_err(|e| Error::from_reason(format!("Serialization failed: {}", e)))
+    }
+
+    /// Estimated heap use (`{ documents, indexes, other }` in bytes) as a JSON string.
+    #[napi]
+    pub fn memory_usage(&self) -> Result<String> {
+        serde_json::to_string(&self.inner()?.memory_usage())
+            .map_err(|e| Error::from_reason(format!("Memory usage failed: {}", e)))
+    }
+
+    /// Every index as `[{ field, kind, entries, heapBytes }]`, as a JSON string.
+    #[napi]
+    pub fn index_status(&self) -> Result<String> {
+        let status: Vec<serde_json::Value> = self
+            .inner()?
+            .index_status()
+            .into_iter()
+            .map(|s| {
+                serde_json::json!({
+                    "field": s.field,
+                    "kind": s.kind,
+                    "entries": s.entries,
+                    "heapBytes": s.heap_bytes,
+                })
+            })
+            .collect();
+        serde_json::to_string(&status)
+            .map_err(|e| Error::from_reason(format!("Index status failed: {}", e)))
+    }
+
+    // ─── Verification ──────────────────────────────────────────────
+
+    /// Check the log and live state. Returns the report as a JSON string.
+    /// `quick` limits the check to the log structure.
+    #[napi]
+    pub fn verify(&self, quick: Option<bool>) -> Result<String> {
+        let level = if quick.unwrap_or(false) { ndb::VerifyLevel::Quick } else { ndb::VerifyLevel::Full };
+        let report = self.inner()?.verify(level)
+            .map_err(db_error("Verify failed"))?;
+        serde_json::to_string(&report)
+            .map_err(|e| Error::from_reason(format!("Verify failed: {}", e)))
+    }
+
+    /// Cheap readiness check. Throws with the first problem found.
+    #[napi]
+    pub fn health(&self) -> Result<()> {
+        self.inner()?.health()
+            .map_err(db_error("Health check failed"))
+    }
+
     // ─── File Buckets ──────────────────────────────────────────────
 
     /// Store a file in a bucket. Returns file metadata as JSON string.
@@ -465,7 +1134,7 @@ impl Database {
         let bkt = self.inner()?.bucket(&bucket);
         let meta = bkt
             .store(&name, &data, &mime_type)
-            .map_err(|e| Error::from_reason(format!("Store file failed: {}", e)))?;
+            .map_err(db_error("Store file failed"))?;
         serde_json::to_string(&meta)
             .map_err(|e| Error::from_reason(format!("Serialization failed: {}", e)))
     }
@@ -480,7 +1149,7 @@ impl Database {
         let bkt = self.inner()?.bucket(&bucket);
         let data = bkt
             .get_by_hash(&hash, &ext)
-            .map_err(|e| Error::from_reason(format!("Get file failed: {}", e)))?;
+            .map_err(db_error("Get file failed"))?;
         Ok(Buffer::from(data))
     }
 
@@ -494,7 +1163,7 @@ impl Database {
             ext,
         };
         bkt.delete(&file_ref)
-            .map_err(|e| Error::from_reason(format!("Delete file failed: {}", e)))
+            .map_err(db_error("Delete file failed"))
     }
 
     /// List files in a bucket.
@@ -502,7 +1171,7 @@ impl Database {
     pub fn list_files(&self, bucket: String) -> Result<Vec<String>> {
         let bkt = self.inner()?.bucket(&bucket);
         bkt.list()
-            .map_err(|e| Error::from_reason(format!("List files failed: {}", e)))
+            .map_err(db_error("List files failed"))
     }
 
     /// Safely delete a file from a bucket if no active document references it.
@@ -514,7 +1183,7 @@ impl Database {
     #[napi]
     pub fn release_file(&self, file_ref_str: String) -> Result<bool> {
         self.inner()?.release_file(&file_ref_str)
-            .map_err(|e| Error::from_reason(format!("Release file failed: {}", e)))
+            .map_err(db_error("Release file failed"))
     }
 
     /// Perform a full garbage collection of all buckets.
@@ -527,20 +1196,68 @@ impl Database {
     pub fn gc_buckets(&self) -> Result<u32> {
         self.inner()?.gc_buckets()
             .map(|c| c as u32)
-            .map_err(|e| Error::from_reason(format!("GC buckets failed: {}", e)))
+            .map_err(db_error("GC buckets failed"))
+    }
+}
+
+/// Persistence mode named `mode`, with intervals from `opts`.
+fn persistence_mode(mode: &str, opts: &DatabaseOptions) -> Persistence {
+    match mode {
+        "immediate" | "Immediate" => Persistence::Immediate,
+        "scheduled" | "Scheduled" => {
+            let secs = opts.interval.unwrap_or(60) as u64;
+            Persistence::Scheduled(std::time::Duration::from_secs(secs))
+        }
+        "group" | "GroupCommit" => {
+            let ms = opts.group_commit_ms.unwrap_or(10) as u64;
+            Persistence::GroupCommit(std::time::Duration::from_millis(ms))
+        }
+        // "lazy" and anything unrecognised
+        _ => Persistence::Lazy,
+    }
+}
+
+/// Parse a per-call durability override: "buffered", "synced" or absent.
+fn durability_override(mode: Option<&str>) -> Result<Option<Durability>> {
+    match mode {
+        None => Ok(None),
+        Some("buffered") => Ok(Some(Durability::Buffered)),
+        Some("synced") => Ok(Some(Durability::Synced)),
+        Some(other) => Err(db_error("Invalid durability")(ndb::Error::invalid_arg(format!(
+            "'{}', expected 'buffered' or 'synced'",
+            other
+        )))),
     }
 }
 
 /// Database options for `Database.open()`.
 #[napi(object)]
+#[derive(Default)]
 pub struct DatabaseOptions {
-    /// Persistence mode: "lazy" (default), "immediate", or "scheduled".
+    /// Persistence mode: "lazy" (default), "immediate", "scheduled" or "group".
     pub persistence: Option<String>,
+    /// Refuse to open a log with unreadable lines instead of skipping them.
+    pub strict: Option<bool>,
     /// Interval in seconds for scheduled persistence. Default: 60.
     pub interval: Option<u32>,
+    /// Maximum milliseconds between syncs for group commit. Default: 10.
+    pub group_commit_ms: Option<u32>,
     /// Auto-empty trash TTL in seconds. Default: no auto-empty.
     pub trash_ttl: Option<u32>,
     /// Background interval in seconds to check for expired trash. Default: 3600 (1 hour).
     pub trash_purge_interval: Option<u32>,
+    /// Typed field map, e.g. `{ year: { type: 'integer', required: true } }`.
+    /// Overrides any schema in `meta.json`.
+    pub schema: Option<serde_json::Value>,
+    /// Reject documents whose JSON is larger than this many bytes.
+    pub max_doc_bytes: Option<u32>,
+    /// Reject document IDs (prefix included) longer than this many bytes.
+    pub max_id_length: Option<u32>,
+    /// Throttle writes above this many documents per second.
+    pub max_writes_per_sec: Option<u32>,
+    /// Reject writes that would grow the log file past this many bytes.
+    pub max_log_bytes: Option<i64>,
+    /// Seconds idempotency keys are remembered. Default: 3600.
+    pub idempotency_window: Option<u32>,
 }
 
@@ -8,8 +8,8 @@
  * Run: node test/test-napi.js
  */
 
-const { Database } = require('../index.js');
-const { existsSync, mkdirSync, rmSync } = require('fs');
+const { Database, Operation } = require('../index.js');
+const { appendFileSync, existsSync, mkdirSync, rmSync } = require('fs');
 const { join } = require('path');
 const os = require('os');
 
@@ -45,6 +45,18 @@ function test(name, fn) {
   }
 }
 
+async function testAsync(name, fn) {
+  try {
+    await fn();
+    passed++;
+    console.log(`  ✓ ${name}`);
+  } catch (e) {
+    failed++;
+    errors.push({ name, error: e.message });
+    console.log(`  ✗ ${name}: ${e.message}`);
+  }
+}
+
 function section(title) {
   console.log(`\n── ${title} ${'─'.repeat(Math.max(0, 60 - title.length))}`);
 }
@@ -94,6 +106,13 @@ test('insert with prefix returns prefixed NanoID', async () => {
   assertEqual(id.length, 21, 'Prefixed ID should be 21 chars (prefix_ + 16)');
 });
 
+test('insertBatch returns IDs in order', async () => {
+  const db = Database.openInMemory();
+  const ids = db.insertBatch([{ n: 0 }, { n: 1 }, { n: 2 }]);
+  assertEqual(ids.length, 3, 'Should return 3 IDs');
+  ids.forEach((id, i) => assertEqual(db.get(id).n, i, 'Doc order'));
+});
+
 test('get by ID returns document', async () => {
   const db = Database.openInMemory();
   const id = db.insert({ title: 'Test', value: 42 });
@@ -217,6 +236,37 @@ test('data persists across database reopen', async () => {
   rmSync(dir, { recursive: true, force: true });
 });
 
+await testAsync('insertBatchAsync and flushAsync persist', async () => {
+  const dir = createTempDir();
+  const path = join(dir, 'async.jsonl');
+
+  const db = new Database(path);
+  const ids = await db.insertBatchAsync([{ n: 0 }, { n: 1 }]);
+  await db.flushAsync();
+  assertEqual(ids.length, 2, 'Should return 2 IDs');
+
+  const db2 = new Database(path);
+  assertEqual(db2.get(ids[1]).n, 1, 'Batch should persist');
+
+  rmSync(dir, { recursive: true, force: true });
+});
+
+await testAsync('createInsertStream loads in batches with backpressure', async () => {
+  const db = Database.openInMemory();
+  const stream = db.createInsertStream({ batchSize: 10 });
+  let paused = false;
+  for (let n = 0; n < 25; n++) {
+    if (!stream.write({ n })) paused = true;
+  }
+  await new Promise((resolve, reject) => {
+    stream.on('error', reject);
+    stream.end(resolve);
+  });
+  assert(paused, 'write() should signal backpressure');
+  assertEqual(stream.inserted, 25, 'All docs counted');
+  assertEqual(db.len(), 25, 'All docs inserted');
+});
+
 test('update persists across reopen', async () => {
   const dir = createTempDir();
   const path = join(dir, 'update.jsonl');
@@ -393,6 +443,54 @@ test('query with $exists', async () => {
   assertEqual(results.length, 1, 'Should find 1 with avatar');
 });
 
+await testAsync('query with field-level $not and $isNull', async () => {
+  const db = Database.openInMemory();
+  db.insert({ name: 'A', deprecated: true, parent: null });
+  db.insert({ name: 'B', deprecated: false });
+  db.insert({ name: 'C' });
+
+  const live = await db.query({ deprecated: { $not: { $eq: true } } });
+  assertEqual(live.map((d) => d.name).sort(), ['B', 'C'], 'Missing flag counts as not deprecated');
+  const roots = await db.query({ parent: { $isNull: true } });
+  assertEqual(roots.map((d) => d.name), ['A'], 'Only explicit null');
+});
+
+await testAsync('query with array operators', async () => {
+  const db = Database.openInMemory();
+  db.insert({ name: 'A', tags: ['rust', 'db'] });
+  db.insert({ name: 'B', tags: ['js'] });
+
+  const rust = await db.query({ tags: { $contains: 'rust' } });
+  assertEqual(rust.map((d) => d.name), ['A'], '$contains');
+  const any = await db.query({ tags: { $containsAny: ['js', 'go'] } });
+  assertEqual(any.map((d) => d.name), ['B'], '$containsAny');
+  const two = await db.query({ tags: { $size: 2 } });
+  assertEqual(two.map((d) => d.name), ['A'], '$size');
+});
+
+await testAsync('query with string operators', async () => {
+  const db = Database.openInMemory();
+  db.insert({ path: 'src/lib.rs' });
+  db.insert({ path: 'SRC/README.md' });
+  db.insert({ path: 'docs/api.md' });
+
+  const src = await db.query({ path: { $startsWith: 'src/', $caseInsensitive: true } });
+  assertEqual(src.length, 2, '$startsWith + $caseInsensitive');
+  const md = await db.query({ path: { $endsWith: '.md' } });
+  assertEqual(md.length, 2, '$endsWith');
+});
+
+await testAsync('query with geo operators', async () => {
+  const db = Database.openInMemory();
+  db.insert({ name: 'berlin', loc: [52.52, 13.405] });
+  db.insert({ name: 'munich', loc: [48.1351, 11.582] });
+
+  const near = await db.query({ loc: { $geoRadius: { lat: 52.5, lon: 13.4, meters: 10000 } } });
+  assertEqual(near.map((d) => d.name), ['berlin'], '$geoRadius');
+  const south = await db.query({ loc: { $geoBox: { minLat: 47, minLon: 5, maxLat: 50, maxLon: 15 } } });
+  assertEqual(south.map((d) => d.name), ['munich'], '$geoBox');
+});
+
 test('queryWith with limit and sort', async () => {
   const db = Database.openInMemory();
   db.insert({ name: 'C', score: 30 });
@@ -436,6 +534,72 @@ test('queryWith with desc sort', async () => {
   assertEqual(results[2].name, 'A', 'Last should be A (lowest)');
 });
 
+test('queryExplain reports index use and counts', () => {
+  const db = Database.openInMemory();
+  db.insert({ name: 'A', status: 'active' });
+  db.insert({ name: 'B', status: 'active' });
+  db.insert({ name: 'C', status: 'inactive' });
+
+  let { results, explanation } = db.queryExplain({ status: 'active' }, { limit: 1 });
+  assertEqual(results.length, 1, 'Should apply limit');
+  assertEqual(explanation.indexed, false, 'No index yet');
+  assertEqual(explanation.scanned, 3, 'Full scan');
+  assertEqual(explanation.matched, 2, 'Matches before limit');
+
+  db.createIndex('status');
+  ({ explanation } = db.queryExplain({ status: 'active' }));
+  assertEqual(explanation.indexed, true, 'Index used');
+  assertEqual(explanation.scanned, 2, 'Only candidates scanned');
+  assertEqual(explanation.selectivity, 1, 'Every candidate matched');
+});
+
+await testAsync('queryGrouped keeps top N per group', async () => {
+  const db = Database.openInMemory();
+  db.insert({ doc: 'a', score: 0.9 });
+  db.insert({ doc: 'a', score: 0.4 });
+  db.insert({ doc: 'a', score: 0.8 });
+  db.insert({ doc: 'b', score: 0.95 });
+
+  const groups = await db.queryGrouped({}, 'doc', 2, { sortBy: 'score', sortDir: 'desc' });
+  assertEqual(groups.map((g) => g.key), ['b', 'a'], 'Groups ordered by best doc');
+  assertEqual(groups[1].docs.map((d) => d.score), [0.9, 0.8], 'Top 2 of group a');
+});
+
+await testAsync('aggregate counts, averages and groups a field', async () => {
+  const db = Database.openInMemory();
+  db.insert({ source: 'web', score: 10 });
+  db.insert({ source: 'web', score: 20 });
+  db.insert({ source: 'mail' });
+
+  assertEqual(await db.aggregate({}, 'score', 'count'), 2, 'Count');
+  assertEqual(await db.aggregate({ source: 'web' }, 'score', 'avg'), 15, 'Avg');
+  const perSource = await db.aggregate({}, 'source', 'distinct');
+  assertEqual(perSource.map((g) => `${g.value}:${g.count}`), ['web:2', 'mail:1'], 'Distinct');
+  let threw = false;
+  try {
+    await db.aggregate({}, 'score', 'median');
+  } catch (e) {
+    threw = /Unknown aggregation/.test(e.message);
+  }
+  assert(threw, 'Unknown kind should throw');
+});
+
+await testAsync('queryBatch returns one result set per query', async () => {
+  const db = Database.openInMemory();
+  db.insert({ name: 'A', score: 10 });
+  db.insert({ name: 'B', score: 20 });
+  db.insert({ name: 'C', score: 30 });
+
+  const [high, top1, none] = await db.queryBatch([
+    { ast: { score: { $gte: 20 } } },
+    { ast: {}, sortBy: 'score', sortDir: 'desc', limit: 1 },
+    { ast: { name: 'Z' } },
+  ]);
+  assertEqual(high.length, 2, 'Two docs with score >= 20');
+  assertEqual(top1.map((d) => d.name), ['C'], 'Sorted + limited');
+  assertEqual(none, [], 'No match');
+});
+
 // ─── Phase 5: Index Management ──────────────────────────────────────
 
 section('Phase 5: Index Management');
@@ -475,21 +639,31 @@ test('createBTreeIndex works', async () => {
   assert(db.hasIndex('score'), 'Should have score BTree index');
 });
 
+await testAsync('createTextIndex serves $text queries', async () => {
+  const db = Database.openInMemory();
+  db.insert({ name: 'A', body: 'Fast JSON storage in Rust' });
+  db.insert({ name: 'B', body: 'rust never sleeps' });
+  db.createTextIndex('body');
+  assertEqual(db.indexStatus()[0].kind, 'text', 'Text index kind');
+  const hits = await db.query({ body: { $text: 'RUST json' } });
+  assertEqual(hits.map((d) => d.name), ['A'], 'All words must match');
+});
+
 // ─── Phase 6: Compaction & Trash ────────────────────────────────────
 
 section('Phase 6: Compaction & Trash');
 
-test('compact removes deleted docs from file', async () => {
+await testAsync('compact removes deleted docs from file', async () => {
   const dir = createTempDir();
   const path = join(dir, 'compact.jsonl');
 
-  const id = (() => {
+  const id = await (async () => {
     const db = new Database(path);
     const id = db.insert({ keep: true });
     const delId = db.insert({ delete: true });
     db.delete(delId);
     db.flush();
-    db.compact();
+    await db.compact();
     return id;
   })();
 
@@ -522,6 +696,25 @@ test('restore recovers deleted document', async () => {
   rmSync(dir, { recursive: true, force: true });
 });
 
+test('restoreTo rolls back a bulk delete', async () => {
+  const dir = createTempDir();
+  const path = join(dir, 'pitr.jsonl');
+  const db = new Database(path);
+
+  const ids = [1, 2, 3].map((n) => db.insert({ n }));
+  const checkpoint = db.logSeq();
+  ids.forEach((id) => db.delete(id));
+  assertEqual(db.len(), 0, 'All deleted');
+
+  const past = Database.openAt(path, checkpoint);
+  assertEqual(past.len(), 3, 'openAt sees pre-delete state');
+
+  assertEqual(db.restoreTo(checkpoint), 3, 'Three docs restored');
+  assertEqual(db.get(ids[1]).n, 2, 'Doc content restored');
+
+  rmSync(dir, { recursive: true, force: true });
+});
+
 // ─── Phase 7: File Buckets ──────────────────────────────────────────
 
 section('Phase 7: File Buckets');
@@ -568,11 +761,11 @@ test('deleteFile removes file', async () => {
   const db = new Database(path);
 
   const meta = db.storeFile('temp', 'del.txt', Buffer.from('delete me'), 'text/plain');
-  db.deleteFile('temp', meta.id, meta.ext);
+  db.deleteFile('temp', meta._file.id, meta._file.ext);
 
   let threw = false;
   try {
-    db.getFile('temp', meta.id, meta.ext);
+    db.getFile('temp', meta._file.id, meta._file.ext);
   } catch (e) {
     threw = true;
   }
@@ -591,7 +784,7 @@ test('file deduplication by content hash', async () => {
   const meta2 = db.storeFile('files', 'copy.txt', content, 'text/plain');
 
   // Same content = same hash = same id
-  assertEqual(meta1.id, meta2.id, 'Same content should produce same hash');
+  assertEqual(meta1._file.id, meta2._file.id, 'Same content should produce same hash');
 
   const files = db.listFiles('files');
   assertEqual(files.length, 1, 'Deduplication should result in 1 file');
@@ -603,7 +796,7 @@ test('file deduplication by content hash', async () => {
 
 section('Phase 8: Complex Scenarios');
 
-test('full lifecycle: insert, query, update, delete, compact', async () => {
+await testAsync('full lifecycle: insert, query, update, delete, compact', async () => {
   const dir = createTempDir();
   const path = join(dir, 'lifecycle.jsonl');
   const db = new Database(path);
@@ -630,7 +823,7 @@ test('full lifecycle: insert, query, update, delete, compact', async () => {
   assertEqual(db.len(), 8, 'Should have 8 after 2 deletes');
 
   // Compact
-  db.compact();
+  await db.compact();
 
   // Reopen and verify
   db.flush();
@@ -683,6 +876,36 @@ test('Database.open with persistence option', async () => {
   rmSync(dir, { recursive: true, force: true });
 });
 
+test('Database.open with group commit persists writes', async () => {
+  const dir = createTempDir();
+  const path = join(dir, 'group.jsonl');
+  const db = Database.open(path, { persistence: 'group', groupCommitMs: 2 });
+  db.insert({ x: 1 });
+  db.insert({ x: 2 });
+  assert(db.metrics().fsync_latency.count >= 1, 'Writes should wait for a sync');
+  assertEqual(Database.open(path).len(), 2, 'Synced writes should be on disk');
+  rmSync(dir, { recursive: true, force: true });
+});
+
+test('Database.open with schema rejects mistyped fields', async () => {
+  const dir = createTempDir();
+  const db = Database.open(join(dir, 'schema.jsonl'), {
+    schema: { title: { type: 'string', required: true }, year: { type: 'integer' } },
+  });
+
+  let message = '';
+  try {
+    db.insert({ title: 'Dune', year: '1965' });
+  } catch (e) {
+    message = e.message;
+  }
+  assert(message.includes("schema violation at 'year'"), 'Should reject string year');
+  db.insert({ title: 'Dune', year: 1965 });
+  assertEqual(db.len(), 1, 'Only the valid insert is stored');
+
+  rmSync(dir, { recursive: true, force: true });
+});
+
 test('concurrent operations sequence', async () => {
   const db = Database.openInMemory();
   
@@ -830,11 +1053,11 @@ test('stress: rapid set same path', async () => {
   assertEqual(doc.counter, 500, 'Counter should be 500 after 500 sets');
 });
 
-test('stress: set array elements then compact', async () => {
+await testAsync('stress: set array elements then compact', async () => {
   const dir = createTempDir();
   const path = join(dir, 'stress_compact.jsonl');
 
-  const id = (() => {
+  const id = await (async () => {
     const db = new Database(path);
     const id = db.insert({ items: [] });
     for (let i = 0; i < 100; i++) {
@@ -846,7 +1069,7 @@ test('stress: set array elements then compact', async () => {
     for (let i = 0; i < 50; i++) {
       db.set(id, `items.${i}.v`, i * 100);
     }
-    db.compact();
+    await db.compact();
     return id;
   })();
 
@@ -882,6 +1105,346 @@ test('full update overwrites prior set patches', async () => {
   rmSync(dir, { recursive: true, force: true });
 });
 
+// ─── Phase 10: Change Feed ──────────────────────────────────────────
+
+section('Phase 10: Change Feed');
+
+await testAsync('subscribe delivers changes in order', async () => {
+  const db = Database.openInMemory();
+  const events = [];
+  db.subscribe((e) => events.push(e));
+  const id = db.insert({ v: 1 });
+  db.set(id, 'v', 2);
+  db.delete(id);
+  await new Promise((r) => setTimeout(r, 50));
+  assertEqual(events.map((e) => e.kind), ['insert', 'update', 'delete'], 'Event kinds');
+  assertEqual(events.map((e) => e.seq), [1, 2, 3], 'Event seqs');
+  assertEqual(events[1].doc.v, 2, 'Update carries new doc');
+  assertEqual(db.changeSeq(), 3, 'changeSeq');
+});
+
+// ─── Phase 11: Metrics & Verification ───────────────────────────────
+
+section('Phase 11: Metrics & Verification');
+
+await testAsync('metrics counts operations', async () => {
+  const db = Database.openInMemory();
+  const id = db.insert({ v: 1 });
+  db.set(id, 'v', 2);
+  await db.query({ v: 2 });
+  const m = db.metrics();
+  assertEqual(m.documents, 1, 'documents');
+  assertEqual(m.inserts, 1, 'inserts');
+  assertEqual(m.patches, 1, 'patches');
+  assertEqual(m.queries, 1, 'queries');
+  assertEqual(m.write_latency.count, 2, 'write latency observations');
+});
+
+test('metricsPrometheus renders text exposition', () => {
+  const db = Database.openInMemory();
+  db.insert({ v: 1 });
+  const text = db.metricsPrometheus();
+  assert(text.includes('# TYPE ndb_inserts_total counter'), 'counter type line');
+  assert(text.includes('ndb_inserts_total 1\n'), 'insert counter');
+});
+
+test('verify reports corrupt log lines', () => {
+  const dir = createTempDir();
+  const path = join(dir, 'data.jsonl');
+  const db = new Database(path);
+  db.insert({ v: 1 });
+  assertEqual(db.verify().issues.length, 0, 'clean log');
+  appendFileSync(path, '{garbage\n');
+  const report = db.verify({ quick: true });
+  assertEqual(report.issues.map((i) => i.kind), ['corrupt_line'], 'issue kinds');
+  assertEqual(report.records, 1, 'records');
+  rmSync(dir, { recursive: true, force: true });
+});
+
+test('health throws once the log is gone', () => {
+  const dir = createTempDir();
+  const path = join(dir, 'data.jsonl');
+  const db = new Database(path);
+  db.health();
+  rmSync(path);
+  let threw = false;
+  try {
+    db.health();
+  } catch (e) {
+    threw = /Health check failed/.test(e.message);
+  }
+  assert(threw, 'missing log reported');
+  rmSync(dir, { recursive: true, force: true });
+});
+
+test('memoryUsage estimates documents and indexes', () => {
+  const db = Database.openInMemory();
+  db.insert({ body: 'x'.repeat(5000), tag: 'a' });
+  db.createIndex('tag');
+  const usage = db.memoryUsage();
+  assert(usage.documents > 5000, 'documents counted');
+  assert(usage.indexes > 0, 'indexes counted');
+});
+
+test('insert durability overrides the persistence mode', () => {
+  const dir = createTempDir();
+  const db = Database.open(join(dir, 'durable.jsonl'));
+  db.insert({ n: 1 });
+  assertEqual(db.metrics().fsync_latency.count, 0, 'Lazy insert does not fsync');
+  db.insert({ n: 2 }, { durability: 'synced' });
+  db.insertBatch([{ n: 3 }], { durability: 'synced' });
+  assertEqual(db.metrics().fsync_latency.count, 2, 'Synced calls fsync');
+  let code = null;
+  try {
+    db.insert({ n: 4 }, { durability: 'eventually' });
+  } catch (e) {
+    code = e.code;
+  }
+  assertEqual(code, 'NDB_INVALID_ARGUMENT', 'Unknown durability throws');
+  rmSync(dir, { recursive: true, force: true });
+});
+
+test('namespaces scope queries, counts and deleteAll', () => {
+  const db = Database.openInMemory();
+  const acme = db.namespace('acme');
+  const globex = db.namespace('globex');
+  acme.insert({ n: 1 });
+  acme.insert({ n: 2 });
+  const other = globex.insert({ n: 1 });
+  assert(other.startsWith('globex_'), 'ID carries the namespace');
+  assertEqual(acme.len(), 2, 'Two documents in acme');
+  assertEqual(acme.query({ n: 1 }).length, 1, 'Query sees acme only');
+  assertEqual(acme.query({}, { sortBy: 'n', sortDir: 'desc', limit: 1 })[0].n, 2, 'Query options apply');
+  let code = null;
+  try {
+    acme.get(other);
+  } catch (e) {
+    code = e.code;
+  }
+  assertEqual(code, 'NDB_NOT_FOUND', 'Other namespace is not found');
+  assertEqual(acme.deleteAll(), 2, 'deleteAll returns the count');
+  assertEqual(db.len(), 1, 'globex untouched');
+});
+
+test('idempotent insert and delete apply once', () => {
+  const db = Database.openInMemory();
+  const id = db.insertIdempotent('req-1', { n: 1 });
+  assertEqual(db.insertIdempotent('req-1', { n: 2 }), id, 'Retry returns the same id');
+  assertEqual(db.len(), 1, 'One document');
+  db.deleteIdempotent('req-2', id);
+  db.deleteIdempotent('req-2', id);
+  assertEqual(db.len(), 0, 'Retried delete does not throw');
+});
+
+test('strict open refuses a corrupt log', () => {
+  const dir = createTempDir();
+  const path = join(dir, 'strict.jsonl');
+  Database.open(path).insert({ n: 1 });
+  appendFileSync(path, '{"_id":"broken"\n');
+  let code = null;
+  try {
+    Database.open(path, { strict: true });
+  } catch (e) {
+    code = e.code;
+  }
+  assertEqual(code, 'NDB_CORRUPTION', 'Strict open throws');
+  assertEqual(Database.open(path).len(), 1, 'Lenient open skips the line');
+  rmSync(dir, { recursive: true, force: true });
+});
+
+test('takeWarnings reports a failed trash archive', () => {
+  const dir = createTempDir();
+  const db = Database.open(join(dir, 'warn.jsonl'));
+  mkdirSync(join(dir, '_trash', 'docs', 'warn.jsonl'), { recursive: true });
+  const id = db.insert({ n: 1 });
+  db.delete(id);
+  const warnings = db.takeWarnings();
+  assertEqual(warnings.map((w) => w.kind), ['trash_archive'], 'One archive warning');
+  assertEqual(db.takeWarnings(), [], 'Drained');
+  assertEqual(db.metrics().warnings, 1, 'Counted in metrics');
+  rmSync(dir, { recursive: true, force: true });
+});
+
+test('indexStatus lists every index', () => {
+  const db = Database.openInMemory();
+  db.insert({ tag: 'a', n: 1 });
+  db.createIndex('tag');
+  db.createBTreeIndex('n');
+  const status = db.indexStatus();
+  assertEqual(status.map((s) => `${s.field}:${s.kind}:${s.entries}`), ['n:btree:1', 'tag:hash:1'], 'Fields, kinds, entries');
+  assert(status.every((s) => s.heapBytes > 0), 'heapBytes reported');
+});
+
+test('open enforces size limits', () => {
+  const dir = createTempDir();
+  const db = Database.open(join(dir, 'limits.jsonl'), { maxDocBytes: 100, maxIdLength: 16 });
+  let message = '';
+  try {
+    db.insert({ body: 'x'.repeat(200) });
+  } catch (e) {
+    message = e.message;
+  }
+  assert(/limit exceeded at 'body'/.test(message), `oversized doc rejected: ${message}`);
+  assert(db.isEmpty(), 'nothing written');
+  db.insert({ body: 'ok' });
+  rmSync(dir, { recursive: true, force: true });
+});
+
+// ─── Phase 12: Document Versions ─────────────────────────────────────
+
+section('Phase 12: Document Versions');
+
+test('updateIfVersion rejects stale writes', () => {
+  const db = Database.openInMemory();
+  const id = db.insert({ n: 1 });
+  const doc = db.get(id);
+  assertEqual(doc._version, 1, 'insert sets version 1');
+
+  assertEqual(db.updateIfVersion(id, { n: 2 }, doc._version), 2, 'new version');
+  let threw = false;
+  try {
+    db.updateIfVersion(id, { n: 3 }, doc._version);
+  } catch (e) {
+    threw = /version conflict/.test(e.message);
+  }
+  assert(threw, 'stale update should conflict');
+  assertEqual(db.get(id).n, 2, 'stale update not applied');
+
+  db.set(id, 'n', 4);
+  assertEqual(db.version(id), 3, 'patches bump the version');
+  db.deleteIfVersion(id, 3);
+  assert(!db.contains(id), 'delete applied');
+});
+
+// ─── Phase 13: Progress & Cancellation ──────────────────────────────
+
+section('Phase 13: Progress & Cancellation');
+
+await testAsync('operation reports compaction progress', async () => {
+  const dir = createTempDir();
+  const db = new Database(join(dir, 'progress.jsonl'));
+  for (let i = 0; i < 10; i++) db.insert({ i });
+
+  const phases = [];
+  const op = new Operation((p) => phases.push(p.phase));
+  await db.compact({ operation: op });
+  await new Promise((resolve) => setTimeout(resolve, 20));
+  assertEqual(phases, ['write', 'swap', 'done'], 'phases in order');
+  assertEqual(db.len(), 10, 'docs kept');
+
+  rmSync(dir, { recursive: true, force: true });
+});
+
+await testAsync('cancelled operations reject and change nothing', async () => {
+  const dir = createTempDir();
+  const db = new Database(join(dir, 'cancel.jsonl'));
+  db.insert({ n: 1 });
+
+  const op = new Operation();
+  op.cancel();
+  assert(op.cancelled, 'cancelled flag set');
+
+  let threw = false;
+  try {
+    await db.compact({ operation: op });
+  } catch (e) {
+    threw = /cancelled/.test(e.message);
+  }
+  assert(threw, 'compact should reject');
+
+  threw = false;
+  try {
+    await db.createIndexAsync('n', { btree: true, operation: op });
+  } catch (e) {
+    threw = /cancelled/.test(e.message);
+  }
+  assert(threw, 'index build should reject');
+  assert(!db.hasIndex('n'), 'no index installed');
+
+  threw = false;
+  try {
+    await db.queryWith({ n: { $eq: 1 } }, { limit: 1, operation: op });
+  } catch (e) {
+    threw = /cancelled/.test(e.message);
+  }
+  assert(threw, 'query should reject');
+  assertEqual((await db.query({ n: { $eq: 1 } })).length, 1, 'uncancelled query runs');
+
+  await db.createIndexAsync('n');
+  assert(db.hasIndex('n'), 'uncancelled build installs');
+
+  rmSync(dir, { recursive: true, force: true });
+});
+
+// ─── Phase 14: Runtime Configuration ─────────────────────────────────
+
+section('Phase 14: Runtime Configuration');
+
+test('setPersistence switches mode on an open database', () => {
+  const dir = join(os.tmpdir(), `ndb-persistence-${Date.now()}`);
+  mkdirSync(dir, { recursive: true });
+  const path = join(dir, 'switch.jsonl');
+  const db = Database.open(path, { persistence: 'immediate' });
+  db.setPersistence('lazy');
+  const ids = db.insertBatch([{ n: 1 }, { n: 2 }]);
+  db.setPersistence('group', { groupCommitMs: 5 });
+  ids.push(db.insert({ n: 3 }));
+
+  const reopened = Database.open(path);
+  assertEqual(ids.map((id) => reopened.get(id).n), [1, 2, 3], 'all writes kept');
+  rmSync(dir, { recursive: true, force: true });
+});
+
+test('metadata persists next to the schema', () => {
+  const dir = join(os.tmpdir(), `ndb-metadata-${Date.now()}`);
+  mkdirSync(dir, { recursive: true });
+  const path = join(dir, 'meta.jsonl');
+  const db = Database.open(path);
+  assertEqual(db.metadata(), {}, 'empty by default');
+  db.setMetadata({ model: 'mini', dims: 384 });
+  assertEqual(Database.open(path).metadata(), { dims: 384, model: 'mini' }, 'reloaded on open');
+  let threw = false;
+  try {
+    db.setMetadata([1]);
+  } catch (e) {
+    threw = e.code === 'NDB_INVALID_ARGUMENT';
+  }
+  assert(threw, 'arrays are rejected');
+  rmSync(dir, { recursive: true, force: true });
+});
+
+// ─── Phase 15: Error Codes ───────────────────────────────────────────
+
+section('Phase 15: Error Codes');
+
+await testAsync('errors carry a stable code and retryability', async () => {
+  const db = Database.openInMemory();
+  const id = db.insert({ n: 1 });
+
+  let err;
+  try {
+    db.get('missing');
+  } catch (e) {
+    err = e;
+  }
+  assertEqual(err.code, 'NDB_NOT_FOUND', 'sync error code');
+  assertEqual(err.retryable, false, 'not found is final');
+  assert(err.message.startsWith('Get failed: not found'), `code stripped from message: ${err.message}`);
+
+  try {
+    db.updateIfVersion(id, { n: 2 }, 7);
+  } catch (e) {
+    err = e;
+  }
+  assertEqual(err.code, 'NDB_VERSION_CONFLICT', 'conflict code');
+  assertEqual(err.retryable, true, 'conflicts can be retried');
+
+  const op = new Operation();
+  op.cancel();
+  await db.queryWith({ n: 1 }, { operation: op }).catch((e) => { err = e; });
+  assertEqual(err.code, 'NDB_CANCELLED', 'async error code');
+});
+
 // ─── Results ─────────────────────────────────────────────────────────
 
 console.log(`\n${'='.repeat(70)}`);
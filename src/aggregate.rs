@@ -0,0 +1,99 @@
+//! Field aggregates for `Database::aggregate`.
+//!
+//! Values are folded in as the matching documents are visited, so nothing
+//! is cloned except the running minimum/maximum and the distinct keys.
+
+use crate::{value_cmp, value_slot};
+use serde_json::{json, Value};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+/// What `Database::aggregate` computes over a field.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Aggregation {
+    /// Matching documents that have the field.
+    Count,
+    /// Smallest number, or smallest string if the field holds no numbers.
+    Min,
+    /// Largest number, or largest string if the field holds no numbers.
+    Max,
+    /// Mean of the numeric values.
+    Avg,
+    /// `[{ "value", "count" }]` per distinct value, most frequent first.
+    Distinct,
+}
+
+/// Running state of one aggregate.
+pub(crate) enum Aggregator {
+    Count(usize),
+    Extreme {
+        keep: Ordering,
+        number: Option<Value>,
+        string: Option<Value>,
+    },
+    Avg {
+        sum: f64,
+        n: usize,
+    },
+    Distinct(HashMap<String, (Value, usize)>),
+}
+
+impl Aggregator {
+    pub(crate) fn new(agg: Aggregation) -> Self {
+        match agg {
+            Aggregation::Count => Aggregator::Count(0),
+            Aggregation::Min | Aggregation::Max => Aggregator::Extreme {
+                keep: if agg == Aggregation::Min { Ordering::Less } else { Ordering::Greater },
+                number: None,
+                string: None,
+            },
+            Aggregation::Avg => Aggregator::Avg { sum: 0.0, n: 0 },
+            Aggregation::Distinct => Aggregator::Distinct(HashMap::new()),
+        }
+    }
+
+    /// Fold in the field value of one matching document.
+    pub(crate) fn add(&mut self, v: &Value) {
+        match self {
+            Aggregator::Count(n) => *n += 1,
+            Aggregator::Extreme { keep, number, string } => {
+                let best = match v {
+                    Value::Number(_) => number,
+                    Value::String(_) => string,
+                    _ => return,
+                };
+                if best.as_ref().map_or(true, |b| value_cmp(v, b) == *keep) {
+                    *best = Some(v.clone());
+                }
+            }
+            Aggregator::Avg { sum, n } => {
+                if let Some(x) = v.as_f64() {
+                    *sum += x;
+                    *n += 1;
+                }
+            }
+            Aggregator::Distinct(seen) => {
+                seen.entry(value_slot(v)).or_insert_with(|| (v.clone(), 0)).1 += 1;
+            }
+        }
+    }
+
+    pub(crate) fn finish(self) -> Value {
+        match self {
+            Aggregator::Count(n) => json!(n),
+            Aggregator::Extreme { number, string, .. } => number.or(string).unwrap_or(Value::Null),
+            Aggregator::Avg { n: 0, .. } => Value::Null,
+            Aggregator::Avg { sum, n } => json!(sum / n as f64),
+            Aggregator::Distinct(seen) => {
+                let mut counts: Vec<(String, Value, usize)> =
+                    seen.into_iter().map(|(slot, (v, n))| (slot, v, n)).collect();
+                // Ties in key order so the result does not depend on hash order
+                counts.sort_by(|a, b| b.2.cmp(&a.2).then_with(|| a.0.cmp(&b.0)));
+                counts
+                    .into_iter()
+                    .map(|(_, value, count)| json!({"value": value, "count": count}))
+                    .collect()
+            }
+        }
+    }
+}
@@ -0,0 +1,411 @@
+//! Write batches: inserts, updates and deletes committed as one log record.
+//!
+//! `Database::begin_batch` returns a `WriteBatch` that buffers operations in
+//! memory. `commit` checks them all against the current state, appends them
+//! as a single `{"_op": "batch", "docs": [...]}` line (deletes as tombstone
+//! entries) and applies them under one writer lock acquisition. A torn line
+//! is discarded on replay, so after a crash the whole batch is recovered or
+//! none of it is. Dropping a batch without committing discards it.
+
+use crate::error::{Error, Result};
+use crate::id::generate_unique_where;
+use crate::{doc_version, metrics, set_version, Change, Database};
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+
+/// One buffered operation.
+#[derive(Debug, Clone)]
+pub(crate) enum BatchOp {
+    Insert(Value),
+    Update(String, Value),
+    Delete(String),
+}
+
+impl BatchOp {
+    fn id(&self) -> &str {
+        match self {
+            BatchOp::Insert(doc) => doc["_id"].as_str().unwrap_or_default(),
+            BatchOp::Update(id, _) | BatchOp::Delete(id) => id,
+        }
+    }
+}
+
+/// Buffered writes, committed atomically by `commit`.
+#[must_use = "a batch does nothing until committed"]
+pub struct WriteBatch<'a> {
+    db: &'a Database,
+    ops: Vec<BatchOp>,
+    /// IDs generated for buffered inserts.
+    new_ids: HashSet<String>,
+}
+
+impl Database {
+    /// Start a batch of writes that commit as one atomic log record.
+    pub fn begin_batch(&self) -> WriteBatch<'_> {
+        WriteBatch {
+            db: self,
+            ops: Vec::new(),
+            new_ids: HashSet::new(),
+        }
+    }
+}
+
+impl WriteBatch<'_> {
+    /// Buffer an insert. Returns the generated `_id`, usable by later
+    /// operations in the same batch.
+    pub fn insert(&mut self, mut doc: Value) -> Result<String> {
+        let obj = doc
+            .as_object_mut()
+            .ok_or_else(|| Error::invalid_arg("batch insert: document must be a JSON object"))?;
+        let id = {
+            let docs = self.db.docs.read();
+            generate_unique_where(|id| self.new_ids.contains(id) || docs.contains_key(id))
+        };
+        obj.insert("_id".to_string(), Value::String(id.clone()));
+        set_version(&mut doc, 1);
+        self.db.check_doc(&doc)?;
+
+        self.new_ids.insert(id.clone());
+        self.ops.push(BatchOp::Insert(doc));
+        Ok(id)
+    }
+
+    /// Buffer a full-document update. The document must exist when the
+    /// batch commits (or be inserted earlier in the batch). Limits and the
+    /// schema are checked by `commit`, once the document has its `_version`.
+    pub fn update(&mut self, id: &str, mut doc: Value) -> Result<()> {
+        let obj = doc
+            .as_object_mut()
+            .ok_or_else(|| Error::invalid_arg("batch update: document must be a JSON object"))?;
+        obj.insert("_id".to_string(), Value::String(id.to_string()));
+
+        self.ops.push(BatchOp::Update(id.to_string(), doc));
+        Ok(())
+    }
+
+    /// Buffer a delete. The document must exist when the batch commits (or
+    /// be inserted earlier in the batch).
+    pub fn delete(&mut self, id: &str) {
+        self.ops.push(BatchOp::Delete(id.to_string()));
+    }
+
+    /// Number of buffered operations.
+    pub fn len(&self) -> usize {
+        self.ops.len()
+    }
+
+    /// True when nothing is buffered.
+    pub fn is_empty(&self) -> bool {
+        self.ops.is_empty()
+    }
+
+    /// Discard every buffered operation. Same as dropping the batch.
+    pub fn rollback(self) {}
+
+    /// Check, persist and apply every buffered operation.
+    ///
+    /// Fails without writing anything if an update or delete targets a
+    /// missing document or an updated document breaks a limit or the
+    /// schema. Operations apply in the order they were buffered.
+    pub fn commit(mut self) -> Result<()> {
+        let db = self.db;
+        db.throttle(self.ops.len())?;
+        let guard = db.writer.lock();
+        let _timer = db.metrics.write_latency.time();
+
+        if self.ops.is_empty() {
+            return Ok(());
+        }
+        let versions = self.check(db)?;
+        for (op, version) in self.ops.iter_mut().zip(versions) {
+            if let BatchOp::Update(_, doc) = op {
+                set_version(doc, version);
+                db.check_doc(doc)?;
+            }
+        }
+
+        let now = unix_now();
+        persist(db, &self.ops, now)?;
+        let (inserts, updates, deletes) = apply(db, self.ops, now);
+
+        drop(guard);
+        db.await_sync()?;
+
+        metrics::Recorder::count(&db.metrics.inserts, inserts);
+        metrics::Recorder::count(&db.metrics.updates, updates);
+        metrics::Recorder::count(&db.metrics.deletes, deletes);
+        Ok(())
+    }
+
+    /// Replay the batch against the current state without changing it.
+    /// Returns the version each operation leaves its document at.
+    fn check(&self, db: &Database) -> Result<Vec<u64>> {
+        let docs = db.docs.read();
+        // id → version after the operations seen so far (None once deleted)
+        let mut overlay: HashMap<&str, Option<u64>> = HashMap::new();
+        let mut versions = Vec::with_capacity(self.ops.len());
+        for op in &self.ops {
+            let id = op.id();
+            let current = match overlay.get(id) {
+                Some(version) => *version,
+                None => docs.get(id).map(doc_version),
+            };
+            let exists = current.is_some();
+            match op {
+                BatchOp::Insert(_) if exists => {
+                    return Err(Error::invalid_arg(format!(
+                        "batch insert: generated id '{}' is already in use",
+                        id
+                    )));
+                }
+                BatchOp::Update(..) | BatchOp::Delete(_) if !exists => {
+                    return Err(Error::not_found(id));
+                }
+                _ => {}
+            }
+            let version = match op {
+                BatchOp::Insert(_) => Some(1),
+                BatchOp::Update(..) => current.map(|v| v + 1),
+                BatchOp::Delete(_) => None,
+            };
+            overlay.insert(id, version);
+            versions.push(version.unwrap_or(0));
+        }
+        Ok(versions)
+    }
+}
+
+/// Append `ops` as one batch record. Call under the writer lock, with
+/// the operations checked and versioned. A batch of deletes only is
+/// exempt from the log quota, like a single delete.
+pub(crate) fn persist(db: &Database, ops: &[BatchOp], now: u64) -> Result<()> {
+    if db.is_in_memory() {
+        return Ok(());
+    }
+    let entries: Vec<Value> = ops
+        .iter()
+        .map(|op| match op {
+            BatchOp::Insert(doc) | BatchOp::Update(_, doc) => doc.clone(),
+            BatchOp::Delete(id) => serde_json::json!({"_id": id, "_deleted": now}),
+        })
+        .collect();
+    let line = serde_json::to_string(&serde_json::json!({"_op": "batch", "docs": entries}))?;
+    if ops.iter().all(|op| matches!(op, BatchOp::Delete(_))) {
+        db.append_tombstone(&line)
+    } else {
+        db.append_record(&line)
+    }
+}
+
+/// Apply persisted `ops` to memory, indexes and buckets. Returns the
+/// number of inserts, updates and deletes.
+pub(crate) fn apply(db: &Database, ops: Vec<BatchOp>, now: u64) -> (u64, u64, u64) {
+    let (mut inserts, mut updates, mut deletes) = (0, 0, 0);
+    for op in ops {
+        match op {
+            BatchOp::Insert(doc) => {
+                apply_insert(db, doc);
+                inserts += 1;
+            }
+            BatchOp::Update(id, doc) => {
+                apply_update(db, &id, doc);
+                updates += 1;
+            }
+            BatchOp::Delete(id) => {
+                apply_delete(db, &id, now);
+                deletes += 1;
+            }
+        }
+    }
+    (inserts, updates, deletes)
+}
+
+pub(crate) fn unix_now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+fn apply_insert(db: &Database, doc: Value) {
+    let id = doc["_id"].as_str().unwrap_or_default().to_string();
+    let mut indexes = db.indexes.write();
+    for (field, index) in indexes.iter_mut() {
+        if let Some(val) = doc.get(field) {
+            index.insert(val, &id);
+        }
+    }
+    drop(indexes);
+
+    db.increment_file_refs(&doc);
+    db.deleted.write().remove(&id);
+    db.docs.write().insert(id.clone(), doc);
+    db.notify(|| Change::Insert { id: id.clone(), doc: db.docs.read()[&id].clone() });
+}
+
+fn apply_update(db: &Database, id: &str, doc: Value) {
+    let old = db.docs.read()[id].clone();
+    db.reindex(id, &old, &doc);
+    db.handle_ref_delta_and_trash(&old, &doc);
+    db.docs.write().insert(id.to_string(), doc);
+    db.notify_update(id);
+}
+
+fn apply_delete(db: &Database, id: &str, now: u64) {
+    let old = db.docs.read()[id].clone();
+    db.trash_deleted_doc(id, old, now);
+
+    db.docs.write().remove(id);
+    db.deleted.write().insert(id.to_string());
+    db.notify(|| Change::Delete { id: id.to_string() });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use tempfile::TempDir;
+
+    #[test]
+    fn mixed_batch_persists_and_replays() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("batch.jsonl");
+        let db = Database::open(&path).unwrap();
+        let keep = db.insert(json!({"n": 1})).unwrap();
+        let gone = db.insert(json!({"n": 2})).unwrap();
+
+        let mut batch = db.begin_batch();
+        let added = batch.insert(json!({"n": 3})).unwrap();
+        let scratch = batch.insert(json!({"n": 4})).unwrap();
+        batch.update(&keep, json!({"n": 10})).unwrap();
+        batch.update(&added, json!({"n": 30})).unwrap();
+        batch.delete(&gone);
+        batch.delete(&scratch);
+        assert_eq!(batch.len(), 6);
+        batch.commit().unwrap();
+
+        let check = |db: &Database| {
+            assert_eq!(db.len(), 2);
+            assert_eq!(db.get(&keep).unwrap()["n"], 10);
+            assert_eq!(db.get(&added).unwrap()["n"], 30);
+            assert!(db.get(&gone).is_err());
+            assert!(db.get(&scratch).is_err());
+        };
+        check(&db);
+        // One log record for the whole batch
+        assert_eq!(db.log_seq().unwrap(), 3);
+        // Deleted documents can still be restored from the trash
+        assert!(db.deleted_ids().contains(&gone));
+        drop(db);
+        check(&Database::open(&path).unwrap());
+    }
+
+    #[test]
+    fn updates_are_checked_with_their_version() {
+        let dir = TempDir::new().unwrap();
+        let db = Database::open(dir.path().join("batch.jsonl")).unwrap();
+        let id = db.insert(json!({"n": 1})).unwrap();
+        // Fits without `_version`, not with it
+        let limit = json!({"_id": id, "n": 2}).to_string().len() + 5;
+        let db = db.with_limits(crate::Limits {
+            max_doc_bytes: Some(limit),
+            ..crate::Limits::default()
+        });
+
+        let mut batch = db.begin_batch();
+        batch.update(&id, json!({"n": 2})).unwrap();
+        assert!(matches!(batch.commit(), Err(Error::LimitExceeded { .. })));
+        assert_eq!(db.get(&id).unwrap()["n"], 1);
+        assert_eq!(db.log_seq().unwrap(), 1);
+    }
+
+    #[test]
+    fn delete_only_batch_is_exempt_from_log_quota() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("batch.jsonl");
+        let db = Database::open(&path).unwrap();
+        let a = db.insert(json!({"n": 1})).unwrap();
+        let b = db.insert(json!({"n": 2})).unwrap();
+        let full = std::fs::metadata(&path).unwrap().len();
+        let db = db.with_limits(crate::Limits {
+            max_log_bytes: Some(full),
+            ..crate::Limits::default()
+        });
+
+        let mut batch = db.begin_batch();
+        batch.insert(json!({"n": 3})).unwrap();
+        batch.delete(&a);
+        assert!(matches!(batch.commit(), Err(Error::LimitExceeded { .. })));
+
+        let mut batch = db.begin_batch();
+        batch.delete(&a);
+        batch.delete(&b);
+        batch.commit().unwrap();
+        assert!(db.is_empty());
+    }
+
+    #[test]
+    fn missing_target_fails_without_writing() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("batch.jsonl");
+        let db = Database::open(&path).unwrap();
+        let id = db.insert(json!({"n": 1})).unwrap();
+
+        let mut batch = db.begin_batch();
+        batch.insert(json!({"n": 2})).unwrap();
+        batch.delete(&id);
+        batch.update(&id, json!({"n": 3})).unwrap();
+        assert!(matches!(batch.commit(), Err(Error::NotFound { .. })));
+
+        assert_eq!(db.len(), 1);
+        assert_eq!(db.log_seq().unwrap(), 1);
+    }
+
+    #[test]
+    fn dropped_batch_is_discarded() {
+        let db = Database::open_in_memory().unwrap();
+        let mut batch = db.begin_batch();
+        batch.insert(json!({"n": 1})).unwrap();
+        drop(batch);
+
+        let mut batch = db.begin_batch();
+        batch.insert(json!({"n": 1})).unwrap();
+        batch.rollback();
+
+        assert!(db.is_empty());
+        assert!(db.begin_batch().commit().is_ok());
+    }
+
+    #[test]
+    fn batch_maintains_indexes() {
+        let db = Database::open_in_memory().unwrap();
+        db.create_index("kind").unwrap();
+        let a = db.insert(json!({"kind": "a"})).unwrap();
+        let b = db.insert(json!({"kind": "a"})).unwrap();
+
+        let mut batch = db.begin_batch();
+        batch.insert(json!({"kind": "b"})).unwrap();
+        batch.update(&a, json!({"kind": "b"})).unwrap();
+        batch.delete(&b);
+        batch.commit().unwrap();
+
+        assert!(db.find("kind", &json!("a")).is_empty());
+        assert_eq!(db.find("kind", &json!("b")).len(), 2);
+    }
+
+    #[test]
+    fn batch_bumps_versions_in_order() {
+        let db = Database::open_in_memory().unwrap();
+        let id = db.insert(json!({"n": 1})).unwrap();
+
+        let mut batch = db.begin_batch();
+        let added = batch.insert(json!({"n": 1})).unwrap();
+        batch.update(&id, json!({"n": 2})).unwrap();
+        batch.update(&id, json!({"n": 3})).unwrap();
+        batch.update(&added, json!({"n": 2})).unwrap();
+        batch.commit().unwrap();
+
+        assert_eq!(db.version(&id).unwrap(), 3);
+        assert_eq!(db.version(&added).unwrap(), 2);
+    }
+}
@@ -22,12 +22,16 @@ fn main() {
         "init" => handle_init(&args[2..]),
         "destroy" | "drop" => handle_destroy(&args[2..]),
         "info" => handle_info(&args[2..]),
+        "list" | "ls" => handle_list(&args[2..]),
+        "manifest" => handle_manifest(&args[2..]),
         "compact" => handle_compact(&args[2..]),
         "export" => handle_export(&args[2..]),
         "import" => handle_import(&args[2..]),
         "merge" => handle_merge(&args[2..]),
         "verify" | "check" => handle_verify(&args[2..]),
         "recover" => handle_recover(&args[2..]),
+        "migrate" => handle_migrate(&args[2..]),
+        "rollback" => handle_rollback(&args[2..]),
         "dump" => handle_dump(&args[2..]),
         "config" => handle_config(&args[2..]),
         "query" => handle_query(&args[2..]),
@@ -42,20 +46,25 @@ fn main() {
 fn print_usage() {
     eprintln!("nDB Command Line Interface");
     eprintln!("Usage: ndb <command> [args...]");
-    eprintln!("");
+    eprintln!();
     eprintln!("Commands:");
     eprintln!("  init <path> [--buckets a,b]   Initialize a new database");
     eprintln!("  destroy <path> --force        Safely delete a database");
     eprintln!("  info <path>                   Show database statistics");
+    eprintln!("  list <dir>                    List JSONL logs in a directory");
+    eprintln!("  manifest <path>               Print meta.json and the log header");
     eprintln!("  compact <path>                Compact the database in-place");
     eprintln!("  export <path> <dest>          Create a portable snapshot");
     eprintln!("  import <src> <path>           Restore a snapshot");
     eprintln!("  merge <base> <merge-in>       Combine databases");
     eprintln!("  verify <path>                 Check for corruptions");
     eprintln!("  recover <src> <dest>          Recover corrupted data");
+    eprintln!("  migrate <path> [--dry-run]    Upgrade the log to the current format");
+    eprintln!("  rollback <path> [seq]         Roll documents back to log record seq");
     eprintln!("  dump <path>                   Export JSON Lines to stdout");
     eprintln!("  config <get|set> ...          Manage metadata/config");
-    eprintln!("  query <path> <query_ast>      Run a raw JSON AST query");
+    eprintln!("  query <path> <query_ast> [--limit n] [--sort field[:desc]]");
+    eprintln!("                                Run a raw JSON AST query");
 }
 
 fn handle_init(args: &[String]) {
@@ -125,17 +134,8 @@ fn handle_init(args: &[String]) {
         eprintln!("Failed to write trash.jsonl: {}", e);
         process::exit(EXIT_GENERAL_ERROR);
     }
-    if !path.join("meta.json").exists() {
-        eprintln!("Error: Target is not a valid nDB folder.");
-        process::exit(EXIT_GENERAL_ERROR);
-    }
-
-    if let Err(e) = fs::remove_dir_all(path) {
-        eprintln!("Failed to destroy database: {}", e);
-        process::exit(EXIT_GENERAL_ERROR);
-    }
 
-    println!("Destroyed database at {}", path.display());
+    println!("Initialized database at {}", path.display());
     process::exit(EXIT_SUCCESS);
 }
 use std::io::Write;
@@ -192,7 +192,7 @@ fn handle_info(args: &[String]) {
     }
 
     let meta_content = fs::read_to_string(&meta_path).unwrap_or_default();
-    let meta: serde_json::Value = serde_json::from_str(&meta_content).unwrap_or_else(|_| serde_json::Value::Null);
+    let meta: serde_json::Value = serde_json::from_str(&meta_content).unwrap_or(serde_json::Value::Null);
 
     let active_size = fs::metadata(path.join("db.jsonl")).map(|m| m.len()).unwrap_or(0);
     let trash_size = fs::metadata(path.join("trash.jsonl")).map(|m| m.len()).unwrap_or(0);
@@ -250,6 +250,105 @@ fn handle_info(args: &[String]) {
     process::exit(EXIT_SUCCESS);
 }
 
+/// Count non-empty records after the `_meta` header line.
+fn count_records(log: &Path) -> usize {
+    use std::io::{BufRead, BufReader};
+    match fs::File::open(log) {
+        Ok(file) => BufReader::new(file)
+            .lines()
+            .skip(1)
+            .map_while(|l| l.ok())
+            .filter(|l| !l.trim().is_empty())
+            .count(),
+        Err(_) => 0,
+    }
+}
+
+fn handle_list(args: &[String]) {
+    if args.is_empty() {
+        eprintln!("Usage: ndb list <dir>");
+        process::exit(EXIT_GENERAL_ERROR);
+    }
+    let dir = Path::new(&args[0]);
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            eprintln!("Failed to read directory: {}", e);
+            process::exit(EXIT_GENERAL_ERROR);
+        }
+    };
+
+    let mut logs: Vec<_> = entries
+        .flatten()
+        .map(|e| e.path())
+        .filter(|p| p.is_file() && p.extension().is_some_and(|ext| ext == "jsonl"))
+        .collect();
+    logs.sort();
+
+    if logs.is_empty() {
+        println!("No JSONL logs in {}", dir.display());
+        process::exit(EXIT_SUCCESS);
+    }
+
+    println!("{:<24} {:>10} {:>14}  header", "log", "records", "bytes");
+    for log in logs {
+        let name = log.file_name().unwrap_or_default().to_string_lossy();
+        let bytes = fs::metadata(&log).map(|m| m.len()).unwrap_or(0);
+        let header = match ndb::storage::read_header(&log) {
+            Ok(Some(h)) => h,
+            Ok(None) => "(none)".to_string(),
+            Err(e) => format!("(unreadable: {})", e),
+        };
+        println!("{:<24} {:>10} {:>14}  {}", name, count_records(&log), bytes, header);
+    }
+    process::exit(EXIT_SUCCESS);
+}
+
+fn handle_manifest(args: &[String]) {
+    if args.is_empty() {
+        eprintln!("Usage: ndb manifest <path>");
+        process::exit(EXIT_GENERAL_ERROR);
+    }
+    let path = Path::new(&args[0]);
+    let meta_path = path.join("meta.json");
+    let db_path = path.join("db.jsonl");
+
+    let meta: serde_json::Value = match fs::read_to_string(&meta_path) {
+        Ok(c) => match serde_json::from_str(&c) {
+            Ok(v) => v,
+            Err(e) => {
+                eprintln!("Invalid meta.json: {}", e);
+                process::exit(EXIT_CORRUPTION);
+            }
+        },
+        Err(e) => {
+            eprintln!("Failed to read {}: {}", meta_path.display(), e);
+            process::exit(EXIT_GENERAL_ERROR);
+        }
+    };
+
+    let header: serde_json::Value = match ndb::storage::read_header(&db_path) {
+        Ok(Some(h)) => serde_json::from_str(&h).unwrap_or(serde_json::Value::String(h)),
+        Ok(None) => serde_json::Value::Null,
+        Err(e) => {
+            eprintln!("Failed to read log header: {}", e);
+            process::exit(EXIT_GENERAL_ERROR);
+        }
+    };
+
+    let manifest = serde_json::json!({
+        "meta": meta,
+        "log": {
+            "path": db_path.display().to_string(),
+            "header": header,
+            "records": count_records(&db_path),
+            "bytes": fs::metadata(&db_path).map(|m| m.len()).unwrap_or(0),
+        },
+    });
+    println!("{}", serde_json::to_string_pretty(&manifest).unwrap_or_default());
+    process::exit(EXIT_SUCCESS);
+}
+
 fn handle_compact(args: &[String]) {
     if args.is_empty() {
         eprintln!("Usage: ndb compact <path>");
@@ -301,11 +400,9 @@ fn handle_export(args: &[String]) {
     let db_path = src_path.join("db.jsonl");
 
     let consistent = args.iter().any(|a| a == "--consistent");
-    if consistent {
-        if !src_path.join(".readonly").exists() {
-            eprintln!("Error: --consistent requested but .readonly marker not found. Database might be actively writing.");
-            process::exit(EXIT_LOCKED);
-        }
+    if consistent && !src_path.join(".readonly").exists() {
+        eprintln!("Error: --consistent requested but .readonly marker not found. Database might be actively writing.");
+        process::exit(EXIT_LOCKED);
     }
 
     if dest_path.exists() {
@@ -780,6 +877,108 @@ fn handle_recover(args: &[String]) {
     process::exit(EXIT_SUCCESS);
 }
 
+fn handle_migrate(args: &[String]) {
+    if args.is_empty() {
+        eprintln!("Usage: ndb migrate <path> [--dry-run]");
+        process::exit(EXIT_GENERAL_ERROR);
+    }
+    let path = Path::new(&args[0]);
+    let dry_run = args.iter().any(|a| a == "--dry-run");
+
+    if path.join(".lock").exists() && !dry_run {
+        eprintln!("Error: Database is actively locked. Stop it before migrating.");
+        process::exit(EXIT_LOCKED);
+    }
+
+    let report = match ndb::migrate::upgrade(&path.join("db.jsonl"), dry_run) {
+        Ok(report) => report,
+        Err(e @ ndb::Error::Corruption { .. }) => {
+            eprintln!("Migration refused: {}", e);
+            process::exit(EXIT_CORRUPTION);
+        }
+        Err(e) => {
+            eprintln!("Migration failed: {}", e);
+            process::exit(EXIT_GENERAL_ERROR);
+        }
+    };
+
+    if report.is_noop() {
+        println!("Already at format version {}.", report.to);
+        process::exit(EXIT_SUCCESS);
+    }
+    for step in &report.steps {
+        eprintln!("  - {}", step);
+    }
+    if dry_run {
+        println!("Dry run: would upgrade {} records from version {} to {}.", report.records, report.from, report.to);
+    } else {
+        println!("Upgraded {} records from version {} to {}.", report.records, report.from, report.to);
+        if let Some(backup) = &report.backup {
+            println!("Original kept at {}", backup.display());
+        }
+    }
+    process::exit(EXIT_SUCCESS);
+}
+
+fn handle_rollback(args: &[String]) {
+    if args.is_empty() {
+        eprintln!("Usage: ndb rollback <path> [seq]");
+        process::exit(EXIT_GENERAL_ERROR);
+    }
+    let path = Path::new(&args[0]);
+    let db_path = path.join("db.jsonl");
+    let seq = match args.get(1).map(|s| s.parse::<u64>()) {
+        None => None,
+        Some(Ok(seq)) => Some(seq),
+        Some(Err(_)) => {
+            eprintln!("Error: seq must be a log record number.");
+            process::exit(EXIT_GENERAL_ERROR);
+        }
+    };
+
+    if seq.is_some() && path.join(".lock").exists() {
+        eprintln!("Error: Database is actively locked. Stop it before rolling back.");
+        process::exit(EXIT_LOCKED);
+    }
+    if !db_path.exists() {
+        eprintln!("Error: Target is not a valid nDB folder.");
+        process::exit(EXIT_GENERAL_ERROR);
+    }
+
+    let db = match ndb::Database::open(&db_path) {
+        Ok(db) => db,
+        Err(e) => {
+            eprintln!("Failed to open database: {}", e);
+            process::exit(EXIT_GENERAL_ERROR);
+        }
+    };
+    let current = match db.log_seq() {
+        Ok(current) => current,
+        Err(e) => {
+            eprintln!("Failed to read log: {}", e);
+            process::exit(EXIT_GENERAL_ERROR);
+        }
+    };
+
+    // Without a seq, only show how far back history reaches
+    let Some(seq) = seq else {
+        println!("Log holds records 0 to {} since the last compaction.", current);
+        process::exit(EXIT_SUCCESS);
+    };
+    match db.restore_to(seq) {
+        Ok(changed) => {
+            println!("Rolled back {} documents to record {}.", changed, seq);
+            // The rollback is one more record, so `current` still names the state before it
+            println!("Undo with: ndb rollback {} {}", path.display(), current);
+            process::exit(EXIT_SUCCESS);
+        }
+        Err(e) => {
+            eprintln!("Rollback failed: {}", e);
+            process::exit(EXIT_GENERAL_ERROR);
+        }
+    }
+}
+
 fn handle_dump(args: &[String]) {
     if args.is_empty() {
         eprintln!("Usage: ndb dump <path>");
@@ -906,13 +1105,12 @@ fn handle_config(args: &[String]) {
 
 fn handle_query(args: &[String]) {
     if args.len() < 2 {
-        eprintln!("Usage: ndb query <path> <query_ast>");
+        eprintln!("Usage: ndb query <path> <query_ast> [--limit n] [--sort field[:desc]]");
         process::exit(EXIT_GENERAL_ERROR);
     }
     let path = Path::new(&args[0]);
-    let query_str = &args[1];
 
-    let query: std::collections::HashMap<String, serde_json::Value> = match serde_json::from_str(query_str) {
+    let query: serde_json::Value = match serde_json::from_str(&args[1]) {
         Ok(q) => q,
         Err(e) => {
             eprintln!("Invalid JSON query: {}", e);
@@ -920,6 +1118,33 @@ fn handle_query(args: &[String]) {
         }
     };
 
+    let mut opts = ndb::QueryOptions::default();
+    let mut i = 2;
+    while i < args.len() {
+        match (args[i].as_str(), args.get(i + 1)) {
+            ("--limit", Some(n)) => match n.parse() {
+                Ok(n) => opts.limit = Some(n),
+                Err(_) => {
+                    eprintln!("Invalid --limit: {}", n);
+                    process::exit(EXIT_GENERAL_ERROR);
+                }
+            },
+            ("--sort", Some(spec)) => {
+                let (field, dir) = match spec.rsplit_once(':') {
+                    Some((field, "desc")) => (field, ndb::SortDir::Desc),
+                    Some((field, "asc")) => (field, ndb::SortDir::Asc),
+                    _ => (spec.as_str(), ndb::SortDir::Asc),
+                };
+                opts.sort_by = Some((field.to_string(), dir));
+            }
+            (flag, _) => {
+                eprintln!("Unknown or incomplete option: {}", flag);
+                process::exit(EXIT_GENERAL_ERROR);
+            }
+        }
+        i += 2;
+    }
+
     let db_path = path.join("db.jsonl");
     let db = match ndb::Database::open(&db_path) {
         Ok(db) => db,
@@ -929,29 +1154,10 @@ fn handle_query(args: &[String]) {
         }
     };
 
-    let mut matches = 0;
-    for id in db.get_all_ids() {
-        if let Ok(doc) = db.get(&id) {
-            let mut matched = true;
-            for (k, v) in &query {
-                if let Some(doc_val) = doc.get(k) {
-                    if let Some(op_obj) = v.as_object() {
-                        if let Some(eq_val) = op_obj.get("$eq") {
-                            if doc_val != eq_val { matched = false; break; }
-                        }
-                    } else if doc_val != v {
-                        matched = false; break;
-                    }
-                } else {
-                    matched = false; break;
-                }
-            }
-            if matched {
-                println!("{}", serde_json::to_string_pretty(&doc).unwrap_or_default());
-                matches += 1;
-            }
-        }
+    let results = db.query_with(query, opts);
+    for doc in &results {
+        println!("{}", serde_json::to_string_pretty(doc).unwrap_or_default());
     }
-    eprintln!("Found {} results.", matches);
+    eprintln!("Found {} results.", results.len());
     process::exit(EXIT_SUCCESS);
 }
@@ -4,6 +4,7 @@
 //! Each bucket is a folder. Trash is per-bucket.
 
 use crate::error::{Error, Result};
+use crate::storage;
 use serde::{Deserialize, Serialize};
 use std::fs;
 use std::io::Write;
@@ -70,6 +71,29 @@ pub struct FileBucket {
     base_dir: PathBuf,
 }
 
+/// Longest bucket name, hash or extension accepted.
+const MAX_NAME_LEN: usize = 128;
+
+/// Check a bucket name, hash or extension before it is joined into a path.
+/// Only ASCII letters, digits, `_` and `-` are allowed, so no value can
+/// leave the bucket directory. Returns `Error::InvalidArgument` otherwise.
+fn check_component(what: &str, value: &str, allow_empty: bool) -> Result<()> {
+    let valid_char = |c: char| c.is_ascii_alphanumeric() || c == '_' || c == '-';
+    let problem = if value.is_empty() && !allow_empty {
+        Some("must not be empty")
+    } else if value.len() > MAX_NAME_LEN {
+        Some("is too long")
+    } else if !value.chars().all(valid_char) {
+        Some("may only contain letters, digits, '_' and '-'")
+    } else {
+        None
+    };
+    match problem {
+        Some(problem) => Err(Error::invalid_arg(format!("{} '{}' {}", what, value, problem))),
+        None => Ok(()),
+    }
+}
+
 impl FileBucket {
     /// Create a new file bucket reference.
     pub fn new(name: &str, base_dir: &Path) -> Self {
@@ -80,14 +104,28 @@ impl FileBucket {
     }
 
     /// Get the directory path for this bucket.
-    fn dir(&self) -> PathBuf {
+    fn dir(&self) -> Result<PathBuf> {
+        check_component("bucket name", &self.name, false)?;
         if self.name == "_files" {
-            self.base_dir.join("_files")
+            Ok(self.base_dir.join("_files"))
         } else {
-            self.base_dir.join("_files").join(&self.name)
+            Ok(self.base_dir.join("_files").join(&self.name))
         }
     }
 
+    /// `trash_dir` after checking the bucket name.
+    fn checked_trash_dir(&self) -> Result<PathBuf> {
+        check_component("bucket name", &self.name, false)?;
+        Ok(self.trash_dir())
+    }
+
+    /// Path of a stored file in `dir`, after checking hash and extension.
+    fn file_path(dir: &Path, hash: &str, ext: &str) -> Result<PathBuf> {
+        check_component("file hash", hash, false)?;
+        check_component("file extension", ext, true)?;
+        Ok(dir.join(format!("{}.{}", hash, ext)))
+    }
+
     /// Get the trash directory for this bucket.
     pub fn trash_dir(&self) -> PathBuf {
         self.base_dir.join("_trash").join("files").join(&self.name)
@@ -105,7 +143,7 @@ impl FileBucket {
     /// Store a file. Returns FileMeta with hash reference.
     /// If file already exists (same hash), just returns the reference (dedup).
     pub fn store(&self, name: &str, data: &[u8], mime_type: &str) -> Result<FileMeta> {
-        let dir = self.dir();
+        let dir = self.dir()?;
         fs::create_dir_all(&dir).map_err(Error::io_err(&dir, "create bucket directory"))?;
 
         let full_hash = Self::sha256(data);
@@ -115,7 +153,7 @@ impl FileBucket {
             .map(|e| e.to_string_lossy().to_string())
             .unwrap_or_default();
 
-        let file_path = dir.join(format!("{}.{}", hash_id, ext));
+        let file_path = Self::file_path(&dir, hash_id, &ext)?;
 
         // Only write if file doesn't exist (dedup)
         if !file_path.exists() {
@@ -131,6 +169,7 @@ impl FileBucket {
                 .map_err(Error::io_err(&tmp_path, "fsync file data"))?;
             fs::rename(&tmp_path, &file_path)
                 .map_err(Error::io_err(&file_path, "atomic rename file"))?;
+            storage::sync_dir(&file_path)?;
         }
 
         let now = std::time::SystemTime::now()
@@ -153,41 +192,45 @@ impl FileBucket {
 
     /// Get file bytes by FileRef.
     pub fn get(&self, file_ref: &FileRef) -> Result<Vec<u8>> {
-        let path = self.dir().join(file_ref.filename());
+        let path = Self::file_path(&self.dir()?, &file_ref.id, &file_ref.ext)?;
         fs::read(&path).map_err(Error::io_err(&path, "read file"))
     }
 
     /// Get file bytes by hash string.
     pub fn get_by_hash(&self, hash: &str, ext: &str) -> Result<Vec<u8>> {
-        let path = self.dir().join(format!("{}.{}", hash, ext));
+        let path = Self::file_path(&self.dir()?, hash, ext)?;
         fs::read(&path).map_err(Error::io_err(&path, "read file by hash"))
     }
 
-    /// Check if a file exists.
+    /// Check if a file exists. False for names that fail validation.
     pub fn exists(&self, file_ref: &FileRef) -> bool {
-        self.dir().join(file_ref.filename()).exists()
+        self.dir()
+            .and_then(|dir| Self::file_path(&dir, &file_ref.id, &file_ref.ext))
+            .is_ok_and(|path| path.exists())
     }
 
     /// Delete a file (move to trash).
     pub fn delete(&self, file_ref: &FileRef) -> Result<()> {
-        let src = self.dir().join(file_ref.filename());
+        let src = Self::file_path(&self.dir()?, &file_ref.id, &file_ref.ext)?;
         if !src.exists() {
             return Err(Error::not_found(file_ref.filename()));
         }
 
-        let trash_dir = self.trash_dir();
+        let trash_dir = self.checked_trash_dir()?;
         fs::create_dir_all(&trash_dir)
             .map_err(Error::io_err(&trash_dir, "create file trash directory"))?;
 
         let dst = trash_dir.join(file_ref.filename());
         fs::rename(&src, &dst).map_err(Error::io_err(&src, "move file to trash"))?;
+        storage::sync_dir(&src)?;
+        storage::sync_dir(&dst)?;
 
         Ok(())
     }
 
     /// List all files in this bucket.
     pub fn list(&self) -> Result<Vec<String>> {
-        let dir = self.dir();
+        let dir = self.dir()?;
         if !dir.exists() {
             return Ok(Vec::new());
         }
@@ -204,24 +247,43 @@ impl FileBucket {
         Ok(files)
     }
 
+    /// Delete `.tmp` files left by stores that never reached their rename.
+    /// Returns the bytes freed. Only safe while no `store` is running.
+    pub fn remove_temp_files(&self) -> Result<u64> {
+        let dir = self.dir()?;
+        if !dir.exists() {
+            return Ok(0);
+        }
+        let mut freed = 0;
+        for entry in fs::read_dir(&dir).map_err(Error::io_err(&dir, "list bucket files"))? {
+            let entry = entry.map_err(Error::io_err(&dir, "read dir entry"))?;
+            let path = entry.path();
+            if path.extension().is_some_and(|ext| ext == "tmp") && entry.file_type().is_ok_and(|t| t.is_file()) {
+                let size = entry.metadata().map(|m| m.len()).unwrap_or(0);
+                fs::remove_file(&path).map_err(Error::io_err(&path, "remove leftover temp file"))?;
+                freed += size;
+            }
+        }
+        Ok(freed)
+    }
+
     /// Restore a file from trash.
     pub fn restore(&self, hash: &str, ext: &str) -> Result<()> {
-        let trash_dir = self.trash_dir();
-        let filename = format!("{}.{}", hash, ext);
-        let src = trash_dir.join(&filename);
-        let dst = self.dir().join(&filename);
+        let src = Self::file_path(&self.checked_trash_dir()?, hash, ext)?;
+        let dst = Self::file_path(&self.dir()?, hash, ext)?;
 
         if !src.exists() {
-            return Err(Error::not_found(filename));
+            return Err(Error::not_found(format!("{}.{}", hash, ext)));
         }
 
         fs::rename(&src, &dst).map_err(Error::io_err(&src, "restore file from trash"))?;
-        Ok(())
+        storage::sync_dir(&src)?;
+        storage::sync_dir(&dst)
     }
 
     /// Purge trashed files older than given duration.
     pub fn purge_trash_ttl(&self, older_than: std::time::Duration) -> Result<usize> {
-        let trash_dir = self.trash_dir();
+        let trash_dir = self.checked_trash_dir()?;
         if !trash_dir.exists() {
             return Ok(0);
         }
@@ -391,6 +453,50 @@ mod tests {
         assert_eq!(retrieved, data);
     }
 
+    #[test]
+    fn names_cannot_escape_the_bucket() {
+        let dir = TempDir::new().unwrap();
+        let secret = dir.path().join("secret.txt");
+        fs::write(&secret, "keep").unwrap();
+
+        for name in ["../..", "..", "", ".hidden", "a.b", "a/b", "a\\b"] {
+            let bucket = FileBucket::new(name, &dir.path().join("db"));
+            assert!(matches!(bucket.list(), Err(Error::InvalidArgument { .. })), "{:?}", name);
+            assert!(matches!(bucket.store("x.txt", b"x", "text/plain"), Err(Error::InvalidArgument { .. })));
+        }
+
+        let bucket = FileBucket::new("docs_v2", &dir.path().join("db"));
+        let escape = FileRef {
+            bucket: "docs_v2".into(),
+            id: "../../../secret".into(),
+            ext: "txt".into(),
+        };
+        assert!(matches!(bucket.delete(&escape), Err(Error::InvalidArgument { .. })));
+        assert!(matches!(bucket.get(&escape), Err(Error::InvalidArgument { .. })));
+        assert!(matches!(bucket.get_by_hash(".", ""), Err(Error::InvalidArgument { .. })));
+        assert!(matches!(bucket.restore("abc", "/x"), Err(Error::InvalidArgument { .. })));
+        assert!(!bucket.exists(&escape));
+        assert!(bucket.store("x.t xt", b"x", "text/plain").is_err());
+        assert_eq!(fs::read_to_string(&secret).unwrap(), "keep");
+
+        // Extension-less files still work
+        let meta = bucket.store("README", b"hi", "text/plain").unwrap();
+        assert_eq!(bucket.get(&meta._file).unwrap(), b"hi");
+    }
+
+    #[test]
+    fn remove_temp_files_skips_stored_files() {
+        let (bucket, _) = test_bucket("uploads");
+        let meta = bucket.store("a.txt", b"kept", "text/plain").unwrap();
+        let dir = bucket.dir().unwrap();
+        fs::write(dir.join("deadbeef.tmp"), b"partial").unwrap();
+
+        assert_eq!(bucket.remove_temp_files().unwrap(), 7);
+        assert!(!dir.join("deadbeef.tmp").exists());
+        assert_eq!(bucket.get(&meta._file).unwrap(), b"kept");
+        assert_eq!(bucket.remove_temp_files().unwrap(), 0);
+    }
+
     #[test]
     fn store_deduplicates() {
         let (bucket, _) = test_bucket("files");
@@ -16,6 +16,11 @@ pub enum Error {
         context: String,
     },
 
+    /// The device ran out of space. Nothing was applied; free space (or
+    /// compact) and retry.
+    #[error("disk full at {path}: {context}")]
+    DiskFull { path: PathBuf, context: String },
+
     /// Data corruption detected.
     #[error("corruption in {file}: {message}")]
     Corruption {
@@ -43,6 +48,42 @@ pub enum Error {
     #[error("index error for field '{field}': {reason}")]
     IndexError { field: String, reason: String },
 
+    /// Document does not satisfy the database schema.
+    #[error("schema violation at '{field}': {reason}")]
+    SchemaViolation { field: String, reason: String },
+
+    /// Write exceeds a configured size limit.
+    #[error("limit exceeded at '{field}': {actual} bytes (limit {limit})")]
+    LimitExceeded {
+        field: String,
+        limit: usize,
+        actual: usize,
+    },
+
+    /// Write rate limit reached; retry after the given wait.
+    #[error("throttled: retry after {retry_after:?}")]
+    Throttled { retry_after: std::time::Duration },
+
+    /// Conditional write lost to a concurrent one.
+    #[error("version conflict on {id}: expected version {expected}, found {actual}")]
+    VersionConflict {
+        id: String,
+        expected: u64,
+        actual: u64,
+    },
+
+    /// Long-running operation stopped by its `OperationHandle`.
+    #[error("{operation} cancelled during {phase}")]
+    Cancelled { operation: String, phase: String },
+
+    /// Log written by a newer (or unknown) format version.
+    #[error("unsupported format version {found} in {path} (this build supports up to {supported})")]
+    UnsupportedVersion {
+        path: PathBuf,
+        found: u64,
+        supported: u64,
+    },
+
     /// File bucket error.
     #[error("file bucket error: {reason}")]
     BucketError { reason: String },
@@ -54,10 +95,18 @@ impl Error {
         path: impl Into<PathBuf>,
         context: impl Into<String>,
     ) -> impl FnOnce(std::io::Error) -> Self {
-        move |e: std::io::Error| Error::Io {
-            source: e,
-            path: path.into(),
-            context: context.into(),
+        move |e: std::io::Error| {
+            if is_disk_full(&e) {
+                return Error::DiskFull {
+                    path: path.into(),
+                    context: context.into(),
+                };
+            }
+            Error::Io {
+                source: e,
+                path: path.into(),
+                context: context.into(),
+            }
         }
     }
 
@@ -74,6 +123,55 @@ impl Error {
         Error::NotFound { id: id.into() }
     }
 
+    /// Create a schema violation error.
+    pub fn schema(field: impl Into<String>, reason: impl Into<String>) -> Self {
+        Error::SchemaViolation {
+            field: field.into(),
+            reason: reason.into(),
+        }
+    }
+
+    /// Create a limit-exceeded error.
+    pub fn limit(field: impl Into<String>, limit: usize, actual: usize) -> Self {
+        Error::LimitExceeded {
+            field: field.into(),
+            limit,
+            actual,
+        }
+    }
+
+    /// Stable machine-readable code, e.g. `"NDB_NOT_FOUND"`. Codes never
+    /// change once released; match on these rather than on messages.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Error::Io { .. } => "NDB_IO",
+            Error::DiskFull { .. } => "NDB_DISK_FULL",
+            Error::Corruption { .. } => "NDB_CORRUPTION",
+            Error::NotFound { .. } => "NDB_NOT_FOUND",
+            Error::InvalidArgument { .. } => "NDB_INVALID_ARGUMENT",
+            Error::Serialization(_) => "NDB_SERIALIZATION",
+            Error::DatabaseLocked { .. } => "NDB_DATABASE_LOCKED",
+            Error::IndexError { .. } => "NDB_INDEX_ERROR",
+            Error::SchemaViolation { .. } => "NDB_SCHEMA_VIOLATION",
+            Error::LimitExceeded { .. } => "NDB_LIMIT_EXCEEDED",
+            Error::Throttled { .. } => "NDB_THROTTLED",
+            Error::VersionConflict { .. } => "NDB_VERSION_CONFLICT",
+            Error::Cancelled { .. } => "NDB_CANCELLED",
+            Error::UnsupportedVersion { .. } => "NDB_UNSUPPORTED_VERSION",
+            Error::BucketError { .. } => "NDB_BUCKET_ERROR",
+        }
+    }
+
+    /// True if the same call may succeed when retried unchanged: the
+    /// database was busy or a conditional write lost a race. Invalid input,
+    /// corruption and I/O failures are not retryable.
+    pub fn is_retryable(&self) -> bool {
+        matches!(
+            self,
+            Error::Throttled { .. } | Error::VersionConflict { .. } | Error::DatabaseLocked { .. }
+        )
+    }
+
     /// Create an invalid argument error.
     pub fn invalid_arg(reason: impl Into<String>) -> Self {
         Error::InvalidArgument {
@@ -82,6 +180,18 @@ impl Error {
     }
 }
 
+/// ENOSPC on unix, ERROR_DISK_FULL / ERROR_HANDLE_DISK_FULL on Windows.
+/// (`ErrorKind::StorageFull` is newer than our MSRV.)
+fn is_disk_full(e: &std::io::Error) -> bool {
+    match e.raw_os_error() {
+        #[cfg(unix)]
+        Some(28) => true,
+        #[cfg(windows)]
+        Some(39 | 112) => true,
+        _ => false,
+    }
+}
+
 impl From<serde_json::Error> for Error {
     fn from(e: serde_json::Error) -> Self {
         Error::Serialization(e.to_string())
@@ -90,3 +200,33 @@ impl From<serde_json::Error> for Error {
 
 /// Result type alias for ndb operations.
 pub type Result<T> = std::result::Result<T, Error>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn codes_and_retryability() {
+        assert_eq!(Error::not_found("x").code(), "NDB_NOT_FOUND");
+        assert_eq!(Error::schema("a", "b").code(), "NDB_SCHEMA_VIOLATION");
+        let io = Error::io_err("p", "read")(std::io::Error::other("boom"));
+        assert_eq!(io.code(), "NDB_IO");
+        assert!(!io.is_retryable());
+        #[cfg(unix)]
+        {
+            let full = Error::io_err("p", "append")(std::io::Error::from_raw_os_error(28));
+            assert_eq!(full.code(), "NDB_DISK_FULL");
+        }
+        assert!(!Error::invalid_arg("bad").is_retryable());
+        assert!(Error::Throttled {
+            retry_after: std::time::Duration::from_millis(5)
+        }
+        .is_retryable());
+        assert!(Error::VersionConflict {
+            id: "x".into(),
+            expected: 1,
+            actual: 2
+        }
+        .is_retryable());
+    }
+}
@@ -28,9 +28,15 @@ pub fn generate_with_prefix(prefix: &str) -> String {
 /// Generate a unique ID, checking against existing keys.
 /// Retries up to 10 times on collision (astronomically unlikely).
 pub fn generate_unique(existing: &HashSet<String>) -> String {
+    generate_unique_where(|id| existing.contains(id))
+}
+
+/// Generate an ID for which `taken` is false, e.g. one free in several sets
+/// at once without merging them. Same retries as `generate_unique`.
+pub fn generate_unique_where(taken: impl Fn(&str) -> bool) -> String {
     for _ in 0..10 {
         let id = generate();
-        if !existing.contains(&id) {
+        if !taken(&id) {
             return id;
         }
     }
@@ -92,6 +98,18 @@ mod tests {
         assert_ne!(id, "aaaaaaaaaaaaaaaa");
     }
 
+    #[test]
+    fn generate_unique_where_retries_taken_ids() {
+        let tried = std::cell::RefCell::new(Vec::new());
+        let id = generate_unique_where(|id| {
+            tried.borrow_mut().push(id.to_string());
+            tried.borrow().len() < 3
+        });
+        let tried = tried.into_inner();
+        assert_eq!(tried.len(), 3);
+        assert_eq!(id, tried[2]);
+    }
+
     #[test]
     fn is_valid_id_checks() {
         assert!(is_valid_id("V1StGXR8Z5jdHi6B"));
@@ -0,0 +1,124 @@
+//! Idempotency keys for `insert_idempotent` / `delete_idempotent`.
+//!
+//! A key maps to the document ID its first write produced. Keys are kept
+//! for a time window and capped in number, oldest dropped first, so a
+//! client retrying after a timeout gets the original outcome instead of a
+//! duplicate insert or a second delete.
+
+use std::collections::{HashMap, VecDeque};
+use std::time::Duration;
+
+/// Keys kept at most, whatever their age.
+pub(crate) const MAX_KEYS: usize = 100_000;
+
+/// Default time a key is remembered.
+pub(crate) const DEFAULT_WINDOW: Duration = Duration::from_secs(3600);
+
+#[derive(Debug)]
+pub(crate) struct KeyWindow {
+    window: Duration,
+    ids: HashMap<String, (String, u64)>,
+    /// `(at, key)` in recording order, for pruning from the front.
+    order: VecDeque<(u64, String)>,
+}
+
+impl Default for KeyWindow {
+    fn default() -> Self {
+        KeyWindow {
+            window: DEFAULT_WINDOW,
+            ids: HashMap::new(),
+            order: VecDeque::new(),
+        }
+    }
+}
+
+impl KeyWindow {
+    pub(crate) fn set_window(&mut self, window: Duration) {
+        self.window = window;
+    }
+
+    /// ID recorded for `key`, if it is still inside the window at `now`.
+    pub(crate) fn get(&mut self, key: &str, now: u64) -> Option<String> {
+        self.prune(now);
+        self.ids.get(key).map(|(id, _)| id.clone())
+    }
+
+    /// Remember that `key` produced `id` at `at` (Unix seconds).
+    pub(crate) fn record(&mut self, key: &str, id: &str, at: u64) {
+        self.ids.insert(key.to_string(), (id.to_string(), at));
+        self.order.push_back((at, key.to_string()));
+        while self.order.len() > MAX_KEYS {
+            self.pop_front();
+        }
+    }
+
+    /// `(key, id, at)` of every key still inside the window at `now`,
+    /// oldest first.
+    pub(crate) fn live(&mut self, now: u64) -> Vec<(String, String, u64)> {
+        self.prune(now);
+        self.order
+            .iter()
+            .filter_map(|(at, key)| match self.ids.get(key) {
+                Some((id, recorded)) if recorded == at => Some((key.clone(), id.clone(), *at)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn prune(&mut self, now: u64) {
+        let cutoff = now.saturating_sub(self.window.as_secs());
+        while self.order.front().is_some_and(|(at, _)| *at < cutoff) {
+            self.pop_front();
+        }
+    }
+
+    fn pop_front(&mut self) {
+        if let Some((at, key)) = self.order.pop_front() {
+            // A key recorded again later has a newer entry further back
+            if self.ids.get(&key).is_some_and(|(_, recorded)| *recorded == at) {
+                self.ids.remove(&key);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys_expire_after_the_window() {
+        let mut keys = KeyWindow::default();
+        keys.set_window(Duration::from_secs(10));
+        keys.record("a", "id1", 100);
+        keys.record("b", "id2", 105);
+        assert_eq!(keys.get("a", 110).as_deref(), Some("id1"));
+        assert_eq!(keys.get("a", 111), None);
+        assert_eq!(keys.get("b", 111).as_deref(), Some("id2"));
+        assert_eq!(keys.get("b", 200), None);
+    }
+
+    #[test]
+    fn live_lists_unexpired_keys_once() {
+        let mut keys = KeyWindow::default();
+        keys.set_window(Duration::from_secs(10));
+        keys.record("a", "id1", 100);
+        keys.record("b", "id2", 105);
+        keys.record("a", "id3", 106);
+        assert_eq!(
+            keys.live(106),
+            vec![("b".to_string(), "id2".to_string(), 105), ("a".to_string(), "id3".to_string(), 106)]
+        );
+        assert_eq!(keys.live(116).len(), 1);
+    }
+
+    #[test]
+    fn count_is_capped() {
+        let mut keys = KeyWindow::default();
+        for n in 0..MAX_KEYS + 1 {
+            keys.record(&n.to_string(), "id", 0);
+        }
+        assert_eq!(keys.get("0", 0), None);
+        assert_eq!(keys.get("1", 0).as_deref(), Some("id"));
+    }
+}
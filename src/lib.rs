@@ -25,13 +25,38 @@
 //! # }
 //! ```
 
+pub mod aggregate;
+pub mod batch;
 pub mod bucket;
 pub mod error;
 pub mod id;
+mod idempotency;
+pub mod metrics;
+pub mod migrate;
+pub mod namespace;
+pub mod progress;
+mod rate;
+pub mod replica;
+pub mod schema;
 pub mod storage;
+mod sync;
+pub mod verify;
+pub mod view;
+pub mod warning;
 
+pub use aggregate::Aggregation;
+pub use batch::WriteBatch;
 pub use bucket::{FileBucket, FileMeta, FileRef};
 pub use error::{Error, Result};
+pub use metrics::{HistogramSnapshot, IndexStatus, MemoryUsage, Metrics};
+pub use migrate::MigrationReport;
+pub use namespace::Namespace;
+pub use progress::{OperationHandle, Progress};
+pub use replica::Replica;
+pub use schema::{FieldSpec, FieldType, Schema};
+pub use verify::{Issue, IssueKind, VerifyLevel, VerifyReport};
+pub use warning::{Warning, WarningKind};
+pub use view::SnapshotView;
 
 use parking_lot::{Mutex, RwLock};
 use serde_json::Value;
@@ -39,35 +64,79 @@ use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fs;
 use std::io::Write;
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{mpsc, Arc};
 use std::time::Duration;
 
 use id::{generate_unique, generate_unique_with_prefix};
 
+// ─── Tracing ────────────────────────────────────────────────────────
+
+/// Enter a `tracing` span until the end of the enclosing scope.
+/// Expands to nothing without the `tracing` feature.
+macro_rules! trace_span {
+    ($($arg:tt)*) => {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::debug_span!($($arg)*).entered();
+    };
+}
+
+/// Emit a `tracing` event. Expands to nothing without the `tracing` feature,
+/// so field expressions are never evaluated in default builds.
+macro_rules! trace_event {
+    ($($arg:tt)*) => {
+        #[cfg(feature = "tracing")]
+        tracing::debug!($($arg)*);
+    };
+}
+
+/// Start a stopwatch that only exists with the `tracing` feature.
+macro_rules! trace_start {
+    ($name:ident) => {
+        #[cfg(feature = "tracing")]
+        let $name = std::time::Instant::now();
+    };
+}
+
 // ─── Persistence Modes ──────────────────────────────────────────────
 
 /// When to persist data to disk.
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
 pub enum Persistence {
     /// Flush on explicit call or shutdown. Fastest, last flush only.
+    #[default]
     Lazy,
-    /// Flush every N seconds. Balanced.
+    /// fsync from a background thread every N (at least 1 ms). Writes
+    /// return before they are synced, so up to one interval of writes can
+    /// be lost.
     Scheduled(Duration),
     /// fsync after every write. Slowest, every write safe.
     Immediate,
+    /// Group commit: fsync from a background thread at most every N (at
+    /// least 1 ms), and return from each write once a sync covers it.
+    /// Concurrent writes share one fsync; each write waits up to N.
+    GroupCommit(Duration),
 }
 
-impl Default for Persistence {
-    fn default() -> Self {
-        Persistence::Lazy
-    }
+/// Per-call override of the persistence mode, for `insert_with` and
+/// `insert_batch_with`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Durability {
+    /// Return once the record is appended. It is synced whenever the
+    /// database's mode next syncs; under `GroupCommit` the call does not
+    /// wait for that sync.
+    Buffered,
+    /// fsync before returning, whatever the database's mode.
+    Synced,
 }
 
 // ─── Trash Mode ─────────────────────────────────────────────────────
 
 /// How to handle trashed documents/files.
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
 pub enum TrashMode {
     /// Never auto-delete (default).
+    #[default]
     Manual,
     /// Auto-purge after given duration.
     TTL(Duration),
@@ -75,10 +144,39 @@ pub enum TrashMode {
     Off,
 }
 
-impl Default for TrashMode {
-    fn default() -> Self {
-        TrashMode::Manual
+// ─── Write Limits ───────────────────────────────────────────────────
+
+/// Limits checked on every write. `None` means unlimited (the default).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Limits {
+    /// Maximum serialized size of a document, in bytes.
+    pub max_doc_bytes: Option<usize>,
+    /// Maximum length of a document `_id` (prefix included), in bytes.
+    pub max_id_len: Option<usize>,
+    /// Sustained documents written per second; bursts up to one second's
+    /// worth pass. Writes over the rate fail with `Error::Throttled`.
+    pub max_writes_per_sec: Option<u32>,
+    /// Maximum size of the log file, in bytes. Writes that would grow it
+    /// past this fail with `Error::LimitExceeded`; deletes are still
+    /// accepted, and `compact` reclaims the space.
+    pub max_log_bytes: Option<u64>,
+}
+
+/// Serialized size of a JSON value, without building the string.
+fn json_len(v: &Value) -> usize {
+    struct Counter(usize);
+    impl std::io::Write for Counter {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0 += buf.len();
+            Ok(buf.len())
+        }
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
     }
+    let mut counter = Counter(0);
+    serde_json::to_writer(&mut counter, v).expect("JSON values always serialize");
+    counter.0
 }
 
 // ─── Query Types ────────────────────────────────────────────────────
@@ -98,6 +196,73 @@ pub struct QueryOptions {
     pub sort_by: Option<(String, SortDir)>,
 }
 
+/// One group of `query_grouped` results: documents sharing a field value.
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct QueryGroup {
+    /// The shared value of the grouping field.
+    pub key: Value,
+    /// Up to `group_size` documents, in sort order.
+    pub docs: Vec<Value>,
+}
+
+/// How a query ran, returned by `query_explain`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Serialize)]
+pub struct QueryExplanation {
+    /// True if an index narrowed the candidates; false means every
+    /// document was evaluated.
+    pub indexed: bool,
+    /// Documents in the database when the query ran.
+    pub documents: usize,
+    /// Documents evaluated against the query.
+    pub scanned: usize,
+    /// Documents that matched, before offset and limit.
+    pub matched: usize,
+    /// Documents returned after sorting and paging.
+    pub returned: usize,
+    /// Microseconds spent picking index candidates.
+    pub plan_us: u64,
+    /// Microseconds spent evaluating candidates.
+    pub scan_us: u64,
+    /// Microseconds spent sorting and paging.
+    pub options_us: u64,
+}
+
+impl QueryExplanation {
+    /// Fraction of scanned documents that matched (0.0 if none were scanned).
+    pub fn selectivity(&self) -> f64 {
+        if self.scanned == 0 {
+            0.0
+        } else {
+            self.matched as f64 / self.scanned as f64
+        }
+    }
+}
+
+// ─── Change Feed ────────────────────────────────────────────────────
+
+/// A single change delivered to subscribers.
+///
+/// Document-level changes carry the full document state *after* the write,
+/// so delta patches (`set`, `remove`, `array_push`) surface as `Update`.
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Change {
+    Insert { id: String, doc: Value },
+    Update { id: String, doc: Value },
+    Delete { id: String },
+    Flush,
+    Compact,
+}
+
+/// A change plus its sequence number. Sequence numbers start at 1 for each
+/// opened `Database` and increase by one per change, in write order.
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct ChangeEvent {
+    pub seq: u64,
+    #[serde(flatten)]
+    pub change: Change,
+}
+
 // ─── Index Types ────────────────────────────────────────────────────
 
 /// Trait for secondary indexes.
@@ -105,6 +270,47 @@ trait Index: Send + Sync {
     fn insert(&mut self, value: &Value, id: &str);
     fn remove(&mut self, value: &Value, id: &str);
     fn get(&self, value: &Value) -> Vec<String>;
+    /// True when `id` is among `get(value)`, without collecting the IDs.
+    fn contains(&self, value: &Value, id: &str) -> bool;
+    /// IDs of every document whose value may equal `value` under query
+    /// semantics, or `None` if this index cannot answer that exactly
+    /// (extra IDs are fine, missing ones are not).
+    fn lookup(&self, value: &Value) -> Option<Vec<String>>;
+    /// Total number of (value, id) entries held.
+    fn len(&self) -> usize;
+    /// Estimated heap bytes held (see `MemoryUsage`).
+    fn heap_bytes(&self) -> usize;
+    /// `"hash"`, `"btree"` or `"text"`, for `IndexStatus`.
+    fn kind(&self) -> &'static str;
+    /// IDs of every document whose field contains all tokens of `query`
+    /// (see `$text`), or `None` if this is not a text index.
+    fn text_lookup(&self, _query: &str) -> Option<Vec<String>> {
+        None
+    }
+}
+
+/// Estimated heap bytes of one index map entry: the value key and its ID set.
+fn index_entry_bytes(key: &str, ids: &HashSet<String>) -> usize {
+    string_bytes(key) + ids.iter().map(|id| string_bytes(id)).sum::<usize>()
+}
+
+/// Estimated heap bytes of an owned string, header included.
+fn string_bytes(s: &str) -> usize {
+    std::mem::size_of::<String>() + s.len()
+}
+
+/// Estimated heap bytes below a JSON value (its own node not included).
+fn value_heap_bytes(v: &Value) -> usize {
+    const NODE: usize = std::mem::size_of::<Value>();
+    match v {
+        Value::String(s) => s.len(),
+        Value::Array(items) => items.iter().map(|x| NODE + value_heap_bytes(x)).sum(),
+        Value::Object(map) => map
+            .iter()
+            .map(|(k, x)| string_bytes(k) + NODE + value_heap_bytes(x))
+            .sum(),
+        _ => 0,
+    }
 }
 
 /// Hash index for O(1) equality lookups.
@@ -151,6 +357,30 @@ impl Index for HashIndex {
         let key = Self::value_key(value);
         self.map.get(&key).map(|s| s.iter().cloned().collect()).unwrap_or_default()
     }
+
+    fn contains(&self, value: &Value, id: &str) -> bool {
+        self.map.get(&Self::value_key(value)).is_some_and(|s| s.contains(id))
+    }
+
+    fn lookup(&self, value: &Value) -> Option<Vec<String>> {
+        // Number keys are textual here, so 1 and 1.0 land in different buckets
+        match value {
+            Value::Number(_) | Value::Array(_) | Value::Object(_) => None,
+            _ => Some(self.get(value)),
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.map.values().map(HashSet::len).sum()
+    }
+
+    fn heap_bytes(&self) -> usize {
+        self.map.iter().map(|(k, ids)| index_entry_bytes(k, ids)).sum()
+    }
+
+    fn kind(&self) -> &'static str {
+        "hash"
+    }
 }
 
 /// BTree index for O(log n) lookups + range queries.
@@ -203,6 +433,221 @@ impl Index for BTreeIndex {
         let key = Self::value_key(value);
         self.map.get(&key).map(|s| s.iter().cloned().collect()).unwrap_or_default()
     }
+
+    fn contains(&self, value: &Value, id: &str) -> bool {
+        self.map.get(&Self::value_key(value)).is_some_and(|s| s.contains(id))
+    }
+
+    fn lookup(&self, value: &Value) -> Option<Vec<String>> {
+        match value {
+            Value::Array(_) | Value::Object(_) => None,
+            _ => Some(self.get(value)),
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.map.values().map(HashSet::len).sum()
+    }
+
+    fn heap_bytes(&self) -> usize {
+        self.map.iter().map(|(k, ids)| index_entry_bytes(k, ids)).sum()
+    }
+
+    fn kind(&self) -> &'static str {
+        "btree"
+    }
+}
+
+/// Split text into lowercase alphanumeric words, for `$text` and `TextIndex`.
+/// Arrays contribute the words of their string elements; other values none.
+fn text_tokens(v: &Value, out: &mut HashSet<String>) {
+    match v {
+        Value::String(s) => out.extend(
+            s.split(|c: char| !c.is_alphanumeric())
+                .filter(|w| !w.is_empty())
+                .map(str::to_lowercase),
+        ),
+        Value::Array(items) => items.iter().for_each(|x| text_tokens(x, out)),
+        _ => {}
+    }
+}
+
+/// Inverted index from words to IDs, for `$text` queries.
+///
+/// Every document with the field is tracked, even one without words, so
+/// `len` is one entry per document like the other indexes.
+struct TextIndex {
+    map: HashMap<String, HashSet<String>>,
+    ids: HashSet<String>,
+}
+
+impl TextIndex {
+    fn new() -> Self {
+        TextIndex {
+            map: HashMap::new(),
+            ids: HashSet::new(),
+        }
+    }
+
+    fn tokens(v: &Value) -> HashSet<String> {
+        let mut tokens = HashSet::new();
+        text_tokens(v, &mut tokens);
+        tokens
+    }
+}
+
+impl Index for TextIndex {
+    fn insert(&mut self, value: &Value, id: &str) {
+        for token in Self::tokens(value) {
+            self.map.entry(token).or_default().insert(id.to_string());
+        }
+        self.ids.insert(id.to_string());
+    }
+
+    fn remove(&mut self, value: &Value, id: &str) {
+        for token in Self::tokens(value) {
+            if let Some(set) = self.map.get_mut(&token) {
+                set.remove(id);
+                if set.is_empty() {
+                    self.map.remove(&token);
+                }
+            }
+        }
+        self.ids.remove(id);
+    }
+
+    /// Documents containing every word of `value`; all of them if it has none.
+    fn get(&self, value: &Value) -> Vec<String> {
+        let tokens = Self::tokens(value);
+        let mut sets: Vec<&HashSet<String>> = Vec::with_capacity(tokens.len());
+        for token in &tokens {
+            match self.map.get(token) {
+                Some(set) => sets.push(set),
+                None => return Vec::new(),
+            }
+        }
+        sets.sort_by_key(|set| set.len());
+        let Some((smallest, rest)) = sets.split_first() else {
+            return self.ids.iter().cloned().collect();
+        };
+        smallest
+            .iter()
+            .filter(|id| rest.iter().all(|set| set.contains(*id)))
+            .cloned()
+            .collect()
+    }
+
+    fn contains(&self, value: &Value, id: &str) -> bool {
+        self.ids.contains(id)
+            && Self::tokens(value)
+                .iter()
+                .all(|token| self.map.get(token).is_some_and(|s| s.contains(id)))
+    }
+
+    fn lookup(&self, _value: &Value) -> Option<Vec<String>> {
+        // Words are not values; equality is left to other indexes or a scan
+        None
+    }
+
+    fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    fn heap_bytes(&self) -> usize {
+        let words: usize = self.map.iter().map(|(k, ids)| index_entry_bytes(k, ids)).sum();
+        words + self.ids.iter().map(|id| string_bytes(id)).sum::<usize>()
+    }
+
+    fn kind(&self) -> &'static str {
+        "text"
+    }
+
+    fn text_lookup(&self, query: &str) -> Option<Vec<String>> {
+        Some(self.get(&Value::from(query)))
+    }
+}
+
+// ─── Query Planner ──────────────────────────────────────────────────
+
+/// Narrow a query to candidate IDs using secondary indexes.
+///
+/// Returns `None` when no index applies and a full scan is needed. The
+/// candidates are a superset of the matches: callers still evaluate the
+/// whole AST on each. Mirrors the precedence in `query_matches`.
+fn index_candidates(indexes: &HashMap<String, Box<dyn Index>>, ast: &Value) -> Option<HashSet<String>> {
+    match ast {
+        Value::Object(map) => {
+            if let Some(and_expr) = map.get("$and") {
+                return intersect_candidates(indexes, and_expr.as_array()?);
+            }
+            if let Some(or_expr) = map.get("$or") {
+                // Every branch must be narrowed, or some match could be missed
+                let mut all = HashSet::new();
+                for cond in or_expr.as_array()? {
+                    all.extend(index_candidates(indexes, cond)?);
+                }
+                return Some(all);
+            }
+            if map.contains_key("$not") {
+                return None;
+            }
+
+            // Most selective indexed field wins; the others are checked by evaluation
+            map.iter()
+                .filter_map(|(field, condition)| field_candidates(indexes, field, condition))
+                .min_by_key(|ids| ids.len())
+        }
+        Value::Array(conditions) => intersect_candidates(indexes, conditions),
+        _ => None,
+    }
+}
+
+/// Smallest candidate set among the clauses of an implicit or explicit `$and`.
+fn intersect_candidates(indexes: &HashMap<String, Box<dyn Index>>, clauses: &[Value]) -> Option<HashSet<String>> {
+    clauses
+        .iter()
+        .filter_map(|cond| index_candidates(indexes, cond))
+        .min_by_key(|ids| ids.len())
+}
+
+/// Candidates for one `field: condition` pair from an index on that field,
+/// or straight from the condition for `_id`.
+fn field_candidates(
+    indexes: &HashMap<String, Box<dyn Index>>,
+    field: &str,
+    condition: &Value,
+) -> Option<HashSet<String>> {
+    // Indexes are keyed on top-level fields; dotted paths resolve into nested values
+    if field.contains('.') {
+        return None;
+    }
+    let index = indexes.get(field);
+    if index.is_none() && field != "_id" {
+        return None;
+    }
+
+    let values: Vec<&Value> = match condition {
+        Value::Object(op_map) => {
+            if let Some(eq) = op_map.get("$eq") {
+                vec![eq]
+            } else if let Some(query) = op_map.get("$text") {
+                return Some(index?.text_lookup(query.as_str()?)?.into_iter().collect());
+            } else {
+                op_map.get("$in")?.as_array()?.iter().collect()
+            }
+        }
+        other => vec![other],
+    };
+
+    // Documents are keyed by `_id`, so ID conditions need no index
+    let Some(index) = index else {
+        return Some(values.iter().filter_map(|v| v.as_str()).map(str::to_string).collect());
+    };
+    let mut ids = HashSet::new();
+    for v in values {
+        ids.extend(index.lookup(v)?);
+    }
+    Some(ids)
 }
 
 // ─── Query Evaluator ────────────────────────────────────────────────
@@ -232,15 +677,7 @@ fn query_matches(doc: &Value, ast: &Value) -> bool {
             map.iter().all(|(field, condition)| {
                 let field_val = field_get(doc, field);
                 match field_val {
-                    None => {
-                        // $exists: false should match when field is missing
-                        if let Value::Object(op_map) = condition {
-                            if let Some(exists_val) = op_map.get("$exists") {
-                                return !exists_val.as_bool().unwrap_or(true);
-                            }
-                        }
-                        false
-                    }
+                    None => evaluate_missing(condition),
                     Some(val) => evaluate_condition(val, condition),
                 }
             })
@@ -253,12 +690,16 @@ fn query_matches(doc: &Value, ast: &Value) -> bool {
     }
 }
 
-/// Get a field value from a document. Supports dot notation.
+/// Get a field value from a document. Supports dot notation; a numeric
+/// segment indexes into an array (`items.0.name`).
 fn field_get<'a>(doc: &'a Value, field: &str) -> Option<&'a Value> {
     let parts: Vec<&str> = field.split('.').collect();
     let mut current = doc;
     for part in parts {
-        current = current.get(part)?;
+        current = match current {
+            Value::Array(arr) => arr.get(part.parse::<usize>().ok()?)?,
+            _ => current.get(part)?,
+        };
     }
     Some(current)
 }
@@ -267,6 +708,12 @@ fn field_get<'a>(doc: &'a Value, field: &str) -> Option<&'a Value> {
 fn evaluate_condition(field_val: &Value, condition: &Value) -> bool {
     match condition {
         Value::Object(op_map) => {
+            // Modifier for the string operators below
+            let ci = op_map
+                .get("$caseInsensitive")
+                .and_then(|v| v.as_bool())
+                .unwrap_or(false);
+
             // Operator-based: {"$eq": "value", "$gt": 10, ...}
             op_map.iter().all(|(op, operand)| match op.as_str() {
                 "$eq" => values_equal(field_val, operand),
@@ -284,6 +731,40 @@ fn evaluate_condition(field_val: &Value, condition: &Value) -> bool {
                     .map(|arr| !arr.iter().any(|v| values_equal(field_val, v)))
                     .unwrap_or(true),
                 "$exists" => operand.as_bool().unwrap_or(true),
+                "$contains" => field_val
+                    .as_array()
+                    .is_some_and(|arr| arr.iter().any(|v| values_equal(v, operand))),
+                "$containsAny" => match (field_val.as_array(), operand.as_array()) {
+                    (Some(arr), Some(wanted)) => wanted
+                        .iter()
+                        .any(|w| arr.iter().any(|v| values_equal(v, w))),
+                    _ => false,
+                },
+                "$containsAll" => match (field_val.as_array(), operand.as_array()) {
+                    (Some(arr), Some(wanted)) => wanted
+                        .iter()
+                        .all(|w| arr.iter().any(|v| values_equal(v, w))),
+                    _ => false,
+                },
+                // Length of an array; the operand is a number or an operator object
+                "$size" => field_val
+                    .as_array()
+                    .is_some_and(|arr| evaluate_condition(&Value::from(arr.len()), operand)),
+                "$startsWith" => string_op(field_val, operand, ci, |s, p| s.starts_with(p)),
+                "$endsWith" => string_op(field_val, operand, ci, |s, p| s.ends_with(p)),
+                "$regex" => regex_matches(field_val, operand, ci),
+                "$text" => operand.as_str().is_some_and(|query| {
+                    let mut have = HashSet::new();
+                    text_tokens(field_val, &mut have);
+                    let mut want = HashSet::new();
+                    text_tokens(&Value::from(query), &mut want);
+                    want.is_subset(&have)
+                }),
+                "$caseInsensitive" => true,
+                "$geoRadius" => geo_radius_matches(field_val, operand),
+                "$geoBox" => geo_box_matches(field_val, operand),
+                "$isNull" => field_val.is_null() == operand.as_bool().unwrap_or(true),
+                "$not" => !evaluate_condition(field_val, operand),
                 _ => true, // Unknown operator = no filter
             })
         }
@@ -292,6 +773,123 @@ fn evaluate_condition(field_val: &Value, condition: &Value) -> bool {
     }
 }
 
+/// Apply a string predicate to a string field and string operand.
+/// Non-string fields or operands never match.
+fn string_op(field_val: &Value, operand: &Value, ci: bool, pred: fn(&str, &str) -> bool) -> bool {
+    match (field_val.as_str(), operand.as_str()) {
+        (Some(s), Some(p)) if ci => pred(&s.to_lowercase(), &p.to_lowercase()),
+        (Some(s), Some(p)) => pred(s, p),
+        _ => false,
+    }
+}
+
+/// `$regex`: match a string field against a pattern. Invalid patterns match
+/// nothing. Without the `regex` feature the operator matches nothing.
+#[cfg(feature = "regex")]
+fn regex_matches(field_val: &Value, operand: &Value, ci: bool) -> bool {
+    use std::cell::RefCell;
+
+    thread_local! {
+        // Compiled patterns, so a scan compiles each pattern once per thread
+        static CACHE: RefCell<HashMap<(String, bool), Option<regex::Regex>>> =
+            RefCell::new(HashMap::new());
+    }
+
+    let (Some(s), Some(pattern)) = (field_val.as_str(), operand.as_str()) else {
+        return false;
+    };
+    CACHE.with(|cache| {
+        let mut cache = cache.borrow_mut();
+        if cache.len() >= 64 {
+            cache.clear();
+        }
+        cache
+            .entry((pattern.to_string(), ci))
+            .or_insert_with(|| {
+                regex::RegexBuilder::new(pattern)
+                    .case_insensitive(ci)
+                    .build()
+                    .ok()
+            })
+            .as_ref()
+            .is_some_and(|re| re.is_match(s))
+    })
+}
+
+#[cfg(not(feature = "regex"))]
+fn regex_matches(_field_val: &Value, _operand: &Value, _ci: bool) -> bool {
+    false
+}
+
+/// Mean Earth radius in meters (IUGG).
+const EARTH_RADIUS_M: f64 = 6_371_008.8;
+
+/// Read a `[lat, lon]` pair from a field value.
+fn geo_point(val: &Value) -> Option<(f64, f64)> {
+    match val.as_array()?.as_slice() {
+        [lat, lon] => Some((lat.as_f64()?, lon.as_f64()?)),
+        _ => None,
+    }
+}
+
+/// Great-circle distance in meters between two points (haversine).
+fn haversine_m((lat1, lon1): (f64, f64), (lat2, lon2): (f64, f64)) -> f64 {
+    let (phi1, phi2) = (lat1.to_radians(), lat2.to_radians());
+    let dphi = (lat2 - lat1).to_radians();
+    let dlambda = (lon2 - lon1).to_radians();
+    let a = (dphi / 2.0).sin().powi(2) + phi1.cos() * phi2.cos() * (dlambda / 2.0).sin().powi(2);
+    2.0 * EARTH_RADIUS_M * a.sqrt().asin()
+}
+
+/// `$geoRadius: {"lat", "lon", "meters"}` — point within `meters` of the center.
+fn geo_radius_matches(field_val: &Value, operand: &Value) -> bool {
+    let center = (
+        operand.get("lat").and_then(|v| v.as_f64()),
+        operand.get("lon").and_then(|v| v.as_f64()),
+        operand.get("meters").and_then(|v| v.as_f64()),
+    );
+    match (geo_point(field_val), center) {
+        (Some(point), (Some(lat), Some(lon), Some(meters))) => haversine_m(point, (lat, lon)) <= meters,
+        _ => false,
+    }
+}
+
+/// `$geoBox: {"minLat", "minLon", "maxLat", "maxLon"}` — point inside the box.
+/// A box with `minLon > maxLon` wraps across the antimeridian.
+fn geo_box_matches(field_val: &Value, operand: &Value) -> bool {
+    let bound = |key: &str| operand.get(key).and_then(|v| v.as_f64());
+    let (Some((lat, lon)), Some(min_lat), Some(min_lon), Some(max_lat), Some(max_lon)) = (
+        geo_point(field_val),
+        bound("minLat"),
+        bound("minLon"),
+        bound("maxLat"),
+        bound("maxLon"),
+    ) else {
+        return false;
+    };
+    let lon_ok = if min_lon <= max_lon {
+        (min_lon..=max_lon).contains(&lon)
+    } else {
+        lon >= min_lon || lon <= max_lon
+    };
+    (min_lat..=max_lat).contains(&lat) && lon_ok
+}
+
+/// Evaluate a condition against a field the document does not have.
+///
+/// A missing field satisfies `$exists: false` and `$isNull: false`, and a
+/// `$not` whose inner condition fails. Every other operator fails.
+fn evaluate_missing(condition: &Value) -> bool {
+    match condition {
+        Value::Object(op_map) => op_map.iter().all(|(op, operand)| match op.as_str() {
+            "$exists" | "$isNull" => !operand.as_bool().unwrap_or(true),
+            "$not" => !evaluate_missing(operand),
+            _ => false,
+        }),
+        _ => false,
+    }
+}
+
 /// Compare two JSON values for equality.
 fn values_equal(a: &Value, b: &Value) -> bool {
     match (a, b) {
@@ -317,6 +915,44 @@ fn value_cmp(a: &Value, b: &Value) -> std::cmp::Ordering {
     }
 }
 
+/// Grouping key for a value. Numbers are keyed by value so 1 and 1.0
+/// share a group, as in `values_equal`.
+fn value_slot(v: &Value) -> String {
+    match v.as_f64() {
+        Some(n) => format!("n:{}", n),
+        None => v.to_string(),
+    }
+}
+
+/// Apply sort, offset and limit from `QueryOptions` to a result set.
+fn apply_query_options(mut results: Vec<Value>, opts: &QueryOptions) -> Vec<Value> {
+    // Sort
+    if let Some((ref field, dir)) = opts.sort_by {
+        results.sort_by(|a, b| {
+            let av = a.get(field).unwrap_or(&Value::Null);
+            let bv = b.get(field).unwrap_or(&Value::Null);
+            let cmp = value_cmp(av, bv);
+            match dir {
+                SortDir::Asc => cmp,
+                SortDir::Desc => cmp.reverse(),
+            }
+        });
+    }
+
+    // Offset
+    let offset = opts.offset.unwrap_or(0);
+    if offset > 0 {
+        results = results.into_iter().skip(offset).collect();
+    }
+
+    // Limit
+    if let Some(limit) = opts.limit {
+        results.truncate(limit);
+    }
+
+    results
+}
+
 // ─── Path-based Mutation Helpers ─────────────────────────────────────
 
 fn apply_path_set(doc: &mut Value, path: &str, value: Value) {
@@ -389,6 +1025,169 @@ fn walk_and_remove(current: &mut Value, segments: &[&str], depth: usize) {
     }
 }
 
+// ─── Document Versions ──────────────────────────────────────────────
+
+/// Reserved field holding a document's version. Set to 1 on insert and
+/// bumped by every write, including patches, so it survives replay and
+/// compaction without a separate record.
+pub const VERSION_FIELD: &str = "_version";
+
+/// Version of a document (0 if it was written before versions existed).
+fn doc_version(doc: &Value) -> u64 {
+    doc.get(VERSION_FIELD).and_then(Value::as_u64).unwrap_or(0)
+}
+
+fn set_version(doc: &mut Value, version: u64) {
+    if let Some(obj) = doc.as_object_mut() {
+        obj.insert(VERSION_FIELD.to_string(), Value::from(version));
+    }
+}
+
+fn bump_version(doc: &mut Value) {
+    set_version(doc, doc_version(doc) + 1);
+}
+
+/// True when two documents differ at most in `_version`.
+fn same_content(a: &Value, b: &Value) -> bool {
+    match (a.as_object(), b.as_object()) {
+        (Some(a), Some(b)) => {
+            let fields = |m: &serde_json::Map<String, Value>| m.len() - m.contains_key(VERSION_FIELD) as usize;
+            fields(a) == fields(b)
+                && a.iter().all(|(k, v)| k == VERSION_FIELD || b.get(k) == Some(v))
+        }
+        _ => a == b,
+    }
+}
+
+/// Fail with `VersionConflict` unless `expected` is absent or matches.
+fn check_version(id: &str, expected: Option<u64>, actual: u64) -> Result<()> {
+    match expected {
+        Some(expected) if expected != actual => Err(Error::VersionConflict {
+            id: id.to_string(),
+            expected,
+            actual,
+        }),
+        _ => Ok(()),
+    }
+}
+
+// ─── Log Replay ─────────────────────────────────────────────────────
+
+/// What `Database::open_with_recovery` set aside.
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize)]
+pub struct RecoveryReport {
+    /// Log lines that could not be read and were moved to quarantine.
+    pub quarantined: Vec<storage::RejectedLine>,
+    /// File the lines were appended to (`None` if nothing was quarantined).
+    pub quarantine_path: Option<PathBuf>,
+    /// Temp files from an interrupted compaction, trash rewrite or
+    /// `set_metadata` that were deleted. Every open removes these.
+    pub removed_temp_files: Vec<PathBuf>,
+    /// Bytes freed by removing `removed_temp_files`.
+    pub reclaimed_bytes: u64,
+}
+
+impl RecoveryReport {
+    /// True when the log was read in full.
+    pub fn is_clean(&self) -> bool {
+        self.quarantined.is_empty()
+    }
+}
+
+/// A copy of `record` tagged with the idempotency key that wrote it.
+fn with_idem_key(record: &Value, key: &str, at: u64) -> Value {
+    let mut record = record.clone();
+    if let Some(obj) = record.as_object_mut() {
+        obj.insert("_idem".to_string(), serde_json::json!({"key": key, "at": at}));
+    }
+    record
+}
+
+/// Apply one JSONL log record to the in-memory state. Last write wins.
+fn replay_record(docs: &mut HashMap<String, Value>, deleted: &mut HashSet<String>, mut record: Value) {
+    // Batch records carry no `_id`: they wrap whole documents written atomically
+    if record.get("_op").and_then(|v| v.as_str()) == Some("batch") {
+        if let Value::Array(batch) = record["docs"].take() {
+            for doc in batch {
+                replay_record(docs, deleted, doc);
+            }
+        }
+        return;
+    }
+
+    if let Some(id) = record.get("_id").and_then(|v| v.as_str()) {
+        if record.get("_deleted").is_some() {
+            // Tombstone entry
+            deleted.insert(id.to_string());
+            docs.remove(id);
+        } else if let Some(op) = record.get("_op").and_then(|v| v.as_str()) {
+            match op {
+                "array_push" => {
+                    if let Some(field) = record.get("field").and_then(|v| v.as_str()) {
+                        if let Some(value) = record.get("value") {
+                            if let Some(existing) = docs.get_mut(id) {
+                                if let Some(obj) = existing.as_object_mut() {
+                                    if let Some(arr) = obj.get_mut(field).and_then(|v| v.as_array_mut()) {
+                                        arr.push(value.clone());
+                                    } else {
+                                        obj.insert(field.to_string(), serde_json::json!([value.clone()]));
+                                    }
+                                }
+                                bump_version(existing);
+                            }
+                        }
+                    }
+                }
+                "set" => {
+                    if let Some(path) = record.get("path").and_then(|v| v.as_str()) {
+                        if let Some(value) = record.get("value") {
+                            if let Some(existing) = docs.get_mut(id) {
+                                apply_path_set(existing, path, value.clone());
+                                bump_version(existing);
+                            }
+                        }
+                    }
+                }
+                "remove" => {
+                    if let Some(path) = record.get("path").and_then(|v| v.as_str()) {
+                        if let Some(existing) = docs.get_mut(id) {
+                            apply_path_remove(existing, path);
+                            bump_version(existing);
+                        }
+                    }
+                }
+                _ => {}
+            }
+        } else {
+            let id = id.to_string();
+            deleted.remove(&id);
+            // The idempotency key belongs to the write, not the document
+            if let Some(obj) = record.as_object_mut() {
+                obj.remove("_idem");
+            }
+            docs.insert(id, record);
+        }
+    }
+}
+
+/// Rebuild the state after the first `seq` records of the log at `path`.
+fn replay_prefix(path: &Path, seq: u64) -> Result<(HashMap<String, Value>, HashSet<String>)> {
+    let records = storage::read_all(path)?;
+    if seq > records.len() as u64 {
+        return Err(Error::invalid_arg(format!(
+            "seq {} is past the end of the log ({} records)",
+            seq,
+            records.len()
+        )));
+    }
+    let mut docs = HashMap::new();
+    let mut deleted = HashSet::new();
+    for record in records.into_iter().take(seq as usize) {
+        replay_record(&mut docs, &mut deleted, record);
+    }
+    Ok((docs, deleted))
+}
+
 // ─── Database ───────────────────────────────────────────────────────
 
 /// The main nDB database.
@@ -410,8 +1209,10 @@ pub struct Database {
     indexes: RwLock<HashMap<String, Box<dyn Index>>>,
     /// Single-writer mutex.
     writer: Mutex<()>,
-    /// Persistence mode.
-    persistence: Persistence,
+    /// Serializes compactions, which hold `writer` only at the start and end.
+    compacting: Mutex<()>,
+    /// Persistence mode. Changed under the writer lock by `set_persistence`.
+    persistence: RwLock<Persistence>,
     /// Trash mode.
     trash_mode: TrashMode,
     /// Auto-purge TTL duration.
@@ -423,16 +1224,107 @@ pub struct Database {
     /// Background thread handle for TTL purging.
     ttl_thread: Mutex<Option<std::thread::JoinHandle<()>>>,
     /// Append-only file handle (held open for writes).
-    file_handle: Mutex<Option<fs::File>>,
+    file_handle: Arc<Mutex<Option<fs::File>>>,
+    /// Write/sync sequence numbers for the background sync thread.
+    syncer: Arc<sync::Syncer>,
+    /// Channel sender to terminate the sync thread on Drop.
+    sync_tx: Mutex<Option<mpsc::Sender<()>>>,
+    /// Background thread for `Scheduled` and `GroupCommit` persistence.
+    sync_thread: Mutex<Option<std::thread::JoinHandle<()>>>,
+    /// Optional typed field map checked on every write.
+    schema: Option<Schema>,
+    /// User metadata, the `"metadata"` object of `meta.json`.
+    metadata: RwLock<serde_json::Map<String, Value>>,
+    /// Limits checked on every write.
+    limits: Limits,
+    /// Token bucket for `limits.max_writes_per_sec`.
+    write_bucket: Option<rate::TokenBucket>,
+    /// Change feed subscribers. Disconnected receivers are pruned on send.
+    subscribers: Mutex<Vec<mpsc::Sender<ChangeEvent>>>,
+    /// Sequence number of the last change published.
+    change_seq: AtomicU64,
+    /// Operation counters and latency histograms.
+    metrics: Arc<metrics::Recorder>,
+    /// Best-effort failures, drained by `take_warnings`.
+    warnings: Arc<warning::Warnings>,
+    /// Keys of recent `insert_idempotent` / `delete_idempotent` calls.
+    idempotency: Mutex<idempotency::KeyWindow>,
 }
 
 impl Database {
     /// Open or create a database at the given path.
     ///
     /// If the file exists, loads all documents into memory.
-    /// If not, creates a new file with _meta header.
+    /// If not, creates a new file with _meta header. A readable log from an
+    /// older format version is upgraded first, as by `open_with_migrations`.
     pub fn open(path: impl AsRef<Path>) -> Result<Self> {
-        let path = path.as_ref().to_path_buf();
+        Self::load(path.as_ref()).map(|(db, _)| db)
+    }
+
+    /// Open like `open`, moving unreadable log lines to quarantine.
+    ///
+    /// Plain `open` skips corrupt lines, and the next compaction drops them for
+    /// good. Here they are first appended to `_quarantine/<log name>` next to
+    /// the log, with their line number and parse error. The log is then
+    /// compacted so it holds only the readable data and the same lines are not
+    /// reported again. The database opens read-write either way.
+    pub fn open_with_recovery(path: impl AsRef<Path>) -> Result<(Self, RecoveryReport)> {
+        let (db, mut report) = Self::load(path.as_ref())?;
+        if report.quarantined.is_empty() {
+            return Ok((db, report));
+        }
+
+        let filename = db.path.file_name().unwrap_or(std::ffi::OsStr::new("data.jsonl"));
+        let quarantine_path = db.base_dir.join("_quarantine").join(filename);
+        storage::append_quarantine(&quarantine_path, &db.path, &report.quarantined)?;
+        db.compact()?;
+
+        report.quarantine_path = Some(quarantine_path);
+        Ok((db, report))
+    }
+
+    /// Open like `open`, but refuse a log with any unreadable line.
+    ///
+    /// Fails with `Error::Corruption` naming the first bad line, its byte
+    /// offset and the parse error, and leaves the log as it is. A torn last
+    /// line from a crash counts too: inspect the log, then repair it with
+    /// `open_with_recovery`.
+    pub fn open_strict(path: impl AsRef<Path>) -> Result<Self> {
+        let (db, report) = Self::load(path.as_ref())?;
+        match report.quarantined.first() {
+            None => Ok(db),
+            Some(reject) => Err(Error::corruption(
+                &db.path,
+                format!(
+                    "unreadable line {} at byte {} ({} in total): {}",
+                    reject.line,
+                    reject.offset,
+                    report.quarantined.len(),
+                    reject.error
+                ),
+            )),
+        }
+    }
+
+    /// Upgrade the log at `path` to the current format version, then open it.
+    ///
+    /// Runs the steps in `migrate::MIGRATIONS` that apply, keeping the original
+    /// log as `<path>.v<old>.bak`. Use `migrate::upgrade(path, true)` for a
+    /// dry run. Plain `open` upgrades too but does not report it, and leaves
+    /// a log with unreadable lines at its old version. Both refuse newer logs.
+    pub fn open_with_migrations(path: impl AsRef<Path>) -> Result<(Self, MigrationReport)> {
+        let path = path.as_ref();
+        if !path.exists() {
+            storage::init_file(path)?;
+        }
+        let report = migrate::upgrade(path, false)?;
+        Ok((Self::open(path)?, report))
+    }
+
+    /// Read the log into a new database. The report lists unreadable lines
+    /// (not yet quarantined) and the temp files that were removed.
+    fn load(path: &Path) -> Result<(Self, RecoveryReport)> {
+        let path = path.to_path_buf();
         let base_dir = path.parent().unwrap_or(Path::new(".")).to_path_buf();
 
         // Ensure file exists
@@ -440,61 +1332,46 @@ impl Database {
             storage::init_file(&path)?;
         }
 
+        // Refuse logs from a newer format; an unreadable header is left to crash recovery
+        if let Ok(version) = migrate::log_version(&path) {
+            migrate::check_supported(&path, version)?;
+            // Bring older logs up first so this build never appends records
+            // an older build would misread. A log with unreadable lines is
+            // left as is; recovery rewrites it with a current header.
+            if version < storage::STORAGE_VERSION {
+                match migrate::upgrade(&path, false) {
+                    Ok(_) | Err(Error::Corruption { .. }) => {}
+                    Err(e) => return Err(e),
+                }
+            }
+        }
+
         // Load all documents from file
-        let raw_docs = storage::read_all(&path)?;
+        let (raw_docs, rejects) = storage::read_all_with_rejects(&path)?;
+        let metrics = metrics::Recorder::default();
+        metrics.log_records.store(raw_docs.len() as u64, Ordering::Relaxed);
 
         // Build in-memory state: last write wins
         let mut docs: HashMap<String, Value> = HashMap::new();
         let mut deleted = HashSet::new();
+        let mut keys = idempotency::KeyWindow::default();
 
-        for doc in raw_docs {
-            if let Some(id) = doc.get("_id").and_then(|v| v.as_str()) {
-                if doc.get("_deleted").is_some() {
-                    // Tombstone entry
-                    deleted.insert(id.to_string());
-                    docs.remove(id);
-                } else if let Some(op) = doc.get("_op").and_then(|v| v.as_str()) {
-                    match op {
-                        "array_push" => {
-                            if let Some(field) = doc.get("field").and_then(|v| v.as_str()) {
-                                if let Some(value) = doc.get("value") {
-                                    if let Some(existing) = docs.get_mut(id) {
-                                        if let Some(obj) = existing.as_object_mut() {
-                                            if let Some(arr) = obj.get_mut(field).and_then(|v| v.as_array_mut()) {
-                                                arr.push(value.clone());
-                                            } else {
-                                                obj.insert(field.to_string(), serde_json::json!([value.clone()]));
-                                            }
-                                        }
-                                    }
-                                }
-                            }
-                        }
-                        "set" => {
-                            if let Some(path) = doc.get("path").and_then(|v| v.as_str()) {
-                                if let Some(value) = doc.get("value") {
-                                    if let Some(existing) = docs.get_mut(id) {
-                                        apply_path_set(existing, path, value.clone());
-                                    }
-                                }
-                            }
-                        }
-                        "remove" => {
-                            if let Some(path) = doc.get("path").and_then(|v| v.as_str()) {
-                                if let Some(existing) = docs.get_mut(id) {
-                                    apply_path_remove(existing, path);
-                                }
-                            }
-                        }
-                        _ => {}
-                    }
-                } else {
-                    deleted.remove(id);
-                    docs.insert(id.to_string(), doc);
+        for record in raw_docs {
+            if let (Some(id), Some(idem)) = (record.get("_id").and_then(Value::as_str), record.get("_idem")) {
+                if let (Some(key), Some(at)) = (idem["key"].as_str(), idem["at"].as_u64()) {
+                    keys.record(key, id, at);
                 }
             }
+            replay_record(&mut docs, &mut deleted, record);
         }
 
+        // Existing documents are not re-validated; the schema applies to new writes
+        let schema = Schema::load(&base_dir)?;
+        let metadata = match storage::read_meta(&base_dir)?.as_ref().and_then(|m| m.get("metadata")) {
+            Some(Value::Object(map)) => map.clone(),
+            _ => serde_json::Map::new(),
+        };
+
         // Initialize file reference counter
         let mut file_refs: HashMap<String, usize> = HashMap::new();
         for doc in docs.values() {
@@ -505,7 +1382,7 @@ impl Database {
             }
         }
 
-        Ok(Database {
+        let db = Database {
             path,
             base_dir,
             docs: RwLock::new(docs),
@@ -513,14 +1390,53 @@ impl Database {
             file_refs: RwLock::new(file_refs),
             indexes: RwLock::new(HashMap::new()),
             writer: Mutex::new(()),
-            persistence: Persistence::Lazy,
+            compacting: Mutex::new(()),
+            persistence: RwLock::new(Persistence::Lazy),
             trash_mode: TrashMode::Manual,
             trash_ttl: None,
             trash_purge_interval: None,
             ttl_tx: Mutex::new(None),
             ttl_thread: Mutex::new(None),
-            file_handle: Mutex::new(None),
-        })
+            file_handle: Arc::new(Mutex::new(None)),
+            syncer: Arc::new(sync::Syncer::default()),
+            sync_tx: Mutex::new(None),
+            sync_thread: Mutex::new(None),
+            schema,
+            metadata: RwLock::new(metadata),
+            limits: Limits::default(),
+            write_bucket: None,
+            subscribers: Mutex::new(Vec::new()),
+            change_seq: AtomicU64::new(0),
+            metrics: Arc::new(metrics),
+            warnings: Arc::default(),
+            idempotency: Mutex::new(keys),
+        };
+        let mut report = RecoveryReport {
+            quarantined: rejects,
+            ..RecoveryReport::default()
+        };
+        db.remove_temp_files(&mut report)?;
+        Ok((db, report))
+    }
+
+    /// Delete temp files a crash left next to the log, trash and
+    /// `meta.json`. Each would only have been renamed into place after a
+    /// full sync, so the file it was meant to replace still holds every
+    /// acknowledged write.
+    fn remove_temp_files(&self, report: &mut RecoveryReport) -> Result<()> {
+        let leftovers = [
+            self.path.with_extension("jsonl.tmp"),
+            self.trash_doc_path().with_extension("jsonl.tmp"),
+            self.base_dir.join("meta.json.tmp"),
+        ];
+        for path in leftovers {
+            if let Ok(meta) = fs::metadata(&path) {
+                fs::remove_file(&path).map_err(Error::io_err(&path, "remove leftover temp file"))?;
+                report.reclaimed_bytes += meta.len();
+                report.removed_temp_files.push(path);
+            }
+        }
+        Ok(())
     }
 
     /// Open a purely in-memory database (no disk file).
@@ -533,19 +1449,122 @@ impl Database {
             file_refs: RwLock::new(HashMap::new()),
             indexes: RwLock::new(HashMap::new()),
             writer: Mutex::new(()),
-            persistence: Persistence::Lazy,
+            compacting: Mutex::new(()),
+            persistence: RwLock::new(Persistence::Lazy),
             trash_mode: TrashMode::Manual,
             trash_ttl: None,
             trash_purge_interval: None,
             ttl_tx: Mutex::new(None),
             ttl_thread: Mutex::new(None),
-            file_handle: Mutex::new(None),
+            file_handle: Arc::new(Mutex::new(None)),
+            syncer: Arc::new(sync::Syncer::default()),
+            sync_tx: Mutex::new(None),
+            sync_thread: Mutex::new(None),
+            schema: None,
+            metadata: RwLock::new(serde_json::Map::new()),
+            limits: Limits::default(),
+            write_bucket: None,
+            subscribers: Mutex::new(Vec::new()),
+            change_seq: AtomicU64::new(0),
+            metrics: Arc::new(metrics::Recorder::default()),
+            warnings: Arc::default(),
+            idempotency: Mutex::default(),
         })
     }
 
+    /// Open a detached, in-memory view of the database at `path` as of log
+    /// record `seq` (the state after the first `seq` records were applied).
+    ///
+    /// Writes to the returned database never touch the file. History only
+    /// reaches back to the last compaction, which rewrites the log.
+    pub fn open_at(path: impl AsRef<Path>, seq: u64) -> Result<Self> {
+        let (docs, deleted) = replay_prefix(path.as_ref(), seq)?;
+        let db = Self::open_in_memory()?;
+        *db.docs.write() = docs;
+        *db.deleted.write() = deleted;
+        Ok(db)
+    }
+
     /// Set persistence mode. Returns self for chaining.
     pub fn with_persistence(mut self, mode: Persistence) -> Self {
-        self.persistence = mode;
+        *self.persistence.get_mut() = mode;
+        self.stop_sync_thread();
+        if let Persistence::Scheduled(interval) | Persistence::GroupCommit(interval) = mode {
+            self.start_sync_thread(interval);
+        }
+        self
+    }
+
+    /// Switch persistence mode on an open database, e.g. to `Lazy` for a
+    /// bulk load and back to `Immediate` afterwards. Waits for in-flight
+    /// writes and syncs everything written so far first, so the new mode's
+    /// guarantee covers earlier writes too. Lasts until the database is
+    /// dropped; like `with_persistence`, the mode is not stored on disk.
+    pub fn set_persistence(&self, mode: Persistence) -> Result<()> {
+        let _guard = self.writer.lock();
+        self.flush()?;
+        self.stop_sync_thread();
+        *self.persistence.write() = mode;
+        if let Persistence::Scheduled(interval) | Persistence::GroupCommit(interval) = mode {
+            self.start_sync_thread(interval);
+        }
+        Ok(())
+    }
+
+    /// Current persistence mode.
+    pub fn persistence(&self) -> Persistence {
+        *self.persistence.read()
+    }
+
+    /// Validate every written document against `schema`, replacing any schema
+    /// loaded from `meta.json`. Returns self for chaining.
+    pub fn with_schema(mut self, schema: Schema) -> Self {
+        self.schema = Some(schema);
+        self
+    }
+
+    /// The schema new writes are validated against, if any.
+    pub fn schema(&self) -> Option<&Schema> {
+        self.schema.as_ref()
+    }
+
+    /// User metadata stored with the database, e.g. which model produced
+    /// its embeddings. An empty object if none was set.
+    pub fn metadata(&self) -> Value {
+        Value::Object(self.metadata.read().clone())
+    }
+
+    /// Replace the user metadata with `metadata`, which must be a JSON
+    /// object. Stored under `"metadata"` in `meta.json`, next to the schema,
+    /// and written atomically before this returns.
+    pub fn set_metadata(&self, metadata: Value) -> Result<()> {
+        let Value::Object(map) = metadata else {
+            return Err(Error::invalid_arg("metadata must be a JSON object"));
+        };
+        let mut current = self.metadata.write();
+        if !self.is_in_memory() {
+            let meta_path = self.base_dir.join("meta.json");
+            let mut meta = storage::read_meta(&self.base_dir)?.unwrap_or_else(|| Value::Object(serde_json::Map::new()));
+            meta.as_object_mut()
+                .ok_or_else(|| Error::corruption(&meta_path, "meta.json is not an object"))?
+                .insert("metadata".to_string(), Value::Object(map.clone()));
+            storage::write_meta(&self.base_dir, &meta)?;
+        }
+        *current = map;
+        Ok(())
+    }
+
+    /// Reject writes that exceed `limits`. Returns self for chaining.
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self.write_bucket = limits.max_writes_per_sec.map(rate::TokenBucket::new);
+        self
+    }
+
+    /// Remember idempotency keys for `window` (default one hour) instead.
+    /// Returns self for chaining.
+    pub fn with_idempotency_window(self, window: Duration) -> Self {
+        self.idempotency.lock().set_window(window);
         self
     }
 
@@ -574,6 +1593,7 @@ impl Database {
         let trash_file = self.trash_doc_path();
         let mode = self.trash_mode;
         let ttl_dur = self.trash_ttl.unwrap();
+        let warnings = Arc::clone(&self.warnings);
 
         let (tx, rx) = std::sync::mpsc::channel();
         *self.ttl_tx.lock() = Some(tx);
@@ -584,7 +1604,8 @@ impl Database {
                     Ok(_) => break, // Cancellation signal received via tx.send(())
                     Err(std::sync::mpsc::RecvTimeoutError::Timeout) => {
                         // Time to purge
-                        let _ = Self::purge_trash_static(&base_dir, &trash_file, mode, Some(ttl_dur));
+                        let purged = Self::purge_trash_static(&base_dir, &trash_file, mode, Some(ttl_dur), &warnings);
+                        warnings.check(WarningKind::TrashPurge, "background trash purge", purged);
                     }
                     Err(std::sync::mpsc::RecvTimeoutError::Disconnected) => break, // DB dropped
                 }
@@ -594,12 +1615,88 @@ impl Database {
         *self.ttl_thread.lock() = Some(thread_handle);
     }
 
+    /// Start the background thread that syncs the log every `interval`.
+    fn start_sync_thread(&self, interval: Duration) {
+        if self.is_in_memory() {
+            return;
+        }
+
+        let path = self.path.clone();
+        let file_handle = Arc::clone(&self.file_handle);
+        let syncer = Arc::clone(&self.syncer);
+        let metrics = Arc::clone(&self.metrics);
+        let warnings = Arc::clone(&self.warnings);
+
+        let interval = interval.max(sync::MIN_INTERVAL);
+        let (tx, rx) = mpsc::channel();
+        *self.sync_tx.lock() = Some(tx);
+
+        let thread_handle = std::thread::spawn(move || {
+            while let Err(mpsc::RecvTimeoutError::Timeout) = rx.recv_timeout(interval) {
+                if !syncer.is_dirty() {
+                    continue;
+                }
+                // Read the target under the handle lock, then sync a clone
+                // of the handle so appends are not blocked by the fsync.
+                // Without a handle (dropped after a failed append) the
+                // records before the failure still need syncing, so sync a
+                // fresh one. It is not kept: appends reopen the log themselves.
+                let (file, target) = {
+                    let handle = file_handle.lock();
+                    let file = match handle.as_ref() {
+                        Some(file) => file.try_clone(),
+                        None => fs::OpenOptions::new().append(true).open(&path),
+                    };
+                    (file, syncer.last_written())
+                };
+                match file {
+                    Ok(file) => {
+                        let _timer = metrics.fsync_latency.time();
+                        if let Err(e) = syncer.sync(&file, target) {
+                            warnings.push(WarningKind::BackgroundSync, format!("background fsync: {}", e));
+                            break;
+                        }
+                    }
+                    Err(e) => {
+                        warnings.push(WarningKind::BackgroundSync, format!("open log for fsync: {}", e));
+                        syncer.failed(&e);
+                        break;
+                    }
+                }
+            }
+        });
+
+        *self.sync_thread.lock() = Some(thread_handle);
+    }
+
+    /// Stop the background sync thread, if running.
+    fn stop_sync_thread(&self) {
+        if let Some(tx) = self.sync_tx.lock().take() {
+            let _ = tx.send(());
+        }
+        if let Some(handle) = self.sync_thread.lock().take() {
+            let _ = handle.join();
+        }
+    }
+
+    /// Under `GroupCommit`, block until every write so far is synced.
+    /// Call after releasing the writer lock so other writers can join the sync.
+    fn await_sync(&self) -> Result<()> {
+        if !matches!(self.persistence(), Persistence::GroupCommit(_)) || self.is_in_memory() {
+            return Ok(());
+        }
+        self.syncer
+            .wait()
+            .map_err(Error::io_err(&self.path, "group commit fsync"))
+    }
+
     /// Static version of purge_trash that doesn't need `&self`, used by the background thread.
     fn purge_trash_static(
         base_dir: &Path,
         trash_file: &Path,
         trash_mode: TrashMode,
         trash_ttl: Option<Duration>,
+        warnings: &warning::Warnings,
     ) -> Result<usize> {
         let ttl = match (trash_mode, trash_ttl) {
             (TrashMode::TTL(t), _) => t,
@@ -644,7 +1741,7 @@ impl Database {
                     if let Ok(file_type) = entry.file_type() {
                         if file_type.is_dir() {
                             let bucket = FileBucket::new(&entry.file_name().to_string_lossy(), base_dir);
-                            let _ = bucket.purge_trash_ttl(ttl);
+                            warnings.check(WarningKind::TrashPurge, "purge file trash", bucket.purge_trash_ttl(ttl));
                         }
                     }
                 }
@@ -670,36 +1767,143 @@ impl Database {
         Ok(handle)
     }
 
+    /// Append one record line to the log, honouring the persistence mode
+    /// and `limits.max_log_bytes`.
+    fn append_record(&self, line: &str) -> Result<()> {
+        self.append(line, true, None)
+    }
+
+    /// `append_record`, with the persistence mode overridden for this record.
+    fn append_record_with(&self, line: &str, durability: Option<Durability>) -> Result<()> {
+        self.append(line, true, durability)
+    }
+
+    /// Append a delete tombstone. Exempt from the log quota so that a full
+    /// database can still shed documents before compacting.
+    fn append_tombstone(&self, line: &str) -> Result<()> {
+        self.append(line, false, None)
+    }
+
+    fn append(&self, line: &str, quota: bool, durability: Option<Durability>) -> Result<()> {
+        let mut handle = self.get_file_handle()?;
+        if let Some(ref mut file) = *handle {
+            if let (true, Some(limit)) = (quota, self.limits.max_log_bytes) {
+                let len = file
+                    .metadata()
+                    .map_err(Error::io_err(&self.path, "stat log"))?
+                    .len();
+                let after = len + line.len() as u64 + 1;
+                if after > limit {
+                    return Err(Error::limit("_log", limit as usize, after as usize));
+                }
+            }
+            let synced = match durability {
+                Some(durability) => durability == Durability::Synced,
+                None => self.persistence() == Persistence::Immediate,
+            };
+            let written = if synced {
+                let _timer = self.metrics.fsync_latency.time();
+                trace_start!(sync_start);
+                let written = storage::append_line_sync(file, &self.path, line);
+                trace_event!(
+                    bytes = line.len(),
+                    sync_us = sync_start.elapsed().as_micros() as u64,
+                    "log append synced"
+                );
+                written
+            } else {
+                storage::append_line(file, &self.path, line).map(|()| self.syncer.written())
+            };
+            if let Err(e) = written {
+                // A failed write (e.g. disk full) may leave part of the line
+                // behind. Reopen on the next append so `open_for_append`
+                // terminates it instead of the next record running into it.
+                *handle = None;
+                return Err(e);
+            }
+            metrics::Recorder::count(&self.metrics.log_records, 1);
+        }
+        Ok(())
+    }
+
     // ─── Layer 1: Core Operations ──────────────────────────────────
 
     /// Insert a document. Generates a NanoID `_id` and returns it.
     /// O(1) operation: HashMap insert + file append.
-    pub fn insert(&self, mut doc: Value) -> Result<String> {
-        let _guard = self.writer.lock();
+    pub fn insert(&self, doc: Value) -> Result<String> {
+        self.write_insert(None, doc, None, None)
+    }
+
+    /// `insert` with the persistence mode overridden for this call, e.g.
+    /// `Durability::Synced` for a user-facing write in a `Lazy` database.
+    pub fn insert_with(&self, doc: Value, durability: Durability) -> Result<String> {
+        self.write_insert(None, doc, None, Some(durability))
+    }
+
+    /// Insert a document with a prefixed ID.
+    pub fn insert_with_prefix(&self, prefix: &str, doc: Value) -> Result<String> {
+        self.write_insert(Some(prefix), doc, None, None)
+    }
+
+    /// Insert a document once per `key`. A retry with the same key inside
+    /// the idempotency window (see `with_idempotency_window`) writes nothing
+    /// and returns the ID of the first insert, even if that document has
+    /// since been changed or deleted.
+    ///
+    /// The key is stored with the log record, and compaction carries it over
+    /// while it is inside the window, so it survives restarts.
+    pub fn insert_idempotent(&self, key: &str, doc: Value) -> Result<String> {
+        if key.is_empty() {
+            return Err(Error::invalid_arg("idempotency key must not be empty"));
+        }
+        self.write_insert(None, doc, Some(key), None)
+    }
+
+    fn write_insert(
+        &self,
+        prefix: Option<&str>,
+        mut doc: Value,
+        key: Option<&str>,
+        durability: Option<Durability>,
+    ) -> Result<String> {
+        self.throttle(1)?;
+        let guard = self.writer.lock();
+        let _timer = self.metrics.write_latency.time();
+        trace_span!("ndb.insert", prefix);
+
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        if let Some(id) = key.and_then(|key| self.idempotency.lock().get(key, now)) {
+            trace_event!(id = %id, "idempotent insert repeated");
+            return Ok(id);
+        }
 
         let docs_reader = self.docs.read();
         let existing: HashSet<String> = docs_reader.keys().cloned().collect();
         drop(docs_reader);
 
-        let id = generate_unique(&existing);
+        let id = match prefix {
+            Some(prefix) => generate_unique_with_prefix(prefix, &existing),
+            None => generate_unique(&existing),
+        };
         doc.as_object_mut()
             .unwrap()
             .insert("_id".to_string(), Value::String(id.clone()));
+        set_version(&mut doc, 1);
+        self.check_doc(&doc)?;
 
         // Append to file
         if !self.is_in_memory() {
-            let line = serde_json::to_string(&doc)?;
-            let mut handle = self.get_file_handle()?;
-            if let Some(ref mut file) = *handle {
-                match self.persistence {
-                    Persistence::Immediate => {
-                        storage::append_line_sync(file, &self.path, &line)?;
-                    }
-                    _ => {
-                        storage::append_line(file, &self.path, &line)?;
-                    }
-                }
-            }
+            let line = match key {
+                Some(key) => serde_json::to_string(&with_idem_key(&doc, key, now))?,
+                None => serde_json::to_string(&doc)?,
+            };
+            self.append_record_with(&line, durability)?;
+        }
+        if let Some(key) = key {
+            self.idempotency.lock().record(key, &id, now);
         }
 
         // Update indexes
@@ -717,53 +1921,103 @@ impl Database {
         let mut docs = self.docs.write();
         self.deleted.write().remove(&id);
         docs.insert(id.clone(), doc);
+        drop(docs);
+
+        self.notify(|| Change::Insert { id: id.clone(), doc: self.docs.read()[&id].clone() });
+
+        drop(guard);
+        if durability.is_none() {
+            self.await_sync()?;
+        }
 
+        metrics::Recorder::count(&self.metrics.inserts, 1);
+        trace_event!(id = %id, "inserted");
         Ok(id)
     }
 
-    /// Insert a document with a prefixed ID.
-    pub fn insert_with_prefix(&self, prefix: &str, mut doc: Value) -> Result<String> {
-        let _guard = self.writer.lock();
+    /// Insert several documents atomically. Returns the generated IDs in input order.
+    ///
+    /// The batch is appended as a single `{"_op": "batch", "docs": [...]}` line.
+    /// A torn line is discarded on replay, so after a crash either every
+    /// document of the batch is recovered or none of them is.
+    pub fn insert_batch(&self, docs: Vec<Value>) -> Result<Vec<String>> {
+        self.write_insert_batch(docs, None)
+    }
 
-        let docs_reader = self.docs.read();
-        let existing: HashSet<String> = docs_reader.keys().cloned().collect();
-        drop(docs_reader);
+    /// `insert_batch` with the persistence mode overridden for this call,
+    /// e.g. `Durability::Buffered` for a backfill in an `Immediate` database.
+    pub fn insert_batch_with(&self, docs: Vec<Value>, durability: Durability) -> Result<Vec<String>> {
+        self.write_insert_batch(docs, Some(durability))
+    }
 
-        let id = generate_unique_with_prefix(prefix, &existing);
-        doc.as_object_mut()
-            .unwrap()
-            .insert("_id".to_string(), Value::String(id.clone()));
+    fn write_insert_batch(&self, docs: Vec<Value>, durability: Option<Durability>) -> Result<Vec<String>> {
+        self.throttle(docs.len())?;
+        let guard = self.writer.lock();
+        let _timer = self.metrics.write_latency.time();
+        trace_span!("ndb.insert_batch", docs = docs.len());
+
+        if docs.iter().any(|d| !d.is_object()) {
+            return Err(Error::invalid_arg("insert_batch: every document must be a JSON object"));
+        }
+        if docs.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let mut existing: HashSet<String> = self.docs.read().keys().cloned().collect();
+        let mut ids = Vec::with_capacity(docs.len());
+        let mut batch = Vec::with_capacity(docs.len());
+        for mut doc in docs {
+            let id = generate_unique(&existing);
+            existing.insert(id.clone());
+            doc.as_object_mut()
+                .unwrap()
+                .insert("_id".to_string(), Value::String(id.clone()));
+            set_version(&mut doc, 1);
+            self.check_doc(&doc)?;
+            ids.push(id);
+            batch.push(doc);
+        }
 
         if !self.is_in_memory() {
-            let line = serde_json::to_string(&doc)?;
-            let mut handle = self.get_file_handle()?;
-            if let Some(ref mut file) = *handle {
-                match self.persistence {
-                    Persistence::Immediate => {
-                        storage::append_line_sync(file, &self.path, &line)?;
-                    }
-                    _ => {
-                        storage::append_line(file, &self.path, &line)?;
-                    }
-                }
-            }
+            let record = serde_json::json!({"_op": "batch", "docs": batch});
+            let line = serde_json::to_string(&record)?;
+            self.append_record_with(&line, durability)?;
         }
 
         let mut indexes = self.indexes.write();
-        for (field, index) in indexes.iter_mut() {
-            if let Some(val) = doc.get(field) {
-                index.insert(val, &id);
+        for (id, doc) in ids.iter().zip(&batch) {
+            for (field, index) in indexes.iter_mut() {
+                if let Some(val) = doc.get(field) {
+                    index.insert(val, id);
+                }
             }
         }
         drop(indexes);
 
-        self.increment_file_refs(&doc);
+        for doc in &batch {
+            self.increment_file_refs(doc);
+        }
 
-        let mut docs = self.docs.write();
-        self.deleted.write().remove(&id);
-        docs.insert(id.clone(), doc);
+        let mut store = self.docs.write();
+        let mut deleted = self.deleted.write();
+        for (id, doc) in ids.iter().zip(batch) {
+            deleted.remove(id);
+            store.insert(id.clone(), doc);
+        }
+        drop(deleted);
+        drop(store);
 
-        Ok(id)
+        for id in &ids {
+            self.notify(|| Change::Insert { id: id.clone(), doc: self.docs.read()[id].clone() });
+        }
+
+        drop(guard);
+        if durability.is_none() {
+            self.await_sync()?;
+        }
+
+        metrics::Recorder::count(&self.metrics.inserts, ids.len() as u64);
+        Ok(ids)
     }
 
     /// Get a document by ID. O(1) HashMap lookup.
@@ -776,117 +2030,89 @@ impl Database {
 
     /// Update a document. Appends new version to file, old version superseded.
     /// O(1) operation.
-    pub fn update(&self, id: &str, mut new_doc: Value) -> Result<()> {
-        let _guard = self.writer.lock();
+    pub fn update(&self, id: &str, new_doc: Value) -> Result<()> {
+        self.write_update(id, new_doc, None).map(|_| ())
+    }
 
-        {
+    /// Update a document only if its `_version` is still `expected`.
+    ///
+    /// Returns the new version, or `Error::VersionConflict` (writing nothing)
+    /// if another write got there first. Read the document, change it, and
+    /// retry on conflict instead of silently overwriting a concurrent update.
+    pub fn update_if_version(&self, id: &str, new_doc: Value, expected: u64) -> Result<u64> {
+        self.write_update(id, new_doc, Some(expected))
+    }
+
+    /// Current `_version` of a document.
+    pub fn version(&self, id: &str) -> Result<u64> {
+        self.docs
+            .read()
+            .get(id)
+            .map(doc_version)
+            .ok_or_else(|| Error::not_found(id))
+    }
+
+    fn write_update(&self, id: &str, mut new_doc: Value, expected: Option<u64>) -> Result<u64> {
+        self.throttle(1)?;
+        let guard = self.writer.lock();
+        let _timer = self.metrics.write_latency.time();
+        trace_span!("ndb.update", id);
+
+        let version = {
             let docs = self.docs.read();
-            if !docs.contains_key(id) {
-                return Err(Error::not_found(id));
-            }
-        }
+            let current = docs.get(id).map(doc_version).ok_or_else(|| Error::not_found(id))?;
+            check_version(id, expected, current)?;
+            current + 1
+        };
 
         // Set _id on new doc
         new_doc
             .as_object_mut()
             .unwrap()
             .insert("_id".to_string(), Value::String(id.to_string()));
+        set_version(&mut new_doc, version);
+        self.check_doc(&new_doc)?;
 
-        // Remove old values from indexes, add new
-        let mut old_doc_clone = None;
-        let mut indexes = self.indexes.write();
-        {
-            let docs = self.docs.read();
-            if let Some(old_doc) = docs.get(id) {
-                old_doc_clone = Some(old_doc.clone());
-                for (field, index) in indexes.iter_mut() {
-                    if let Some(old_val) = old_doc.get(field) {
-                        index.remove(old_val, id);
-                    }
-                }
-            }
-        }
-        for (field, index) in indexes.iter_mut() {
-            if let Some(val) = new_doc.get(field) {
-                index.insert(val, id);
-            }
-        }
-        drop(indexes);
-
-        if let Some(old) = old_doc_clone {
-            self.handle_ref_delta_and_trash(&old, &new_doc);
-        }
-
-        // Append to file
+        // Log first: a rejected append (e.g. the log quota) must leave no trace
         if !self.is_in_memory() {
             let line = serde_json::to_string(&new_doc)?;
-            let mut handle = self.get_file_handle()?;
-            if let Some(ref mut file) = *handle {
-                match self.persistence {
-                    Persistence::Immediate => {
-                        storage::append_line_sync(file, &self.path, &line)?;
-                    }
-                    _ => {
-                        storage::append_line(file, &self.path, &line)?;
-                    }
-                }
-            }
+            self.append_record(&line)?;
         }
 
+        let old_doc = self.docs.read()[id].clone();
+        self.reindex(id, &old_doc, &new_doc);
+        self.handle_ref_delta_and_trash(&old_doc, &new_doc);
+
         // Update in-memory store
-        let mut docs = self.docs.write();
-        docs.insert(id.to_string(), new_doc);
+        self.docs.write().insert(id.to_string(), new_doc);
 
-        Ok(())
+        self.notify_update(id);
+
+        drop(guard);
+        self.await_sync()?;
+
+        metrics::Recorder::count(&self.metrics.updates, 1);
+        Ok(version)
     }
 
     /// Append an element to an array field. O(1) file write.
     pub fn array_push(&self, id: &str, field: &str, value: Value) -> Result<()> {
-        let _guard = self.writer.lock();
-
-        let mut old_doc = None;
-        {
-            let mut docs = self.docs.write();
-            if let Some(doc) = docs.get_mut(id) {
-                old_doc = Some(doc.clone());
-                if let Some(obj) = doc.as_object_mut() {
-                    if let Some(arr) = obj.get_mut(field).and_then(|v| v.as_array_mut()) {
-                        arr.push(value.clone());
-                    } else {
-                        obj.insert(field.to_string(), serde_json::json!([value.clone()]));
-                    }
-                }
-                if let Some(old) = &old_doc {
-                    self.handle_ref_delta_and_trash(old, doc);
-                }
-            } else {
-                return Err(Error::not_found(id));
-            }
-        }
-
-        // Write patch to file
-        if !self.is_in_memory() {
-            let patch = serde_json::json!({
-                "_id": id,
-                "_op": "array_push",
-                "field": field,
-                "value": value
-            });
-            let line = serde_json::to_string(&patch)?;
-            let mut handle = self.get_file_handle()?;
-            if let Some(ref mut file) = *handle {
-                match self.persistence {
-                    Persistence::Immediate => {
-                        storage::append_line_sync(file, &self.path, &line)?;
-                    }
-                    _ => {
-                        storage::append_line(file, &self.path, &line)?;
-                    }
+        trace_span!("ndb.array_push", id, field);
+        let record = serde_json::json!({
+            "_id": id,
+            "_op": "array_push",
+            "field": field,
+            "value": value
+        });
+        self.write_patch(id, record, |doc| {
+            if let Some(obj) = doc.as_object_mut() {
+                if let Some(arr) = obj.get_mut(field).and_then(|v| v.as_array_mut()) {
+                    arr.push(value.clone());
+                } else {
+                    obj.insert(field.to_string(), serde_json::json!([value.clone()]));
                 }
             }
-        }
-
-        Ok(())
+        })
     }
 
     /// Set a value at a dot-separated path within a document. O(1) file write.
@@ -895,44 +2121,14 @@ impl Database {
     /// Array indices are addressed by numeric path segments.
     /// If the path doesn't resolve, the patch is silently skipped during replay.
     pub fn set(&self, id: &str, path: &str, value: Value) -> Result<()> {
-        let _guard = self.writer.lock();
-
-        let mut old_doc = None;
-        {
-            let mut docs = self.docs.write();
-            if let Some(doc) = docs.get_mut(id) {
-                old_doc = Some(doc.clone());
-                apply_path_set(doc, path, value.clone());
-                if let Some(old) = &old_doc {
-                    self.handle_ref_delta_and_trash(old, doc);
-                }
-            } else {
-                return Err(Error::not_found(id));
-            }
-        }
-
-        if !self.is_in_memory() {
-            let patch = serde_json::json!({
-                "_id": id,
-                "_op": "set",
-                "path": path,
-                "value": value
-            });
-            let line = serde_json::to_string(&patch)?;
-            let mut handle = self.get_file_handle()?;
-            if let Some(ref mut file) = *handle {
-                match self.persistence {
-                    Persistence::Immediate => {
-                        storage::append_line_sync(file, &self.path, &line)?;
-                    }
-                    _ => {
-                        storage::append_line(file, &self.path, &line)?;
-                    }
-                }
-            }
-        }
-
-        Ok(())
+        trace_span!("ndb.set", id, path);
+        let record = serde_json::json!({
+            "_id": id,
+            "_op": "set",
+            "path": path,
+            "value": value
+        });
+        self.write_patch(id, record, |doc| apply_path_set(doc, path, value.clone()))
     }
 
     /// Remove a field or array element at a dot-separated path. O(1) file write.
@@ -941,42 +2137,43 @@ impl Database {
     /// For array elements, the index is removed and the array shifts.
     /// If the path doesn't resolve, the patch is silently skipped during replay.
     pub fn remove(&self, id: &str, path: &str) -> Result<()> {
-        let _guard = self.writer.lock();
+        trace_span!("ndb.remove", id, path);
+        let record = serde_json::json!({
+            "_id": id,
+            "_op": "remove",
+            "path": path
+        });
+        self.write_patch(id, record, |doc| apply_path_remove(doc, path))
+    }
 
-        let mut old_doc = None;
-        {
-            let mut docs = self.docs.write();
-            if let Some(doc) = docs.get_mut(id) {
-                old_doc = Some(doc.clone());
-                apply_path_remove(doc, path);
-                if let Some(old) = &old_doc {
-                    self.handle_ref_delta_and_trash(old, doc);
-                }
-            } else {
-                return Err(Error::not_found(id));
-            }
-        }
+    /// Apply `patch` to a copy of document `id`, log `record`, then swap the
+    /// copy in. Nothing in memory, the indexes or the buckets changes unless
+    /// the append succeeded.
+    fn write_patch(&self, id: &str, record: Value, patch: impl FnOnce(&mut Value)) -> Result<()> {
+        self.throttle(1)?;
+        let guard = self.writer.lock();
+        let _timer = self.metrics.write_latency.time();
+
+        let mut new_doc = self.docs.read().get(id).cloned().ok_or_else(|| Error::not_found(id))?;
+        let version = doc_version(&new_doc) + 1;
+        patch(&mut new_doc);
+        set_version(&mut new_doc, version);
+        self.check_doc(&new_doc)?;
 
         if !self.is_in_memory() {
-            let patch = serde_json::json!({
-                "_id": id,
-                "_op": "remove",
-                "path": path
-            });
-            let line = serde_json::to_string(&patch)?;
-            let mut handle = self.get_file_handle()?;
-            if let Some(ref mut file) = *handle {
-                match self.persistence {
-                    Persistence::Immediate => {
-                        storage::append_line_sync(file, &self.path, &line)?;
-                    }
-                    _ => {
-                        storage::append_line(file, &self.path, &line)?;
-                    }
-                }
-            }
+            self.append_record(&serde_json::to_string(&record)?)?;
         }
 
+        let old_doc = self.docs.write().insert(id.to_string(), new_doc.clone()).unwrap_or_default();
+        self.handle_ref_delta_and_trash(&old_doc, &new_doc);
+        self.reindex(id, &old_doc, &new_doc);
+
+        self.notify_update(id);
+
+        drop(guard);
+        self.await_sync()?;
+
+        metrics::Recorder::count(&self.metrics.patches, 1);
         Ok(())
     }
 
@@ -990,107 +2187,85 @@ impl Database {
     /// Delete a document by ID. O(1) duration.
     /// In an on-disk database, writes a tombstone instead of deleting data.
     pub fn delete(&self, id: &str) -> Result<()> {
-        let _guard = self.writer.lock();
-
-        let doc_to_trash = {
-            let docs = self.docs.read();
-            if let Some(doc) = docs.get(id) {
-                doc.clone()
-            } else {
-                return Err(Error::not_found(id));
-            }
-        };
-
-        // Extract file references safely
-        let mut extracted_file_refs = HashSet::new();
-        Self::extract_file_refs(&doc_to_trash, &mut extracted_file_refs);
+        self.write_delete(id, None, None)
+    }
 
-        let mut orphaned_files = Vec::new();
-        // Update in-memory file reference counter
-        {
-            let mut file_refs = self.file_refs.write();
-            for r in extracted_file_refs {
-                if let Some(count) = file_refs.get_mut(&r) {
-                    *count = count.saturating_sub(1);
-                    if *count == 0 {
-                        file_refs.remove(&r);
-                        orphaned_files.push(r);
-                    }
-                }
-            }
+    /// Delete a document once per `key`. A retry with the same key inside
+    /// the idempotency window succeeds without deleting anything, so it
+    /// cannot remove a document re-created in the meantime, and does not
+    /// fail with `NotFound`.
+    pub fn delete_idempotent(&self, key: &str, id: &str) -> Result<()> {
+        if key.is_empty() {
+            return Err(Error::invalid_arg("idempotency key must not be empty"));
         }
+        self.write_delete(id, None, Some(key))
+    }
 
-        // Trash the orphaned files
-        for f in &orphaned_files {
-            if let Some(file_ref) = FileRef::from_compact(f) {
-                let bucket = self.bucket(&file_ref.bucket);
-                let _ = bucket.delete(&file_ref);
-            }
-        }
+    /// Delete a document only if its `_version` is still `expected`;
+    /// otherwise return `Error::VersionConflict` and delete nothing.
+    pub fn delete_if_version(&self, id: &str, expected: u64) -> Result<()> {
+        self.write_delete(id, Some(expected), None)
+    }
 
-        // Append to persistent doc trash file
-        if !self.is_in_memory() && self.trash_mode != TrashMode::Off {
-            let now = std::time::SystemTime::now()
-                .duration_since(std::time::UNIX_EPOCH)
-                .unwrap_or_default()
-                .as_secs();
-            let mut trash_doc = doc_to_trash;
-            if let Some(obj) = trash_doc.as_object_mut() {
-                obj.insert("_deleted".to_string(), serde_json::json!(now));
-                if !orphaned_files.is_empty() {
-                    obj.insert("_trashed_files".to_string(), serde_json::json!(orphaned_files));
-                }
-            }
-            let _ = storage::append_doc_trash(&self.trash_doc_path(), &trash_doc);
+    fn write_delete(&self, id: &str, expected: Option<u64>, key: Option<&str>) -> Result<()> {
+        self.throttle(1)?;
+        let guard = self.writer.lock();
+        let _timer = self.metrics.write_latency.time();
+        trace_span!("ndb.delete", id);
+
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        if key.is_some_and(|key| self.idempotency.lock().get(key, now).is_some()) {
+            trace_event!(id, "idempotent delete repeated");
+            return Ok(());
         }
 
-        // Remove from indexes
-        let mut indexes = self.indexes.write();
-        {
+        let doc_to_trash = {
             let docs = self.docs.read();
             if let Some(doc) = docs.get(id) {
-                for (field, index) in indexes.iter_mut() {
-                    if let Some(val) = doc.get(field) {
-                        index.remove(val, id);
-                    }
-                }
+                check_version(id, expected, doc_version(doc))?;
+                doc.clone()
+            } else {
+                return Err(Error::not_found(id));
             }
-        }
-        drop(indexes);
+        };
 
-        // Write tombstone to file
+        // Log first: a failed append (e.g. disk full) must leave the document
+        // live, indexed and with its files in place
         if !self.is_in_memory() {
-            let now = std::time::SystemTime::now()
-                .duration_since(std::time::UNIX_EPOCH)
-                .unwrap_or_default()
-                .as_secs();
             let tombstone = serde_json::json!({
                 "_id": id,
                 "_deleted": now
             });
-            let line = serde_json::to_string(&tombstone)?;
-            let mut handle = self.get_file_handle()?;
-            if let Some(ref mut file) = *handle {
-                match self.persistence {
-                    Persistence::Immediate => {
-                        storage::append_line_sync(file, &self.path, &line)?;
-                    }
-                    _ => {
-                        storage::append_line(file, &self.path, &line)?;
-                    }
-                }
-            }
+            let line = match key {
+                Some(key) => serde_json::to_string(&with_idem_key(&tombstone, key, now))?,
+                None => serde_json::to_string(&tombstone)?,
+            };
+            self.append_tombstone(&line)?;
         }
+        if let Some(key) = key {
+            self.idempotency.lock().record(key, id, now);
+        }
+
+        self.trash_deleted_doc(id, doc_to_trash, now);
 
         // Update in-memory state
         self.docs.write().remove(id);
         self.deleted.write().insert(id.to_string());
 
+        self.notify(|| Change::Delete { id: id.to_string() });
+
         // Handle trash mode
         if self.trash_mode == TrashMode::Off {
             // Hard delete — nothing to keep
         }
 
+        drop(guard);
+        self.await_sync()?;
+
+        metrics::Recorder::count(&self.metrics.deletes, 1);
         Ok(())
     }
 
@@ -1124,7 +2299,8 @@ impl Database {
         // Check for index
         {
             let indexes = self.indexes.read();
-            if let Some(index) = indexes.get(field) {
+            // A text index matches words, not whole values
+            if let Some(index) = indexes.get(field).filter(|index| index.kind() != "text") {
                 let ids = index.get(value);
                 let docs = self.docs.read();
                 return ids
@@ -1155,7 +2331,7 @@ impl Database {
         docs.values()
             .filter(|doc| {
                 doc.get(field)
-                    .map(|v| predicate(v))
+                    .map(&predicate)
                     .unwrap_or(false)
             })
             .cloned()
@@ -1182,83 +2358,299 @@ impl Database {
     // ─── Layer 3: JSON AST Queries ─────────────────────────────────
 
     /// Execute a JSON AST query. Returns all matching documents.
+    ///
+    /// Equality and `$in` conditions on indexed top-level fields narrow the
+    /// scan to the most selective index's candidates; otherwise every
+    /// document is evaluated.
     pub fn query(&self, ast: Value) -> Vec<Value> {
+        let _timer = self.metrics.query_latency.time();
+        metrics::Recorder::count(&self.metrics.queries, 1);
+        trace_span!("ndb.query");
+        // Only fails when cancelled, and there is no handle to cancel
+        self.scan(&ast, None).unwrap_or_default()
+    }
+
+    /// `query_with` that checks `op` for cancellation every 1024 candidates
+    /// and reports `scan` progress. A cancelled query returns `Error::Cancelled`.
+    pub fn query_cancellable(&self, ast: Value, opts: QueryOptions, op: &OperationHandle) -> Result<Vec<Value>> {
+        self.query_filtered(&ast, &opts, Some(op), |_| true)
+    }
+
+    /// `query_cancellable` over the documents whose ID passes `keep`, e.g.
+    /// the IDs of one namespace.
+    pub(crate) fn query_filtered(
+        &self,
+        ast: &Value,
+        opts: &QueryOptions,
+        op: Option<&OperationHandle>,
+        keep: impl Fn(&str) -> bool,
+    ) -> Result<Vec<Value>> {
+        let _timer = self.metrics.query_latency.time();
+        metrics::Recorder::count(&self.metrics.queries, 1);
+        trace_span!("ndb.query");
+        let mut results = Vec::new();
+        self.visit_filtered(ast, op, keep, |doc| results.push(doc.clone()))?;
+        trace_event!(matched = results.len(), "query done");
+        if let Some(op) = op {
+            op.report("query", "done", results.len(), results.len());
+        }
+        Ok(apply_query_options(results, opts))
+    }
+
+    /// Collect the documents matching `ast`, narrowed by indexes when one applies.
+    fn scan(&self, ast: &Value, op: Option<&OperationHandle>) -> Result<Vec<Value>> {
+        let mut results = Vec::new();
+        self.visit(ast, op, |doc| results.push(doc.clone()))?;
+        trace_event!(matched = results.len(), "query done");
+        Ok(results)
+    }
+
+    /// Call `f` with each document matching `ast`, without cloning. Holds
+    /// the document read lock throughout. Returns the plan and scan part
+    /// of the query's explanation.
+    fn visit(&self, ast: &Value, op: Option<&OperationHandle>, f: impl FnMut(&Value)) -> Result<QueryExplanation> {
+        self.visit_filtered(ast, op, |_| true, f)
+    }
+
+    /// `visit` restricted to documents whose ID passes `keep`, checked
+    /// before the document is evaluated.
+    fn visit_filtered(
+        &self,
+        ast: &Value,
+        op: Option<&OperationHandle>,
+        keep: impl Fn(&str) -> bool,
+        mut f: impl FnMut(&Value),
+    ) -> Result<QueryExplanation> {
+        let start = std::time::Instant::now();
+        let candidates = index_candidates(&self.indexes.read(), ast);
+        let plan_us = start.elapsed().as_micros() as u64;
         let docs = self.docs.read();
-        docs.values()
-            .filter(|doc| query_matches(doc, &ast))
-            .cloned()
-            .collect()
+        let total = candidates.as_ref().map_or(docs.len(), |ids| ids.len());
+        let explanation = QueryExplanation {
+            indexed: candidates.is_some(),
+            documents: docs.len(),
+            scanned: total,
+            plan_us,
+            ..QueryExplanation::default()
+        };
+        trace_event!(indexed = candidates.is_some(), scanned = total, "query plan");
+        let check = |done: usize| match op {
+            Some(op) if done % progress::CHECK_EVERY == 0 => op.checkpoint("query", "scan", done, total),
+            _ => Ok(()),
+        };
+
+        match candidates {
+            Some(ids) => {
+                for (i, id) in ids.iter().enumerate() {
+                    check(i)?;
+                    if !keep(id) {
+                        continue;
+                    }
+                    if let Some(doc) = docs.get(id).filter(|doc| query_matches(doc, ast)) {
+                        f(doc);
+                    }
+                }
+            }
+            None => {
+                for (i, (id, doc)) in docs.iter().enumerate() {
+                    check(i)?;
+                    if keep(id) && query_matches(doc, ast) {
+                        f(doc);
+                    }
+                }
+            }
+        }
+        Ok(QueryExplanation {
+            scan_us: start.elapsed().as_micros() as u64 - plan_us,
+            ..explanation
+        })
+    }
+
+    /// `query_with` that also reports how the query ran: whether an index
+    /// was used or every document was scanned, how many documents were
+    /// evaluated and matched, and time spent per phase. Useful for finding
+    /// out why a query is slow or why a document is missing.
+    pub fn query_explain(&self, ast: Value, opts: QueryOptions) -> (Vec<Value>, QueryExplanation) {
+        let _timer = self.metrics.query_latency.time();
+        metrics::Recorder::count(&self.metrics.queries, 1);
+        trace_span!("ndb.query");
+        let mut results = Vec::new();
+        // Only fails when cancelled, and there is no handle to cancel
+        let mut explanation = self
+            .visit(&ast, None, |doc| results.push(doc.clone()))
+            .unwrap_or_default();
+        explanation.matched = results.len();
+
+        let start = std::time::Instant::now();
+        let results = apply_query_options(results, &opts);
+        explanation.options_us = start.elapsed().as_micros() as u64;
+        explanation.returned = results.len();
+        (results, explanation)
     }
 
     /// Execute a JSON AST query with options (limit, sort, offset).
     pub fn query_with(&self, ast: Value, opts: QueryOptions) -> Vec<Value> {
-        let mut results = self.query(ast);
-
-        // Sort
-        if let Some((ref field, dir)) = opts.sort_by {
-            results.sort_by(|a, b| {
-                let av = a.get(field).unwrap_or(&Value::Null);
-                let bv = b.get(field).unwrap_or(&Value::Null);
-                let cmp = value_cmp(av, bv);
-                match dir {
-                    SortDir::Asc => cmp,
-                    SortDir::Desc => cmp.reverse(),
+        apply_query_options(self.query(ast), &opts)
+    }
+
+    /// Execute several JSON AST queries in one pass over the store.
+    ///
+    /// Every query sees the same consistent state, and the documents are
+    /// scanned once instead of once per query. Results are returned in the
+    /// order of `queries`, each with its own options applied.
+    pub fn query_batch(&self, queries: Vec<(Value, QueryOptions)>) -> Vec<Vec<Value>> {
+        let _timer = self.metrics.query_latency.time();
+        metrics::Recorder::count(&self.metrics.queries, queries.len() as u64);
+        trace_span!("ndb.query_batch", queries = queries.len());
+        let mut results: Vec<Vec<Value>> = vec![Vec::new(); queries.len()];
+        {
+            let docs = self.docs.read();
+            for doc in docs.values() {
+                for (i, (ast, _)) in queries.iter().enumerate() {
+                    if query_matches(doc, ast) {
+                        results[i].push(doc.clone());
+                    }
                 }
-            });
+            }
         }
 
-        // Offset
-        let offset = opts.offset.unwrap_or(0);
-        if offset > 0 {
-            results = results.into_iter().skip(offset).collect();
-        }
+        results
+            .into_iter()
+            .zip(queries)
+            .map(|(matches, (_, opts))| apply_query_options(matches, &opts))
+            .collect()
+    }
+
+    /// Execute a JSON AST query and group matches by the value of `group_by`.
+    ///
+    /// Matches are sorted by `opts.sort_by` first; each group keeps its first
+    /// `group_size` documents and groups are ordered by their best document.
+    /// `opts.offset` / `opts.limit` page over groups, not documents.
+    /// Documents without the grouping field are left out.
+    pub fn query_grouped(
+        &self,
+        ast: Value,
+        group_by: &str,
+        group_size: usize,
+        opts: QueryOptions,
+    ) -> Vec<QueryGroup> {
+        let sorted = apply_query_options(
+            self.query(ast),
+            &QueryOptions {
+                sort_by: opts.sort_by.clone(),
+                ..QueryOptions::default()
+            },
+        );
 
-        // Limit
-        if let Some(limit) = opts.limit {
-            results.truncate(limit);
+        let mut groups: Vec<QueryGroup> = Vec::new();
+        let mut positions: HashMap<String, usize> = HashMap::new();
+        for doc in sorted {
+            let key = match field_get(&doc, group_by) {
+                Some(key) => key.clone(),
+                None => continue,
+            };
+            let pos = *positions.entry(value_slot(&key)).or_insert_with(|| {
+                groups.push(QueryGroup {
+                    key,
+                    docs: Vec::new(),
+                });
+                groups.len() - 1
+            });
+            if groups[pos].docs.len() < group_size {
+                groups[pos].docs.push(doc);
+            }
         }
 
-        results
+        let offset = opts.offset.unwrap_or(0);
+        let limit = opts.limit.unwrap_or(usize::MAX);
+        groups.into_iter().skip(offset).take(limit).collect()
+    }
+
+    /// Aggregate `field` over the documents matching `ast` (`json!({})` for
+    /// all) without copying them out. Dotted paths reach nested fields.
+    ///
+    /// Returns a number for `Count`, the extreme value (or `null`) for `Min`
+    /// and `Max`, the mean (or `null`) for `Avg`, and `[{"value", "count"}]`
+    /// for `Distinct`.
+    pub fn aggregate(&self, ast: Value, field: &str, agg: Aggregation) -> Value {
+        let _timer = self.metrics.query_latency.time();
+        metrics::Recorder::count(&self.metrics.queries, 1);
+        trace_span!("ndb.aggregate", field);
+        let mut acc = aggregate::Aggregator::new(agg);
+        // Only fails when cancelled, and there is no handle to cancel
+        let _ = self.visit(&ast, None, |doc| {
+            if let Some(v) = field_get(doc, field) {
+                acc.add(v);
+            }
+        });
+        acc.finish()
     }
 
     // ─── Index Management ──────────────────────────────────────────
 
     /// Create a hash index on a field. Scans all documents once.
     pub fn create_index(&self, field: &str) -> Result<()> {
-        let _guard = self.writer.lock();
-
-        let mut index = HashIndex::new();
-        let docs = self.docs.read();
-        for (id, doc) in docs.iter() {
-            if let Some(val) = doc.get(field) {
-                index.insert(val, id);
-            }
-        }
+        self.create_index_with(field, &OperationHandle::new())
+    }
 
-        self.indexes
-            .write()
-            .insert(field.to_string(), Box::new(index));
-        Ok(())
+    /// `create_index` with progress reports and cancellation. A cancelled
+    /// build installs nothing.
+    pub fn create_index_with(&self, field: &str, op: &OperationHandle) -> Result<()> {
+        trace_span!("ndb.create_index", field, kind = "hash");
+        self.build_index(field, Box::new(HashIndex::new()), op)
     }
 
     /// Create a BTree index on a field (for range queries).
     pub fn create_btree_index(&self, field: &str) -> Result<()> {
+        self.create_btree_index_with(field, &OperationHandle::new())
+    }
+
+    /// `create_btree_index` with progress reports and cancellation.
+    pub fn create_btree_index_with(&self, field: &str, op: &OperationHandle) -> Result<()> {
+        trace_span!("ndb.create_index", field, kind = "btree");
+        self.build_index(field, Box::new(BTreeIndex::new()), op)
+    }
+
+    fn build_index(&self, field: &str, mut index: Box<dyn Index>, op: &OperationHandle) -> Result<()> {
         let _guard = self.writer.lock();
+        trace_start!(build_start);
 
-        let mut index = BTreeIndex::new();
         let docs = self.docs.read();
-        for (id, doc) in docs.iter() {
+        let total = docs.len();
+        op.checkpoint("create_index", "build", 0, total)?;
+        for (i, (id, doc)) in docs.iter().enumerate() {
             if let Some(val) = doc.get(field) {
                 index.insert(val, id);
             }
+            if (i + 1) % progress::CHECK_EVERY == 0 {
+                op.checkpoint("create_index", "build", i + 1, total)?;
+            }
         }
+        drop(docs);
+        op.checkpoint("create_index", "build", total, total)?;
+        trace_event!(
+            docs = total,
+            elapsed_ms = build_start.elapsed().as_millis() as u64,
+            "index built"
+        );
 
-        self.indexes
-            .write()
-            .insert(field.to_string(), Box::new(index));
+        self.indexes.write().insert(field.to_string(), index);
+        op.report("create_index", "done", total, total);
         Ok(())
     }
 
+    /// Create a full-text index on a field, for `$text` queries. String
+    /// values (and strings inside arrays) are split into lowercase words.
+    pub fn create_text_index(&self, field: &str) -> Result<()> {
+        self.create_text_index_with(field, &OperationHandle::new())
+    }
+
+    /// `create_text_index` with progress reports and cancellation.
+    pub fn create_text_index_with(&self, field: &str, op: &OperationHandle) -> Result<()> {
+        trace_span!("ndb.create_index", field, kind = "text");
+        self.build_index(field, Box::new(TextIndex::new()), op)
+    }
+
     /// Drop an index, freeing memory.
     pub fn drop_index(&self, field: &str) -> Result<()> {
         let mut indexes = self.indexes.write();
@@ -1273,33 +2665,118 @@ impl Database {
         self.indexes.read().contains_key(field)
     }
 
+    /// Kind, size and memory of every index, sorted by field.
+    pub fn index_status(&self) -> Vec<IndexStatus> {
+        let mut status: Vec<IndexStatus> = self
+            .indexes
+            .read()
+            .iter()
+            .map(|(field, index)| IndexStatus {
+                field: field.clone(),
+                kind: index.kind(),
+                entries: index.len(),
+                heap_bytes: index.heap_bytes(),
+            })
+            .collect();
+        status.sort_by(|a, b| a.field.cmp(&b.field));
+        status
+    }
+
     // ─── Compaction & Trash ────────────────────────────────────────
 
     /// Compact the database: rewrite active docs to a single file and discard any tombstones.
+    /// Writes continue while the new file is written and are carried over before the swap.
     pub fn compact(&self) -> Result<()> {
-        let _guard = self.writer.lock();
+        self.compact_with(&OperationHandle::new())
+    }
+
+    /// `compact` with progress reports and cancellation. Cancelling before
+    /// the swap removes the partial rewrite and keeps the current log.
+    pub fn compact_with(&self, op: &OperationHandle) -> Result<()> {
+        let _compacting = self.compacting.lock();
 
         if self.is_in_memory() {
+            op.report("compact", "done", 0, 0);
             return Ok(());
         }
+        let _timer = self.metrics.compaction_duration.time();
+        trace_span!("ndb.compact");
+        trace_start!(compact_start);
+
+        // Snapshot the store and the log length together. Appends are
+        // unbuffered, so everything past `offset` was written after the
+        // snapshot.
+        let (snapshot, keys, offset, records_before) = {
+            let _guard = self.writer.lock();
+            let snapshot: Vec<Value> = self.docs.read().values().cloned().collect();
+            let keys = self.idempotency_records();
+            let offset = fs::metadata(&self.path)
+                .map_err(Error::io_err(&self.path, "stat log before compaction"))?
+                .len();
+            (snapshot, keys, offset, self.metrics.log_records.load(Ordering::Relaxed))
+        };
+        let active: Vec<&Value> = snapshot.iter().chain(&keys).collect();
+        let total = active.len();
+        op.checkpoint("compact", "write", 0, total)?;
+
+        // Rewrite active docs without holding the writer lock. Tombstones in the old
+        // data.jsonl are permanently dropped, which is safe because `delete()` already
+        // archived the full documents into the persistent `_trash/docs/{dbname}.jsonl` file.
+        let tmp_path = storage::write_temp(&self.path, &active, |done| {
+            if done % progress::CHECK_EVERY == 0 {
+                op.checkpoint("compact", "write", done, total)
+            } else {
+                Ok(())
+            }
+        })?;
 
-        // Close file handle before rewrite
+        let _guard = self.writer.lock();
+        if let Err(e) = op.checkpoint("compact", "swap", total, total) {
+            let _ = fs::remove_file(&tmp_path);
+            return Err(e);
+        }
+        // Close file handle before the swap; the next append reopens it
         {
             let mut handle = self.file_handle.lock();
             *handle = None;
         }
+        // Carry over records written during the rewrite, then swap
+        storage::swap_with_tail(&self.path, &tmp_path, offset)?;
+        let carried = self.metrics.log_records.load(Ordering::Relaxed) - records_before;
+        self.metrics.log_records.store(total as u64 + carried, Ordering::Relaxed);
+        // The rewritten log is synced and holds every earlier write
+        self.syncer.synced_through(self.syncer.last_written());
+        trace_event!(
+            docs = total,
+            bytes = fs::metadata(&self.path).map(|m| m.len()).unwrap_or(0),
+            elapsed_ms = compact_start.elapsed().as_millis() as u64,
+            "log compacted"
+        );
 
-        let docs = self.docs.read();
-        let active: Vec<&Value> = docs.values().collect();
-
-        // Rewrite active docs. Tombstones in the old data.jsonl are permanently dropped, 
-        // which is safe because `delete()` already archived the full documents into 
-        // the persistent `_trash/docs/{dbname}.jsonl` file.
-        storage::rewrite_atomic(&self.path, &active)?;
+        metrics::Recorder::count(&self.metrics.compactions, 1);
+        metrics::Recorder::stamp(&self.metrics.last_compaction);
+        self.notify(|| Change::Compact);
+        op.report("compact", "done", total, total);
 
         Ok(())
     }
 
+    /// Idempotency keys still inside their window, as `_op: "idem"` records
+    /// that carry the key over into a compacted log. Replay ignores them;
+    /// `load` reads their `_idem` like any other record's.
+    fn idempotency_records(&self) -> Vec<Value> {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        self.idempotency
+            .lock()
+            .live(now)
+            .into_iter()
+            .map(|(key, id, at)| serde_json::json!({"_id": id, "_op": "idem", "_idem": {"key": key, "at": at}}))
+            .collect()
+    }
+
     /// Purge documents from the persistent trash file and files from the file trash 
     /// that are older than the configured TTL (or all if duration is ZERO).
     pub fn purge_trash(&self) -> Result<usize> {
@@ -1345,7 +2822,7 @@ impl Database {
                     if let Ok(file_type) = entry.file_type() {
                         if file_type.is_dir() {
                             let bucket = self.bucket(&entry.file_name().to_string_lossy());
-                            let _ = bucket.purge_trash_ttl(ttl);
+                            self.warnings.check(WarningKind::TrashPurge, "purge file trash", bucket.purge_trash_ttl(ttl));
                         }
                     }
                 }
@@ -1383,7 +2860,7 @@ impl Database {
             // Find only buckets natively active in this DB
             for entry in fs::read_dir(&buckets_src).map_err(Error::io_err(&buckets_src, "read buckets dir"))? {
                 let entry = entry.map_err(Error::io_err(&buckets_src, "read bucket entry"))?;
-                if entry.file_type().map_or(false, |t| t.is_dir()) {
+                if entry.file_type().is_ok_and(|t| t.is_dir()) {
                     let bucket_name = entry.file_name();
                     let dst_bucket = buckets_dst.join(&bucket_name);
                     fs::create_dir_all(&dst_bucket).map_err(Error::io_err(&dst_bucket, "create snapshot bucket"))?;
@@ -1392,7 +2869,7 @@ impl Database {
                     for file_entry in fs::read_dir(entry.path()).map_err(Error::io_err(&entry.path(), "read bucket files"))? {
                         let f = file_entry.map_err(Error::io_err(&entry.path(), "read active binary"))?;
                         let file_name = f.file_name();
-                        if file_name != "_trash" && f.file_type().map_or(false, |t| t.is_file()) {
+                        if file_name != "_trash" && f.file_type().is_ok_and(|t| t.is_file()) {
                             fs::copy(f.path(), dst_bucket.join(&file_name)).map_err(Error::io_err(f.path(), "copy file to snapshot"))?;
                         }
                     }
@@ -1420,7 +2897,7 @@ impl Database {
 
     /// Restore a deleted document from trash by ID.
     pub fn restore(&self, id: &str) -> Result<()> {
-        let _guard = self.writer.lock();
+        let guard = self.writer.lock();
 
         if self.is_in_memory() {
             return Err(Error::invalid_arg("cannot restore in in-memory database"));
@@ -1444,7 +2921,8 @@ impl Database {
                 if let Some(s) = f.as_str() {
                     if let Some(file_ref) = FileRef::from_compact(s) {
                         let bucket = self.bucket(&file_ref.bucket);
-                        let _ = bucket.restore(&file_ref.id, &file_ref.ext);
+                        let restored = bucket.restore(&file_ref.id, &file_ref.ext);
+                        self.warnings.check(WarningKind::FileRestore, &format!("restore file {}", s), restored);
                     }
                 }
             }
@@ -1456,7 +2934,8 @@ impl Database {
             obj.remove("_trashed_files");
         }
 
-        let doc = trash_doc;
+        let mut doc = trash_doc;
+        bump_version(&mut doc);
 
         // Restore file reference counters
         let mut extracted_file_refs = HashSet::new();
@@ -1468,27 +2947,261 @@ impl Database {
             }
         }
 
-        // Append restored doc to file
-        let line = serde_json::to_string(&doc)?;
-        let mut handle = self.get_file_handle()?;
-        if let Some(ref mut file) = *handle {
-            match self.persistence {
-                Persistence::Immediate => {
-                    storage::append_line_sync(file, &self.path, &line)?;
-                }
-                _ => {
-                    storage::append_line(file, &self.path, &line)?;
+        // Append restored doc to file
+        let line = serde_json::to_string(&doc)?;
+        self.append_record(&line)?;
+
+        self.reindex(id, &Value::Null, &doc);
+
+        // Update in-memory state
+        self.deleted.write().remove(id);
+        self.docs.write().insert(id.to_string(), doc);
+
+        self.notify(|| Change::Insert { id: id.to_string(), doc: self.docs.read()[id].clone() });
+
+        drop(guard);
+        self.await_sync()?;
+
+        Ok(())
+    }
+
+    /// Number of records in the current log file, i.e. the `seq` that
+    /// `open_at` / `restore_to` would reproduce the present state from.
+    pub fn log_seq(&self) -> Result<u64> {
+        if self.is_in_memory() {
+            return Err(Error::invalid_arg("in-memory database has no log"));
+        }
+        let _guard = self.writer.lock();
+        Ok(storage::read_all(&self.path)?.len() as u64)
+    }
+
+    /// Roll every document back to its state as of log record `seq`.
+    ///
+    /// Differences are appended as one batch record (full documents and
+    /// tombstones), so the rollback is applied whole or not at all, is itself
+    /// part of history and can be undone with another `restore_to`. Removed
+    /// documents go to the trash like any delete. Returns the number of
+    /// documents changed.
+    pub fn restore_to(&self, seq: u64) -> Result<usize> {
+        let guard = self.writer.lock();
+
+        if self.is_in_memory() {
+            return Err(Error::invalid_arg("cannot restore_to in in-memory database"));
+        }
+
+        let (target, _) = replay_prefix(&self.path, seq)?;
+        let current = self.docs.read().clone();
+
+        let mut ids: Vec<&String> = current.keys().chain(target.keys()).collect();
+        ids.sort();
+        ids.dedup();
+
+        // Documents kept or brought back first, removals last, so a file
+        // shared between the two never drops to zero references on the way
+        let mut ops = Vec::new();
+        let mut removed = Vec::new();
+        for id in ids {
+            let old_doc = current.get(id);
+            match (old_doc, target.get(id)) {
+                (None, None) => continue,
+                (Some(a), Some(b)) if same_content(a, b) => continue,
+                (Some(_), None) => {
+                    removed.push(batch::BatchOp::Delete(id.clone()));
+                    continue;
+                }
+                _ => {}
+            }
+            // Rolled-back documents move forward in version so stale conditional writes still conflict
+            let mut doc = target[id].clone();
+            let version = old_doc.map(doc_version).unwrap_or(0).max(doc_version(&doc)) + 1;
+            set_version(&mut doc, version);
+            ops.push(match old_doc {
+                Some(_) => batch::BatchOp::Update(id.clone(), doc),
+                None => batch::BatchOp::Insert(doc),
+            });
+        }
+        ops.append(&mut removed);
+        if ops.is_empty() {
+            return Ok(0);
+        }
+
+        // One record, so a crash or failed append leaves no partial rollback.
+        // Removed documents are archived like any delete, since compaction
+        // drops the tombstone.
+        let now = batch::unix_now();
+        batch::persist(self, &ops, now)?;
+
+        // Bring back bucket files the target state references again
+        for op in &ops {
+            let (batch::BatchOp::Insert(doc) | batch::BatchOp::Update(_, doc)) = op else {
+                continue;
+            };
+            let mut refs = HashSet::new();
+            Self::extract_file_refs(doc, &mut refs);
+            for r in &refs {
+                if !self.file_refs.read().contains_key(r) {
+                    if let Some(file_ref) = FileRef::from_compact(r) {
+                        let restored = self.bucket(&file_ref.bucket).restore(&file_ref.id, &file_ref.ext);
+                        self.warnings.check(WarningKind::FileRestore, &format!("restore file {}", r), restored);
+                    }
+                }
+            }
+        }
+        let changed = ops.len();
+        batch::apply(self, ops, now);
+
+        drop(guard);
+        self.await_sync()?;
+
+        Ok(changed)
+    }
+
+    // ─── Verification ──────────────────────────────────────────────
+
+    /// Check the log and, at `VerifyLevel::Full`, the live state derived from it.
+    ///
+    /// Every problem is collected into the report rather than returned as an
+    /// error; `Err` means the log could not be read at all. Writes are blocked
+    /// while the log is scanned so it can be compared against memory. In-memory
+    /// databases have no log and only get the index check.
+    pub fn verify(&self, level: VerifyLevel) -> Result<VerifyReport> {
+        let _guard = self.writer.lock();
+
+        let (mut report, replayed) = if self.is_in_memory() {
+            (VerifyReport::default(), None)
+        } else {
+            let scan = verify::scan_log(&self.path)?;
+            (scan.report, Some(scan.docs))
+        };
+        if level == VerifyLevel::Quick {
+            return Ok(report);
+        }
+
+        let indexes = self.indexes.read();
+        let docs = self.docs.read();
+        report.documents = docs.len();
+
+        if let Some(replayed) = replayed {
+            for (id, doc) in docs.iter() {
+                match replayed.get(id) {
+                    Some(logged) if logged == doc => {}
+                    Some(_) => report.push(IssueKind::StateMismatch, None, Some(id), "replayed document differs from memory"),
+                    None => report.push(IssueKind::StateMismatch, None, Some(id), "document is not in the log"),
+                }
+            }
+            for id in replayed.keys().filter(|id| !docs.contains_key(*id)) {
+                report.push(IssueKind::StateMismatch, None, Some(id), "logged document is missing from memory");
+            }
+        }
+
+        for (field, index) in indexes.iter() {
+            let mut indexed = 0;
+            for (id, doc) in docs.iter() {
+                if let Some(val) = doc.get(field) {
+                    if index.contains(val, id) {
+                        indexed += 1;
+                    } else {
+                        report.push(IssueKind::IndexMismatch, None, Some(id), format!("missing from index on '{}'", field));
+                    }
+                }
+            }
+            // Each document holds at most one entry, so any surplus is stale
+            let stale = index.len().saturating_sub(indexed);
+            if stale > 0 {
+                report.push(IssueKind::IndexMismatch, None, None, format!("{} stale entries in index on '{}'", stale, field));
+            }
+        }
+
+        if !self.is_in_memory() {
+            for (id, doc) in docs.iter() {
+                let mut refs = Vec::new();
+                verify::collect_file_refs(doc, &mut refs);
+                for r in refs {
+                    if !self.bucket(&r.bucket).exists(&r) {
+                        report.push(IssueKind::MissingFile, None, Some(id), format!("missing file {}", r.to_string_compact()));
+                    }
                 }
             }
+            verify::scan_trash(&mut report, &self.trash_doc_path())?;
         }
 
-        // Update in-memory state
-        self.deleted.write().remove(id);
-        self.docs.write().insert(id.to_string(), doc);
+        Ok(report)
+    }
 
+    /// Cheap readiness check, e.g. for a health endpoint: the log can still
+    /// be opened for appending, `meta.json` (if any) still parses, and no
+    /// background fsync has failed. Returns the first problem found.
+    ///
+    /// Unlike `verify` it reads no documents and never blocks writers.
+    pub fn health(&self) -> Result<()> {
+        if let Some(e) = self.syncer.error() {
+            return Err(Error::io_err(&self.path, "background fsync")(e));
+        }
+        if self.is_in_memory() {
+            return Ok(());
+        }
+        // Not `open_for_append`: that may write, and a writer could be mid-line
+        fs::OpenOptions::new()
+            .append(true)
+            .open(&self.path)
+            .map_err(Error::io_err(&self.path, "open JSONL for append"))?;
+        Schema::load(&self.base_dir)?;
         Ok(())
     }
 
+    // ─── Metrics ───────────────────────────────────────────────────
+
+    /// Snapshot of document gauges, operation counters and latency histograms.
+    ///
+    /// Counters are per process and start at zero on open. Render with
+    /// `Metrics::to_prometheus()` for a scrape endpoint.
+    pub fn metrics(&self) -> Metrics {
+        let mut snapshot = self.metrics.snapshot();
+        snapshot.indexes = self.indexes.read().len();
+        snapshot.documents = self.docs.read().len();
+        snapshot.deleted = self.deleted.read().len();
+        snapshot.warnings = self.warnings.total();
+        if !self.is_in_memory() {
+            snapshot.log_bytes = fs::metadata(&self.path).map(|m| m.len()).unwrap_or(0);
+            snapshot.trash_bytes = fs::metadata(self.trash_doc_path()).map(|m| m.len()).unwrap_or(0);
+        }
+        snapshot
+    }
+
+    /// Drain the warnings recorded since the last call, oldest first.
+    ///
+    /// Best-effort side effects (archiving a deleted document, moving its
+    /// files to the trash, background purges and fsyncs) do not fail the
+    /// call that triggered them; their failures land here instead. Up to
+    /// 256 are kept; `Metrics::warnings` counts all of them.
+    pub fn take_warnings(&self) -> Vec<Warning> {
+        self.warnings.take()
+    }
+
+    /// Estimated heap use of documents, indexes and bookkeeping. Walks every
+    /// document, so call it periodically rather than per request.
+    pub fn memory_usage(&self) -> MemoryUsage {
+        let documents = self
+            .docs
+            .read()
+            .iter()
+            .map(|(id, doc)| string_bytes(id) + std::mem::size_of::<Value>() + value_heap_bytes(doc))
+            .sum();
+        let indexes = self.indexes.read().values().map(|index| index.heap_bytes()).sum();
+        let deleted: usize = self.deleted.read().iter().map(|id| string_bytes(id)).sum();
+        let file_refs: usize = self
+            .file_refs
+            .read()
+            .keys()
+            .map(|uri| string_bytes(uri) + std::mem::size_of::<usize>())
+            .sum();
+        MemoryUsage {
+            documents,
+            indexes,
+            other: deleted + file_refs,
+        }
+    }
+
     // ─── Persistence ───────────────────────────────────────────────
 
     /// Explicitly flush pending writes to disk.
@@ -1496,14 +3209,24 @@ impl Database {
         if self.is_in_memory() {
             return Ok(());
         }
+        trace_span!("ndb.flush");
 
         let mut handle = self.file_handle.lock();
         if let Some(ref mut file) = *handle {
+            let _timer = self.metrics.fsync_latency.time();
+            trace_start!(sync_start);
             file.flush()
                 .map_err(Error::io_err(&self.path, "flush"))?;
             file.sync_all()
                 .map_err(Error::io_err(&self.path, "fsync"))?;
+            trace_event!(sync_us = sync_start.elapsed().as_micros() as u64, "log synced");
+            self.syncer.synced_through(self.syncer.last_written());
         }
+        drop(handle);
+
+        metrics::Recorder::count(&self.metrics.flushes, 1);
+        metrics::Recorder::stamp(&self.metrics.last_flush);
+        self.notify(|| Change::Flush);
 
         Ok(())
     }
@@ -1513,6 +3236,38 @@ impl Database {
         &self.path
     }
 
+    // ─── Change Feed ───────────────────────────────────────────────
+
+    /// Subscribe to the change feed. Every subsequent write, flush and
+    /// compaction is delivered on the returned receiver, in write order.
+    /// Dropping the receiver unsubscribes.
+    pub fn subscribe(&self) -> mpsc::Receiver<ChangeEvent> {
+        let (tx, rx) = mpsc::channel();
+        self.subscribers.lock().push(tx);
+        rx
+    }
+
+    /// Sequence number of the most recent change (0 if nothing changed since open).
+    pub fn change_seq(&self) -> u64 {
+        self.change_seq.load(Ordering::SeqCst)
+    }
+
+    /// Publish a change. The event is only built when someone is listening.
+    fn notify(&self, change: impl FnOnce() -> Change) {
+        let mut subscribers = self.subscribers.lock();
+        let seq = self.change_seq.fetch_add(1, Ordering::SeqCst) + 1;
+        if subscribers.is_empty() {
+            return;
+        }
+        let event = ChangeEvent { seq, change: change() };
+        subscribers.retain(|tx| tx.send(event.clone()).is_ok());
+    }
+
+    /// Publish the current state of a document as an `Update`.
+    fn notify_update(&self, id: &str) {
+        self.notify(|| Change::Update { id: id.to_string(), doc: self.docs.read()[id].clone() });
+    }
+
     // ─── File Buckets ──────────────────────────────────────────────
 
     /// Get or create a named file bucket for binary storage.
@@ -1557,7 +3312,8 @@ impl Database {
     /// Perform a full maintenance garbage collection of all buckets.
     /// Traverses all active documents, records `FileRef` patterns,
     /// then sweeps all buckets moving unreferenced physical files to trash.
-    /// Returns the number of files moved to trash.
+    /// Also deletes `.tmp` files left by interrupted stores, so run it while
+    /// no `store` is in flight. Returns the number of files moved to trash.
     pub fn gc_buckets(&self) -> Result<usize> {
         let mut active_refs = HashSet::new();
         
@@ -1579,6 +3335,7 @@ impl Database {
                     let bucket_name = entry.file_name().to_string_lossy().to_string();
                     let bkt = self.bucket(&bucket_name);
                     
+                    self.warnings.check(WarningKind::TempCleanup, "remove bucket temp files", bkt.remove_temp_files());
                     if let Ok(files) = bkt.list() {
                         for filename in files {
                             let ref_str = format!("{}:{}", bucket_name, filename);
@@ -1592,7 +3349,8 @@ impl Database {
                                         id,
                                         ext,
                                     };
-                                    let _ = bkt.delete(&file_ref); // Ignore deletion errors here
+                                    let deleted = bkt.delete(&file_ref);
+                                    self.warnings.check(WarningKind::FileCleanup, &format!("trash unreferenced file {}", ref_str), deleted);
                                     trashed_count += 1;
                                 }
                             }
@@ -1607,14 +3365,12 @@ impl Database {
 
     fn extract_file_refs(value: &Value, refs: &mut HashSet<String>) {
         match value {
-            Value::String(s) => {
-                // Heuristic: looks like "bucket:hash.ext"
-                // E.g. "images:a1b2c3d4.png"
-                if s.contains(':') && s.contains('.') {
-                    let parts: Vec<&str> = s.splitn(2, ':').collect();
-                    if parts.len() == 2 && parts[1].len() >= 8 {
-                        refs.insert(s.to_string());
-                    }
+            // Heuristic: looks like "bucket:hash.ext"
+            // E.g. "images:a1b2c3d4.png"
+            Value::String(s) if s.contains(':') && s.contains('.') => {
+                let parts: Vec<&str> = s.splitn(2, ':').collect();
+                if parts.len() == 2 && parts[1].len() >= 8 {
+                    refs.insert(s.to_string());
                 }
             }
             Value::Array(a) => {
@@ -1631,6 +3387,90 @@ impl Database {
         }
     }
 
+    /// Take `n` write tokens, or fail with `Throttled` if the write rate is exceeded.
+    fn throttle(&self, n: usize) -> Result<()> {
+        match &self.write_bucket {
+            Some(bucket) => bucket.take(n).map_err(|retry_after| Error::Throttled { retry_after }),
+            None => Ok(()),
+        }
+    }
+
+    /// Validate a document against the size limits and the schema, if configured.
+    fn check_doc(&self, doc: &Value) -> Result<()> {
+        if let (Some(limit), Some(id)) = (self.limits.max_id_len, doc.get("_id").and_then(Value::as_str)) {
+            if id.len() > limit {
+                return Err(Error::limit("_id", limit, id.len()));
+            }
+        }
+        if let Some(limit) = self.limits.max_doc_bytes {
+            let size = json_len(doc);
+            if size > limit {
+                // Name the biggest top-level field: usually the one to trim
+                let field = doc
+                    .as_object()
+                    .and_then(|o| o.iter().max_by_key(|(_, v)| json_len(v)))
+                    .map(|(k, _)| k.as_str())
+                    .unwrap_or("");
+                return Err(Error::limit(field, limit, size));
+            }
+        }
+        match &self.schema {
+            Some(schema) => schema.validate(doc),
+            None => Ok(()),
+        }
+    }
+
+    /// Move a document's entries in every secondary index from `old_doc`'s
+    /// values to `new_doc`'s. Must not be called while holding `docs`.
+    fn reindex(&self, id: &str, old_doc: &Value, new_doc: &Value) {
+        let mut indexes = self.indexes.write();
+        for (field, index) in indexes.iter_mut() {
+            let (old_val, new_val) = (old_doc.get(field), new_doc.get(field));
+            if old_val == new_val {
+                continue;
+            }
+            if let Some(val) = old_val {
+                index.remove(val, id);
+            }
+            if let Some(val) = new_val {
+                index.insert(val, id);
+            }
+        }
+    }
+
+    /// Side effects of deleting `doc`: release its file refs and trash the
+    /// files left unreferenced, archive it to the document trash for
+    /// `restore`, and drop it from the indexes. Failures become warnings.
+    /// Must not be called while holding `docs`.
+    fn trash_deleted_doc(&self, id: &str, mut doc: Value, now: u64) {
+        let orphaned_files = self.decrement_file_refs(&doc);
+        for f in &orphaned_files {
+            if let Some(file_ref) = FileRef::from_compact(f) {
+                let bucket = self.bucket(&file_ref.bucket);
+                self.warnings.check(WarningKind::FileCleanup, &format!("trash file {}", f), bucket.delete(&file_ref));
+            }
+        }
+
+        let mut indexes = self.indexes.write();
+        for (field, index) in indexes.iter_mut() {
+            if let Some(val) = doc.get(field) {
+                index.remove(val, id);
+            }
+        }
+        drop(indexes);
+
+        if !self.is_in_memory() && self.trash_mode != TrashMode::Off {
+            if let Some(obj) = doc.as_object_mut() {
+                obj.insert("_deleted".to_string(), serde_json::json!(now));
+                if !orphaned_files.is_empty() {
+                    obj.insert("_trashed_files".to_string(), serde_json::json!(orphaned_files));
+                }
+            }
+            let archived = storage::append_doc_trash(&self.trash_doc_path(), &doc);
+            self.warnings.check(WarningKind::TrashArchive, &format!("archive deleted document {}", id), archived);
+        }
+    }
+
     /// Diff file refs between old and new state, update counters, and trash orphaned.
     fn handle_ref_delta_and_trash(&self, old_doc: &Value, new_doc: &Value) {
         let mut old_refs = HashSet::new();
@@ -1661,7 +3501,7 @@ impl Database {
         for f in &orphaned_files {
             if let Some(file_ref) = FileRef::from_compact(f) {
                 let bucket = self.bucket(&file_ref.bucket);
-                let _ = bucket.delete(&file_ref);
+                self.warnings.check(WarningKind::FileCleanup, &format!("trash file {}", f), bucket.delete(&file_ref));
             }
         }
     }
@@ -1719,6 +3559,7 @@ impl Drop for Database {
         if let Some(handle) = self.ttl_thread.lock().take() {
             let _ = handle.join();
         }
+        self.stop_sync_thread();
 
         // Flush any pending writes if lazy
         let _ = self.flush();
@@ -2171,6 +4012,26 @@ mod tests {
         assert_eq!(bob.len(), 1);
     }
 
+    #[test]
+    fn index_contains_agrees_with_get() {
+        let indexes: Vec<Box<dyn Index>> = vec![
+            Box::new(HashIndex::new()),
+            Box::new(BTreeIndex::new()),
+            Box::new(TextIndex::new()),
+        ];
+        for mut index in indexes {
+            index.insert(&json!("red fox"), "a");
+            index.insert(&json!("red fox"), "b");
+            index.insert(&json!("blue"), "c");
+            for value in [json!("red fox"), json!("blue"), json!("green")] {
+                for id in ["a", "b", "c", "d"] {
+                    let expected = index.get(&value).iter().any(|x| x == id);
+                    assert_eq!(index.contains(&value, id), expected, "{} {} {}", index.kind(), value, id);
+                }
+            }
+        }
+    }
+
     // ─── Flush ─────────────────────────────────────────────────────
 
     #[test]
@@ -2187,6 +4048,34 @@ mod tests {
         assert_eq!(db2.len(), 1);
     }
 
+    #[test]
+    fn group_commit_waiters_survive_failed_append() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("gc.jsonl");
+        let db = Database::open(&path)
+            .unwrap()
+            .with_persistence(Persistence::GroupCommit(Duration::from_millis(200)));
+        let wait = || {
+            let syncer = Arc::clone(&db.syncer);
+            let (tx, rx) = mpsc::channel();
+            std::thread::spawn(move || tx.send(syncer.wait()));
+            rx.recv_timeout(Duration::from_secs(5)).expect("group commit wait hung")
+        };
+
+        // A record is pending when a later append fails and drops the handle
+        db.append_record(r#"{"_id":"a"}"#).unwrap();
+        *db.file_handle.lock() = None;
+        assert!(wait().is_ok());
+
+        // If the log cannot be reopened either, waiters fail instead of hanging
+        db.append_record(r#"{"_id":"b"}"#).unwrap();
+        *db.file_handle.lock() = None;
+        fs::remove_file(&path).unwrap();
+        fs::create_dir(&path).unwrap();
+        assert!(wait().is_err());
+        assert!(db.health().is_err());
+    }
+
     // ─── Edge Cases ────────────────────────────────────────────────
 
     #[test]
@@ -2312,6 +4201,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::approx_constant)]
     fn set_type_variety() {
         let (db, _dir) = test_db();
         let id = db.insert(json!({"s": "", "n": 0, "b": false, "a": [], "o": {}})).unwrap();
@@ -2797,4 +4687,368 @@ mod tests {
         let doc = db.get(&id).unwrap();
         assert_eq!(doc["items"], json!([]));
     }
+
+    // ─── Document Versions ───────────────────────────────────────────
+
+    #[test]
+    fn every_write_bumps_version() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("versions.jsonl");
+        let db = Database::open(&path).unwrap();
+        let id = db.insert(json!({"n": 1, "tags": []})).unwrap();
+        assert_eq!(db.version(&id).unwrap(), 1);
+
+        db.update(&id, json!({"n": 2, "tags": [], "_version": 99})).unwrap();
+        db.set(&id, "n", json!(3)).unwrap();
+        db.array_push(&id, "tags", json!("a")).unwrap();
+        db.remove(&id, "n").unwrap();
+        assert_eq!(db.get(&id).unwrap()["_version"], 5);
+        drop(db);
+
+        // Patch replay bumps the same way, and compaction keeps the field
+        let db = Database::open(&path).unwrap();
+        assert_eq!(db.version(&id).unwrap(), 5);
+        db.compact().unwrap();
+        drop(db);
+        assert_eq!(Database::open(&path).unwrap().version(&id).unwrap(), 5);
+    }
+
+    #[test]
+    fn conditional_writes_detect_conflicts() {
+        let (db, _dir) = test_db();
+        let id = db.insert(json!({"n": 1})).unwrap();
+
+        assert_eq!(db.update_if_version(&id, json!({"n": 2}), 1).unwrap(), 2);
+        match db.update_if_version(&id, json!({"n": 3}), 1) {
+            Err(Error::VersionConflict { expected, actual, .. }) => assert_eq!((expected, actual), (1, 2)),
+            other => panic!("expected conflict, got {:?}", other),
+        }
+        assert_eq!(db.get(&id).unwrap()["n"], 2);
+
+        assert!(matches!(db.delete_if_version(&id, 1), Err(Error::VersionConflict { .. })));
+        assert!(db.contains(&id));
+        db.delete_if_version(&id, 2).unwrap();
+        assert!(matches!(db.version(&id), Err(Error::NotFound { .. })));
+    }
+
+    #[test]
+    fn restore_moves_version_forward() {
+        let (db, _dir) = test_db();
+        let id = db.insert(json!({"n": 1})).unwrap();
+        db.delete(&id).unwrap();
+        db.restore(&id).unwrap();
+        assert_eq!(db.version(&id).unwrap(), 2);
+    }
+
+    // ─── Batch Inserts ───────────────────────────────────────────────
+
+    #[test]
+    fn insert_batch_returns_ids_in_order() {
+        let (db, _dir) = test_db();
+        let ids = db.insert_batch(vec![json!({"n": 0}), json!({"n": 1}), json!({"n": 2})]).unwrap();
+        assert_eq!(ids.len(), 3);
+        for (i, id) in ids.iter().enumerate() {
+            assert_eq!(db.get(id).unwrap()["n"], i);
+        }
+    }
+
+    #[test]
+    fn insert_batch_empty_is_noop() {
+        let (db, _dir) = test_db();
+        assert!(db.insert_batch(Vec::new()).unwrap().is_empty());
+        assert_eq!(db.len(), 0);
+    }
+
+    #[test]
+    fn insert_batch_rejects_non_objects_without_writing() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("batch_reject.jsonl");
+        let db = Database::open(&path).unwrap();
+        assert!(db.insert_batch(vec![json!({"ok": true}), json!(42)]).is_err());
+        assert_eq!(db.len(), 0);
+        drop(db);
+        assert_eq!(Database::open(&path).unwrap().len(), 0);
+    }
+
+    #[test]
+    fn insert_batch_persists_and_replays() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("batch.jsonl");
+        let db = Database::open(&path).unwrap();
+        let ids = db.insert_batch(vec![json!({"a": 1}), json!({"b": 2})]).unwrap();
+        db.set(&ids[0], "a", json!(10)).unwrap();
+        drop(db);
+
+        let db2 = Database::open(&path).unwrap();
+        assert_eq!(db2.len(), 2);
+        assert_eq!(db2.get(&ids[0]).unwrap()["a"], 10);
+        assert_eq!(db2.get(&ids[1]).unwrap()["b"], 2);
+    }
+
+    #[test]
+    fn insert_batch_torn_write_discards_whole_batch() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("batch_torn.jsonl");
+        let db = Database::open(&path).unwrap();
+        let kept = db.insert(json!({"before": true})).unwrap();
+        db.insert_batch(vec![json!({"x": 1}), json!({"x": 2})]).unwrap();
+        drop(db);
+
+        // Chop the batch line mid-way, as a crash during append would
+        let content = fs::read_to_string(&path).unwrap();
+        let batch_start = content.find("\"_op\":\"batch\"").unwrap();
+        fs::write(&path, &content[..batch_start + 20]).unwrap();
+
+        let db2 = Database::open(&path).unwrap();
+        assert_eq!(db2.len(), 1);
+        assert!(db2.get(&kept).is_ok());
+    }
+
+    #[test]
+    fn insert_batch_updates_indexes() {
+        let (db, _dir) = test_db();
+        db.create_index("kind").unwrap();
+        db.insert_batch(vec![json!({"kind": "a"}), json!({"kind": "b"}), json!({"kind": "a"})]).unwrap();
+        assert_eq!(db.find("kind", &json!("a")).len(), 2);
+    }
+
+    #[test]
+    fn compact_flattens_batches() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("batch_compact.jsonl");
+        let db = Database::open(&path).unwrap();
+        let ids = db.insert_batch(vec![json!({"v": 1}), json!({"v": 2})]).unwrap();
+        db.compact().unwrap();
+        drop(db);
+
+        let content = fs::read_to_string(&path).unwrap();
+        assert!(!content.contains("\"_op\":\"batch\""));
+        let db2 = Database::open(&path).unwrap();
+        assert_eq!(db2.get(&ids[1]).unwrap()["v"], 2);
+    }
+
+    #[test]
+    fn compact_keeps_writes_made_during_rewrite() {
+        use std::sync::Arc;
+
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("online_compact.jsonl");
+        let db = Arc::new(Database::open(&path).unwrap());
+        let kept = db.insert(json!({"v": 1})).unwrap();
+        let changed = db.insert(json!({"v": 1})).unwrap();
+        let dropped = db.insert(json!({"v": 1})).unwrap();
+        db.delete(&dropped).unwrap();
+
+        // Writes from the progress callback would deadlock if compaction
+        // held the writer lock while rewriting
+        let added = Arc::new(Mutex::new(None));
+        let op = {
+            let db = Arc::clone(&db);
+            let (changed, kept, added) = (changed.clone(), kept.clone(), Arc::clone(&added));
+            OperationHandle::new().on_progress(move |p| {
+                if p.phase == "write" && p.done == 0 {
+                    db.update(&changed, json!({"v": 2})).unwrap();
+                    db.delete(&kept).unwrap();
+                    *added.lock() = Some(db.insert(json!({"v": 3})).unwrap());
+                }
+            })
+        };
+        db.compact_with(&op).unwrap();
+        drop(op);
+        let added = added.lock().take().unwrap();
+        db.set(&added, "v", json!(4)).unwrap();
+
+        let content = fs::read_to_string(&path).unwrap();
+        assert!(!content.contains(&dropped));
+        drop(db);
+
+        let db = Database::open(&path).unwrap();
+        assert_eq!(db.len(), 2);
+        assert!(!db.contains(&kept));
+        assert_eq!(db.get(&changed).unwrap()["v"], 2);
+        assert_eq!(db.get(&added).unwrap()["v"], 4);
+    }
+
+    #[test]
+    fn panicking_callbacks_leave_database_usable() {
+        use std::panic::{catch_unwind, AssertUnwindSafe};
+
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("panics.jsonl");
+        let db = Database::open(&path).unwrap();
+        let id = db.insert(json!({"v": 1})).unwrap();
+        let boom = || OperationHandle::new().on_progress(|_| panic!("callback panicked"));
+
+        // Each of these runs user code while holding the writer or docs lock
+        assert!(catch_unwind(AssertUnwindSafe(|| db.find_where("v", |_| panic!("predicate panicked")))).is_err());
+        assert!(catch_unwind(AssertUnwindSafe(|| db.create_index_with("v", &boom()))).is_err());
+        assert!(catch_unwind(AssertUnwindSafe(|| db.compact_with(&boom()))).is_err());
+        assert!(catch_unwind(AssertUnwindSafe(|| db.query_cancellable(json!({}), Default::default(), &boom()))).is_err());
+
+        assert!(!db.has_index("v"));
+        db.update(&id, json!({"v": 2})).unwrap();
+        let other = db.insert(json!({"v": 3})).unwrap();
+        db.create_index("v").unwrap();
+        assert_eq!(db.find("v", &json!(3)).len(), 1);
+        db.compact().unwrap();
+        drop(db);
+
+        let db = Database::open(&path).unwrap();
+        assert_eq!(db.get(&id).unwrap()["v"], 2);
+        assert!(db.contains(&other));
+    }
+
+    // ─── Change Feed ─────────────────────────────────────────────────
+
+    #[test]
+    fn subscribe_receives_changes_in_order() {
+        let (db, _dir) = test_db();
+        let rx = db.subscribe();
+        let id = db.insert(json!({"v": 1})).unwrap();
+        db.update(&id, json!({"v": 2})).unwrap();
+        db.array_push(&id, "tags", json!("a")).unwrap();
+        db.delete(&id).unwrap();
+
+        let events: Vec<ChangeEvent> = rx.try_iter().collect();
+        assert_eq!(events.iter().map(|e| e.seq).collect::<Vec<_>>(), vec![1, 2, 3, 4]);
+        assert!(matches!(&events[0].change, Change::Insert { id: i, doc } if i == &id && doc["v"] == 1));
+        assert!(matches!(&events[1].change, Change::Update { doc, .. } if doc["v"] == 2));
+        assert!(matches!(&events[2].change, Change::Update { doc, .. } if doc["tags"] == json!(["a"])));
+        assert_eq!(events[3].change, Change::Delete { id: id.clone() });
+        assert_eq!(db.change_seq(), 4);
+    }
+
+    #[test]
+    fn subscribe_sees_flush_compact_and_restore() {
+        let (db, _dir) = test_db();
+        let id = db.insert(json!({"v": 1})).unwrap();
+        db.delete(&id).unwrap();
+        let rx = db.subscribe();
+        db.restore(&id).unwrap();
+        db.flush().unwrap();
+        db.compact().unwrap();
+
+        let changes: Vec<Change> = rx.try_iter().map(|e| e.change).collect();
+        assert!(matches!(&changes[0], Change::Insert { id: i, .. } if i == &id));
+        assert_eq!(changes[1..], [Change::Flush, Change::Compact]);
+    }
+
+    #[test]
+    fn subscribe_batch_emits_one_insert_per_doc() {
+        let db = Database::open_in_memory().unwrap();
+        let rx = db.subscribe();
+        let ids = db.insert_batch(vec![json!({"n": 1}), json!({"n": 2})]).unwrap();
+        let got: Vec<String> = rx
+            .try_iter()
+            .map(|e| match e.change {
+                Change::Insert { id, .. } => id,
+                other => panic!("unexpected change {:?}", other),
+            })
+            .collect();
+        assert_eq!(got, ids);
+    }
+
+    #[test]
+    fn dropped_subscriber_is_pruned() {
+        let db = Database::open_in_memory().unwrap();
+        let rx = db.subscribe();
+        drop(rx);
+        db.insert(json!({"a": 1})).unwrap();
+        assert!(db.subscribers.lock().is_empty());
+    }
+
+    #[test]
+    fn change_event_serializes_with_kind_tag() {
+        let event = ChangeEvent { seq: 7, change: Change::Delete { id: "abc".into() } };
+        assert_eq!(serde_json::to_value(&event).unwrap(), json!({"seq": 7, "kind": "delete", "id": "abc"}));
+    }
+
+    // ─── Verification ──────────────────────────────────────────────
+
+    #[test]
+    fn verify_detects_index_drift() {
+        let db = Database::open_in_memory().unwrap();
+        db.create_index("n").unwrap();
+        let a = db.insert(json!({"n": 1})).unwrap();
+        db.insert(json!({"n": 2})).unwrap();
+        assert!(db.verify(VerifyLevel::Full).unwrap().is_ok());
+
+        {
+            let mut indexes = db.indexes.write();
+            let index = indexes.get_mut("n").unwrap();
+            index.remove(&json!(1), &a);
+            index.insert(&json!(5), "gone");
+        }
+        let report = db.verify(VerifyLevel::Full).unwrap();
+        assert_eq!(report.count(IssueKind::IndexMismatch), 2);
+        assert_eq!(report.issues.iter().filter(|i| i.id.as_deref() == Some(a.as_str())).count(), 1);
+        assert!(db.verify(VerifyLevel::Quick).unwrap().is_ok());
+    }
+
+    // ─── Tracing ───────────────────────────────────────────────────
+
+    /// Records span names and event messages.
+    #[cfg(feature = "tracing")]
+    #[derive(Default)]
+    struct Recorder {
+        seen: Mutex<Vec<String>>,
+        next_id: AtomicU64,
+    }
+
+    #[cfg(feature = "tracing")]
+    impl tracing::Subscriber for Recorder {
+        fn enabled(&self, _: &tracing::Metadata<'_>) -> bool {
+            true
+        }
+        fn new_span(&self, span: &tracing::span::Attributes<'_>) -> tracing::span::Id {
+            self.seen.lock().push(span.metadata().name().to_string());
+            tracing::span::Id::from_u64(self.next_id.fetch_add(1, Ordering::Relaxed) + 1)
+        }
+        fn record(&self, _: &tracing::span::Id, _: &tracing::span::Record<'_>) {}
+        fn record_follows_from(&self, _: &tracing::span::Id, _: &tracing::span::Id) {}
+        fn event(&self, event: &tracing::Event<'_>) {
+            struct Message<'a>(&'a mut Vec<String>);
+            impl tracing::field::Visit for Message<'_> {
+                fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn std::fmt::Debug) {
+                    if field.name() == "message" {
+                        self.0.push(format!("{:?}", value));
+                    }
+                }
+            }
+            event.record(&mut Message(&mut self.seen.lock()));
+        }
+        fn enter(&self, _: &tracing::span::Id) {}
+        fn exit(&self, _: &tracing::span::Id) {}
+    }
+
+    #[cfg(feature = "tracing")]
+    #[test]
+    fn tracing_spans_and_events() {
+        let recorder = std::sync::Arc::new(Recorder::default());
+        let (db, _dir) = test_db();
+        let db = db.with_persistence(Persistence::Immediate);
+        tracing::subscriber::with_default(recorder.clone(), || {
+            db.create_index("n").unwrap();
+            db.insert(json!({"n": 1})).unwrap();
+            db.query(json!({"n": 1}));
+            db.flush().unwrap();
+            db.compact().unwrap();
+        });
+        let seen = recorder.seen.lock().clone();
+        for expected in [
+            "ndb.create_index",
+            "index built",
+            "ndb.insert",
+            "log append synced",
+            "inserted",
+            "ndb.query",
+            "query plan",
+            "query done",
+            "ndb.flush",
+            "ndb.compact",
+            "log compacted",
+        ] {
+            assert!(seen.iter().any(|s| s == expected), "missing {:?} in {:?}", expected, seen);
+        }
+    }
 }
@@ -0,0 +1,378 @@
+//! Operation counters and latency histograms.
+//!
+//! Every `Database` records into a lock-free `Recorder`; `Database::metrics()`
+//! returns a `Metrics` snapshot that can be inspected directly or rendered in
+//! the Prometheus text exposition format.
+
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+/// Upper bounds (seconds) of the latency histogram buckets.
+pub const LATENCY_BUCKETS: [f64; 10] = [
+    0.000_05, 0.000_1, 0.000_5, 0.001, 0.005, 0.01, 0.05, 0.1, 0.5, 1.0,
+];
+
+/// Cumulative-bucket latency histogram.
+#[derive(Debug, Default)]
+pub(crate) struct Histogram {
+    buckets: [AtomicU64; LATENCY_BUCKETS.len()],
+    count: AtomicU64,
+    sum_nanos: AtomicU64,
+}
+
+impl Histogram {
+    pub(crate) fn observe(&self, elapsed: Duration) {
+        let secs = elapsed.as_secs_f64();
+        if let Some(i) = LATENCY_BUCKETS.iter().position(|&le| secs <= le) {
+            self.buckets[i].fetch_add(1, Ordering::Relaxed);
+        }
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.sum_nanos
+            .fetch_add(elapsed.as_nanos() as u64, Ordering::Relaxed);
+    }
+
+    /// Start timing; the elapsed time is observed when the guard drops.
+    pub(crate) fn time(&self) -> Timer<'_> {
+        Timer {
+            histogram: self,
+            start: Instant::now(),
+        }
+    }
+
+    fn snapshot(&self) -> HistogramSnapshot {
+        let mut cumulative = 0;
+        let buckets = LATENCY_BUCKETS
+            .iter()
+            .zip(&self.buckets)
+            .map(|(&le, n)| {
+                cumulative += n.load(Ordering::Relaxed);
+                (le, cumulative)
+            })
+            .collect();
+        HistogramSnapshot {
+            buckets,
+            count: self.count.load(Ordering::Relaxed),
+            sum: Duration::from_nanos(self.sum_nanos.load(Ordering::Relaxed)).as_secs_f64(),
+        }
+    }
+}
+
+/// Observes into a histogram on drop.
+pub(crate) struct Timer<'a> {
+    histogram: &'a Histogram,
+    start: Instant,
+}
+
+impl Drop for Timer<'_> {
+    fn drop(&mut self) {
+        self.histogram.observe(self.start.elapsed());
+    }
+}
+
+/// Live counters owned by a `Database`.
+#[derive(Debug, Default)]
+pub(crate) struct Recorder {
+    /// Records in the current log: replayed on open, appended since, reset by compaction.
+    pub(crate) log_records: AtomicU64,
+    /// Unix seconds of the last `flush()` / compaction (0 = never).
+    pub(crate) last_flush: AtomicU64,
+    pub(crate) last_compaction: AtomicU64,
+    pub(crate) inserts: AtomicU64,
+    pub(crate) updates: AtomicU64,
+    pub(crate) patches: AtomicU64,
+    pub(crate) deletes: AtomicU64,
+    pub(crate) queries: AtomicU64,
+    pub(crate) flushes: AtomicU64,
+    pub(crate) compactions: AtomicU64,
+    pub(crate) write_latency: Histogram,
+    pub(crate) query_latency: Histogram,
+    pub(crate) fsync_latency: Histogram,
+    pub(crate) compaction_duration: Histogram,
+}
+
+impl Recorder {
+    pub(crate) fn count(counter: &AtomicU64, n: u64) {
+        counter.fetch_add(n, Ordering::Relaxed);
+    }
+
+    /// Store the current Unix time in `gauge`.
+    pub(crate) fn stamp(gauge: &AtomicU64) {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        gauge.store(now, Ordering::Relaxed);
+    }
+
+    pub(crate) fn snapshot(&self) -> Metrics {
+        let load = |c: &AtomicU64| c.load(Ordering::Relaxed);
+        Metrics {
+            documents: 0,
+            deleted: 0,
+            indexes: 0,
+            log_bytes: 0,
+            log_records: load(&self.log_records),
+            trash_bytes: 0,
+            last_flush: Some(load(&self.last_flush)).filter(|&t| t > 0),
+            last_compaction: Some(load(&self.last_compaction)).filter(|&t| t > 0),
+            inserts: load(&self.inserts),
+            updates: load(&self.updates),
+            patches: load(&self.patches),
+            deletes: load(&self.deletes),
+            queries: load(&self.queries),
+            flushes: load(&self.flushes),
+            compactions: load(&self.compactions),
+            warnings: 0,
+            write_latency: self.write_latency.snapshot(),
+            query_latency: self.query_latency.snapshot(),
+            fsync_latency: self.fsync_latency.snapshot(),
+            compaction_duration: self.compaction_duration.snapshot(),
+        }
+    }
+}
+
+/// Point-in-time copy of a histogram.
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct HistogramSnapshot {
+    /// `(upper bound in seconds, cumulative count)` per bucket.
+    pub buckets: Vec<(f64, u64)>,
+    /// Total number of observations.
+    pub count: u64,
+    /// Sum of all observations, in seconds.
+    pub sum: f64,
+}
+
+/// Snapshot of a database's gauges, counters and latency histograms.
+/// Counters start at zero when the database is opened.
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct Metrics {
+    /// Active documents.
+    pub documents: usize,
+    /// Soft-deleted document IDs still tracked.
+    pub deleted: usize,
+    /// Secondary indexes.
+    pub indexes: usize,
+    /// Size of the JSONL log on disk (0 in memory).
+    pub log_bytes: u64,
+    /// Records in the JSONL log, live or superseded (0 in memory).
+    pub log_records: u64,
+    /// Size of the document trash file on disk.
+    pub trash_bytes: u64,
+    /// Unix seconds of the last explicit `flush()`, if any since open.
+    pub last_flush: Option<u64>,
+    /// Unix seconds of the last completed compaction, if any since open.
+    pub last_compaction: Option<u64>,
+    /// Documents inserted (each document of a batch counts).
+    pub inserts: u64,
+    /// Full-document updates.
+    pub updates: u64,
+    /// `set` / `remove` / `array_push` patches.
+    pub patches: u64,
+    /// Deletes.
+    pub deletes: u64,
+    /// AST queries (`query`, `query_with`, `query_grouped`, each `query_batch` entry).
+    pub queries: u64,
+    /// Explicit `flush()` calls.
+    pub flushes: u64,
+    /// Completed compactions.
+    pub compactions: u64,
+    /// Warnings recorded (see `Database::take_warnings`).
+    pub warnings: u64,
+    /// Latency of write calls (insert, update, patch, delete).
+    pub write_latency: HistogramSnapshot,
+    /// Latency of AST queries (a `query_batch` call is one observation).
+    pub query_latency: HistogramSnapshot,
+    /// Time spent in fsync (`Immediate` appends and `flush()`).
+    pub fsync_latency: HistogramSnapshot,
+    /// Duration of compactions.
+    pub compaction_duration: HistogramSnapshot,
+}
+
+/// Estimated heap use of a database, in bytes.
+///
+/// Counts string payloads plus a fixed per-node and per-entry overhead.
+/// Allocator slack and spare hash table capacity are not included, so the
+/// real footprint is somewhat higher.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize)]
+pub struct MemoryUsage {
+    /// Document store: IDs and JSON values.
+    pub documents: usize,
+    /// Secondary indexes: value keys and ID sets.
+    pub indexes: usize,
+    /// Soft-deleted IDs and file reference counts.
+    pub other: usize,
+}
+
+impl MemoryUsage {
+    /// Sum of all parts.
+    pub fn total(&self) -> usize {
+        self.documents + self.indexes + self.other
+    }
+}
+
+/// One secondary index, as reported by `Database::index_status`.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct IndexStatus {
+    /// Indexed field.
+    pub field: String,
+    /// `"hash"`, `"btree"` or `"text"`.
+    pub kind: &'static str,
+    /// (value, id) entries held.
+    pub entries: usize,
+    /// Estimated heap bytes, counted the same way as `MemoryUsage::indexes`.
+    pub heap_bytes: usize,
+}
+
+impl Metrics {
+    /// Share of log records that no longer describe a live document, from
+    /// 0.0 to 1.0. Compaction would shrink the log by about this fraction.
+    pub fn garbage_ratio(&self) -> f64 {
+        if self.log_records == 0 {
+            return 0.0;
+        }
+        1.0 - (self.documents as f64 / self.log_records as f64).min(1.0)
+    }
+
+    /// Render in the Prometheus text exposition format, prefixed `ndb_`.
+    pub fn to_prometheus(&self) -> String {
+        let mut out = String::new();
+
+        let gauges = [
+            ("documents", "Active documents.", self.documents as u64),
+            (
+                "deleted_documents",
+                "Soft-deleted document IDs tracked.",
+                self.deleted as u64,
+            ),
+            ("indexes", "Secondary indexes.", self.indexes as u64),
+            (
+                "log_bytes",
+                "Size of the JSONL log in bytes.",
+                self.log_bytes,
+            ),
+            (
+                "log_records",
+                "Records in the JSONL log, live or superseded.",
+                self.log_records,
+            ),
+            (
+                "trash_bytes",
+                "Size of the document trash file in bytes.",
+                self.trash_bytes,
+            ),
+            (
+                "last_flush_timestamp_seconds",
+                "Unix time of the last explicit flush (0 = never).",
+                self.last_flush.unwrap_or(0),
+            ),
+            (
+                "last_compaction_timestamp_seconds",
+                "Unix time of the last compaction (0 = never).",
+                self.last_compaction.unwrap_or(0),
+            ),
+        ];
+        for (name, help, value) in gauges {
+            let _ = writeln!(out, "# HELP ndb_{} {}", name, help);
+            let _ = writeln!(out, "# TYPE ndb_{} gauge", name);
+            let _ = writeln!(out, "ndb_{} {}", name, value);
+        }
+
+        let counters = [
+            ("inserts_total", "Documents inserted.", self.inserts),
+            ("updates_total", "Full-document updates.", self.updates),
+            (
+                "patches_total",
+                "Path patches (set, remove, array_push).",
+                self.patches,
+            ),
+            ("deletes_total", "Documents deleted.", self.deletes),
+            ("queries_total", "AST queries executed.", self.queries),
+            ("flushes_total", "Explicit flushes.", self.flushes),
+            (
+                "compactions_total",
+                "Completed compactions.",
+                self.compactions,
+            ),
+            ("warnings_total", "Best-effort failures recorded.", self.warnings),
+        ];
+        for (name, help, value) in counters {
+            let _ = writeln!(out, "# HELP ndb_{} {}", name, help);
+            let _ = writeln!(out, "# TYPE ndb_{} counter", name);
+            let _ = writeln!(out, "ndb_{} {}", name, value);
+        }
+
+        let histograms = [
+            (
+                "write_duration_seconds",
+                "Latency of write calls.",
+                &self.write_latency,
+            ),
+            (
+                "query_duration_seconds",
+                "Latency of AST queries.",
+                &self.query_latency,
+            ),
+            (
+                "fsync_duration_seconds",
+                "Time spent in fsync.",
+                &self.fsync_latency,
+            ),
+            (
+                "compaction_duration_seconds",
+                "Duration of compactions.",
+                &self.compaction_duration,
+            ),
+        ];
+        for (name, help, h) in histograms {
+            let _ = writeln!(out, "# HELP ndb_{} {}", name, help);
+            let _ = writeln!(out, "# TYPE ndb_{} histogram", name);
+            for (le, n) in &h.buckets {
+                let _ = writeln!(out, "ndb_{}_bucket{{le=\"{}\"}} {}", name, le, n);
+            }
+            let _ = writeln!(out, "ndb_{}_bucket{{le=\"+Inf\"}} {}", name, h.count);
+            let _ = writeln!(out, "ndb_{}_sum {}", name, h.sum);
+            let _ = writeln!(out, "ndb_{}_count {}", name, h.count);
+        }
+
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn histogram_buckets_are_cumulative() {
+        let h = Histogram::default();
+        h.observe(Duration::from_micros(20));
+        h.observe(Duration::from_micros(700));
+        h.observe(Duration::from_secs(3));
+
+        let snap = h.snapshot();
+        assert_eq!(snap.count, 3);
+        assert_eq!(snap.buckets[0], (0.000_05, 1));
+        assert_eq!(snap.buckets[3], (0.001, 2));
+        // Over the last bound: only in +Inf (count)
+        assert_eq!(snap.buckets.last().unwrap().1, 2);
+        assert!(snap.sum >= 3.0);
+    }
+
+    #[test]
+    fn prometheus_text_format() {
+        let rec = Recorder::default();
+        Recorder::count(&rec.inserts, 2);
+        drop(rec.query_latency.time());
+        let mut m = rec.snapshot();
+        m.documents = 2;
+
+        let text = m.to_prometheus();
+        assert!(text.contains("# TYPE ndb_documents gauge\nndb_documents 2\n"));
+        assert!(text.contains("# TYPE ndb_inserts_total counter\nndb_inserts_total 2\n"));
+        assert!(text.contains("# TYPE ndb_query_duration_seconds histogram\n"));
+        assert!(text.contains("ndb_query_duration_seconds_bucket{le=\"+Inf\"} 1\n"));
+        assert!(text.contains("ndb_query_duration_seconds_count 1\n"));
+        assert!(text.contains("ndb_write_duration_seconds_count 0\n"));
+    }
+}
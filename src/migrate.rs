@@ -0,0 +1,195 @@
+//! On-disk format versions and upgrades between them.
+//!
+//! The `_meta` header on the first line of a log records its format version.
+//! Logs from before the header existed count as version 0. `upgrade` runs
+//! the registered migrations in order to bring an older log up to
+//! `STORAGE_VERSION`. The original file is kept as a backup. Every record
+//! is preserved, including tombstones and patches; migrations are not
+//! compaction.
+
+use crate::error::{Error, Result};
+use crate::storage::{self, STORAGE_VERSION};
+use serde_json::Value;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// One step from `from` to `from + 1`.
+pub struct Migration {
+    /// Version this step upgrades from.
+    pub from: u64,
+    /// What the step changes, for reports.
+    pub description: &'static str,
+    /// Rewrite one record. Returning several records splits it; none drops it.
+    pub apply: fn(Value) -> Vec<Value>,
+}
+
+/// Registered migrations, ordered by `from`.
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        from: 0,
+        description: "add _meta header",
+        // Records are unchanged; the rewrite itself adds the header
+        apply: |record| vec![record],
+    },
+    Migration {
+        from: 1,
+        description: "allow batch and idempotency records",
+        // Version 1 records are valid version 2 records. The bump is what
+        // matters: version 1 readers would skip batch records, which have no
+        // `_id`, and replay `{"_op":"idem"}` records as whole documents.
+        apply: |record| vec![record],
+    },
+];
+
+/// Outcome of `upgrade`.
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct MigrationReport {
+    /// Version found on disk.
+    pub from: u64,
+    /// Version after the upgrade (or that a dry run would reach).
+    pub to: u64,
+    /// Descriptions of the steps run (or planned), in order.
+    pub steps: Vec<&'static str>,
+    /// Records in the log.
+    pub records: usize,
+    /// Nothing was written.
+    pub dry_run: bool,
+    /// Copy of the original log (`None` for dry runs and no-op upgrades).
+    pub backup: Option<PathBuf>,
+}
+
+impl MigrationReport {
+    /// True when the log was already current.
+    pub fn is_noop(&self) -> bool {
+        self.steps.is_empty()
+    }
+}
+
+/// Format version of the log at `path`. Logs without a `_meta` header are version 0.
+pub fn log_version(path: &Path) -> Result<u64> {
+    let header = match storage::read_header(path)? {
+        Some(header) => header,
+        None => return Ok(0),
+    };
+    let header: Value = serde_json::from_str(&header)
+        .map_err(|e| Error::corruption(path, format!("unreadable _meta header: {}", e)))?;
+    header
+        .get("_meta")
+        .and_then(|m| m.get("version"))
+        .and_then(Value::as_u64)
+        .ok_or_else(|| Error::corruption(path, "_meta header has no numeric version"))
+}
+
+/// Fail with `UnsupportedVersion` if the log is newer than this build.
+pub(crate) fn check_supported(path: &Path, version: u64) -> Result<()> {
+    check_version(path, version, STORAGE_VERSION)
+}
+
+/// `check_supported` for a build that supports up to `supported`.
+fn check_version(path: &Path, version: u64, supported: u64) -> Result<()> {
+    if version > supported {
+        return Err(Error::UnsupportedVersion {
+            path: path.to_path_buf(),
+            found: version,
+            supported,
+        });
+    }
+    Ok(())
+}
+
+/// Upgrade the log at `path` to `STORAGE_VERSION`.
+///
+/// With `dry_run`, only reports what would run. Otherwise the log is
+/// copied to `<path>.v<from>.bak` and atomically rewritten. A log with
+/// unreadable lines is refused rather than silently losing them; recover
+/// it with `Database::open_with_recovery` first.
+pub fn upgrade(path: &Path, dry_run: bool) -> Result<MigrationReport> {
+    let from = log_version(path)?;
+    check_supported(path, from)?;
+
+    let steps: Vec<&Migration> = MIGRATIONS.iter().filter(|m| m.from >= from).collect();
+    let (mut records, rejects) = storage::read_all_with_rejects(path)?;
+    let mut report = MigrationReport {
+        from,
+        to: STORAGE_VERSION,
+        steps: steps.iter().map(|m| m.description).collect(),
+        records: records.len(),
+        dry_run,
+        backup: None,
+    };
+    if steps.is_empty() || dry_run {
+        return Ok(report);
+    }
+    if let Some(first) = rejects.first() {
+        return Err(Error::corruption(
+            path,
+            format!(
+                "{} unreadable line(s), first at line {}; recover before upgrading",
+                rejects.len(),
+                first.line
+            ),
+        ));
+    }
+
+    for step in &steps {
+        records = records.into_iter().flat_map(step.apply).collect();
+    }
+
+    let mut backup = path.as_os_str().to_owned();
+    backup.push(format!(".v{}.bak", from));
+    let backup = PathBuf::from(backup);
+    fs::copy(path, &backup).map_err(Error::io_err(&backup, "back up log before upgrade"))?;
+
+    let refs: Vec<&Value> = records.iter().collect();
+    storage::rewrite_atomic(path, &refs)?;
+
+    report.records = records.len();
+    report.backup = Some(backup);
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn migrations_are_contiguous() {
+        for (i, m) in MIGRATIONS.iter().enumerate() {
+            assert_eq!(m.from, i as u64);
+        }
+        assert_eq!(MIGRATIONS.len() as u64, STORAGE_VERSION);
+    }
+
+    #[test]
+    fn log_version_reads_header() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("v.jsonl");
+        storage::init_file(&path).unwrap();
+        assert_eq!(log_version(&path).unwrap(), STORAGE_VERSION);
+
+        fs::write(&path, "{\"_id\":\"a\"}\n").unwrap();
+        assert_eq!(log_version(&path).unwrap(), 0);
+
+        fs::write(&path, "{\"_meta\":{\"created\":\"0\"}}\n").unwrap();
+        assert!(matches!(log_version(&path), Err(Error::Corruption { .. })));
+    }
+
+    #[test]
+    fn version_1_build_refuses_batch_and_idempotency_records() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("v2.jsonl");
+        let db = crate::Database::open(&path).unwrap();
+        db.insert_batch(vec![serde_json::json!({"n": 1})]).unwrap();
+        db.insert_idempotent("k", serde_json::json!({"n": 2})).unwrap();
+        db.compact().unwrap();
+        drop(db);
+
+        let version = log_version(&path).unwrap();
+        assert_eq!(version, 2);
+        match check_version(&path, version, 1) {
+            Err(Error::UnsupportedVersion { found, supported, .. }) => assert_eq!((found, supported), (2, 1)),
+            other => panic!("expected UnsupportedVersion, got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,139 @@
+//! Tenant namespaces within one database.
+//!
+//! `Database::namespace` returns a handle whose inserts get IDs of the form
+//! `{name}_{random}`, the same IDs `insert_with_prefix` produces. Membership
+//! is read from the ID alone, so scoping a query, counting a tenant or
+//! deleting it never looks at document contents. Documents inserted with
+//! `insert_with_prefix(name, ..)` belong to the namespace too.
+
+use crate::error::{Error, Result};
+use crate::{Database, OperationHandle, QueryOptions};
+use serde_json::Value;
+
+/// Longest accepted namespace name, in bytes.
+pub const MAX_NAME_LEN: usize = 64;
+
+/// Handle on the documents of one namespace.
+#[derive(Clone)]
+pub struct Namespace<'a> {
+    db: &'a Database,
+    name: String,
+    /// `{name}_`, the start of every ID in the namespace.
+    prefix: String,
+}
+
+impl Database {
+    /// Handle on the namespace `name`. Names are 1 to 64 ASCII letters and
+    /// digits; `_` is reserved as the ID separator, so one namespace can
+    /// never match another's IDs.
+    pub fn namespace(&self, name: &str) -> Result<Namespace<'_>> {
+        if name.is_empty() || name.len() > MAX_NAME_LEN || !name.chars().all(|c| c.is_ascii_alphanumeric()) {
+            return Err(Error::invalid_arg(format!(
+                "namespace name must be 1 to {} ASCII letters or digits, got {:?}",
+                MAX_NAME_LEN, name
+            )));
+        }
+        Ok(Namespace {
+            db: self,
+            name: name.to_string(),
+            prefix: format!("{}_", name),
+        })
+    }
+}
+
+impl Namespace<'_> {
+    /// The namespace name.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// True when `id` belongs to this namespace.
+    pub fn owns(&self, id: &str) -> bool {
+        id.starts_with(&self.prefix)
+    }
+
+    /// Insert a document into the namespace.
+    pub fn insert(&self, doc: Value) -> Result<String> {
+        self.db.insert_with_prefix(&self.name, doc)
+    }
+
+    /// Get a document by ID. IDs of other namespaces are `NotFound`.
+    pub fn get(&self, id: &str) -> Result<Value> {
+        if !self.owns(id) {
+            return Err(Error::not_found(id));
+        }
+        self.db.get(id)
+    }
+
+    /// IDs of every document in the namespace.
+    pub fn ids(&self) -> Vec<String> {
+        self.db.docs.read().keys().filter(|id| self.owns(id)).cloned().collect()
+    }
+
+    /// Number of documents in the namespace. Walks the IDs, not the documents.
+    pub fn len(&self) -> usize {
+        self.db.docs.read().keys().filter(|id| self.owns(id)).count()
+    }
+
+    /// True when the namespace has no documents.
+    pub fn is_empty(&self) -> bool {
+        !self.db.docs.read().keys().any(|id| self.owns(id))
+    }
+
+    /// Execute a JSON AST query against this namespace only. Index
+    /// candidates and documents of other namespaces are skipped by ID
+    /// before the query is evaluated.
+    pub fn query(&self, ast: &Value) -> Vec<Value> {
+        self.query_with(ast, &QueryOptions::default())
+    }
+
+    /// Execute a JSON AST query with options (limit, sort, offset).
+    pub fn query_with(&self, ast: &Value, opts: &QueryOptions) -> Vec<Value> {
+        // Only fails when cancelled, and there is no handle to cancel
+        self.db.query_filtered(ast, opts, None, |id| self.owns(id)).unwrap_or_default()
+    }
+
+    /// `query_with` that checks `op` for cancellation, like
+    /// `Database::query_cancellable`.
+    pub fn query_cancellable(&self, ast: &Value, opts: &QueryOptions, op: &OperationHandle) -> Result<Vec<Value>> {
+        self.db.query_filtered(ast, opts, Some(op), |id| self.owns(id))
+    }
+
+    /// Delete every document in the namespace as one batch: one log record,
+    /// recovered whole or not at all after a crash. Deleted documents go to
+    /// the trash like any other delete. Returns how many were deleted.
+    ///
+    /// Fails with `NotFound`, deleting nothing, if a document is deleted
+    /// concurrently between listing and committing; retrying is safe.
+    pub fn delete_all(&self) -> Result<usize> {
+        let ids = self.ids();
+        let mut batch = self.db.begin_batch();
+        for id in &ids {
+            batch.delete(id);
+        }
+        batch.commit()?;
+        Ok(ids.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn names_cannot_overlap() {
+        let db = Database::open_in_memory().unwrap();
+        assert!(db.namespace("").is_err());
+        assert!(db.namespace("a_b").is_err());
+        assert!(db.namespace(&"x".repeat(MAX_NAME_LEN + 1)).is_err());
+
+        let a = db.namespace("a").unwrap();
+        let ab = db.namespace("ab").unwrap();
+        let id = ab.insert(json!({"n": 1})).unwrap();
+        assert!(ab.owns(&id));
+        assert!(!a.owns(&id));
+        assert_eq!(a.len(), 0);
+        assert!(matches!(a.get(&id), Err(Error::NotFound { .. })));
+    }
+}
@@ -0,0 +1,200 @@
+//! Progress reporting and cancellation for long-running operations.
+//!
+//! Pass an `OperationHandle` to `compact_with`, `create_index_with`,
+//! `create_btree_index_with` or `query_cancellable`. Clones share state, so one clone can cancel
+//! from another thread while the operation runs. Cancellation is checked at
+//! phase boundaries and every `CHECK_EVERY` items; a cancelled operation
+//! returns `Error::Cancelled` and leaves the database as it was.
+
+use crate::error::{Error, Result};
+use parking_lot::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// How many items an operation processes between progress reports.
+pub const CHECK_EVERY: usize = 1024;
+
+/// One progress report.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+pub struct Progress {
+    /// Operation name (`"compact"`, `"create_index"`, `"query"`).
+    pub operation: &'static str,
+    /// Current phase: `"write"`, `"swap"`, `"done"` for compaction;
+    /// `"build"`, `"done"` for index builds; `"scan"`, `"done"` for queries.
+    pub phase: &'static str,
+    /// Items finished in this phase.
+    pub done: usize,
+    /// Items in this phase.
+    pub total: usize,
+}
+
+impl Progress {
+    /// Completion of the current phase, from 0.0 to 1.0.
+    pub fn fraction(&self) -> f64 {
+        if self.total == 0 {
+            1.0
+        } else {
+            self.done as f64 / self.total as f64
+        }
+    }
+}
+
+type Callback = Box<dyn Fn(&Progress) + Send + Sync>;
+
+#[derive(Default)]
+struct Shared {
+    cancelled: AtomicBool,
+    on_progress: Mutex<Option<Callback>>,
+}
+
+/// Progress callback and cancellation token for one operation.
+#[derive(Clone, Default)]
+pub struct OperationHandle {
+    shared: Arc<Shared>,
+}
+
+impl OperationHandle {
+    /// Handle with no callback that is not cancelled.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Call `f` with every progress report. Returns self for chaining.
+    pub fn on_progress(self, f: impl Fn(&Progress) + Send + Sync + 'static) -> Self {
+        *self.shared.on_progress.lock() = Some(Box::new(f));
+        self
+    }
+
+    /// Ask the operation to stop at its next check.
+    pub fn cancel(&self) {
+        self.shared.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    /// True once `cancel` was called.
+    pub fn is_cancelled(&self) -> bool {
+        self.shared.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Report progress without checking for cancellation.
+    pub(crate) fn report(&self, operation: &'static str, phase: &'static str, done: usize, total: usize) {
+        if let Some(f) = self.shared.on_progress.lock().as_ref() {
+            f(&Progress {
+                operation,
+                phase,
+                done,
+                total,
+            });
+        }
+    }
+
+    /// Report progress, then fail with `Cancelled` if cancellation was requested.
+    pub(crate) fn checkpoint(&self, operation: &'static str, phase: &'static str, done: usize, total: usize) -> Result<()> {
+        self.report(operation, phase, done, total);
+        if self.is_cancelled() {
+            return Err(Error::Cancelled {
+                operation: operation.to_string(),
+                phase: phase.to_string(),
+            });
+        }
+        Ok(())
+    }
+}
+
+impl std::fmt::Debug for OperationHandle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("OperationHandle")
+            .field("cancelled", &self.is_cancelled())
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Database;
+    use serde_json::json;
+    use std::sync::Mutex as StdMutex;
+    use tempfile::TempDir;
+
+    #[test]
+    fn cancelled_compaction_keeps_log() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("test.jsonl");
+        let db = Database::open(&path).unwrap();
+        let keep = db.insert(json!({"n": 1})).unwrap();
+        let gone = db.insert(json!({"n": 2})).unwrap();
+        db.delete(&gone).unwrap();
+        let before = std::fs::read_to_string(&path).unwrap();
+
+        let op = OperationHandle::new();
+        let canceller = op.clone();
+        let op = op.on_progress(move |p| {
+            if p.phase == "swap" {
+                canceller.cancel();
+            }
+        });
+        let err = db.compact_with(&op).unwrap_err();
+        assert!(matches!(err, Error::Cancelled { ref phase, .. } if phase == "swap"));
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), before);
+        assert!(!path.with_extension("jsonl.tmp").exists());
+
+        // The log is still writable and a later compaction succeeds
+        db.insert(json!({"n": 3})).unwrap();
+        let phases = Arc::new(StdMutex::new(Vec::new()));
+        let seen = Arc::clone(&phases);
+        db.compact_with(&OperationHandle::new().on_progress(move |p| seen.lock().unwrap().push(p.phase)))
+            .unwrap();
+        assert_eq!(*phases.lock().unwrap(), ["write", "swap", "done"]);
+        drop(db);
+
+        let db = Database::open(&path).unwrap();
+        assert_eq!(db.len(), 2);
+        assert!(db.contains(&keep));
+    }
+
+    #[test]
+    fn cancelled_index_build_installs_nothing() {
+        let db = Database::open_in_memory().unwrap();
+        db.insert_batch((0..CHECK_EVERY * 2 + 1).map(|n| json!({"n": n})).collect())
+            .unwrap();
+
+        let reports = Arc::new(StdMutex::new(Vec::new()));
+        let seen = Arc::clone(&reports);
+        let op = OperationHandle::new().on_progress(move |p| seen.lock().unwrap().push(*p));
+        db.create_btree_index_with("n", &op).unwrap();
+        let reports = reports.lock().unwrap();
+        assert_eq!(reports.len(), 5);
+        assert_eq!(reports[1].done, CHECK_EVERY);
+        assert_eq!(reports[4].phase, "done");
+        assert_eq!(reports[4].fraction(), 1.0);
+        assert!(db.has_index("n"));
+
+        let op = OperationHandle::new();
+        op.cancel();
+        assert!(matches!(db.create_index_with("m", &op), Err(Error::Cancelled { .. })));
+        assert!(!db.has_index("m"));
+    }
+
+    #[test]
+    fn query_stops_mid_scan() {
+        let db = Database::open_in_memory().unwrap();
+        db.insert_batch((0..CHECK_EVERY * 3).map(|n| json!({"n": n})).collect())
+            .unwrap();
+        let ast = json!({"n": {"$gte": 0}});
+
+        let op = OperationHandle::new();
+        let canceller = op.clone();
+        let op = op.on_progress(move |p| {
+            if p.done >= CHECK_EVERY {
+                canceller.cancel();
+            }
+        });
+        let err = db.query_cancellable(ast.clone(), Default::default(), &op).unwrap_err();
+        assert!(matches!(err, Error::Cancelled { ref phase, .. } if phase == "scan"));
+
+        let all = db
+            .query_cancellable(ast, Default::default(), &OperationHandle::new())
+            .unwrap();
+        assert_eq!(all.len(), CHECK_EVERY * 3);
+    }
+}
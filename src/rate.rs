@@ -0,0 +1,72 @@
+//! Token bucket behind `Limits::max_writes_per_sec`.
+//!
+//! The bucket holds one second's worth of tokens and refills continuously,
+//! so short bursts up to the rate pass and sustained load is held to it.
+//! Callers that run dry get `Error::Throttled` with the wait until enough
+//! tokens are back, instead of blocking.
+
+use parking_lot::Mutex;
+use std::time::{Duration, Instant};
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    refilled: Instant,
+}
+
+#[derive(Debug)]
+pub(crate) struct TokenBucket {
+    per_sec: f64,
+    bucket: Mutex<Bucket>,
+}
+
+impl TokenBucket {
+    /// Full bucket refilling at `per_sec` tokens per second (at least 1).
+    pub(crate) fn new(per_sec: u32) -> Self {
+        let per_sec = per_sec.max(1) as f64;
+        TokenBucket {
+            per_sec,
+            bucket: Mutex::new(Bucket {
+                tokens: per_sec,
+                refilled: Instant::now(),
+            }),
+        }
+    }
+
+    /// Take `n` tokens, or return how long until they are available. A
+    /// request larger than the bucket only needs a full bucket.
+    pub(crate) fn take(&self, n: usize) -> Result<(), Duration> {
+        let need = (n as f64).min(self.per_sec);
+        let mut bucket = self.bucket.lock();
+        let now = Instant::now();
+        let elapsed = now.duration_since(bucket.refilled).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * self.per_sec).min(self.per_sec);
+        bucket.refilled = now;
+        if bucket.tokens >= need {
+            bucket.tokens -= need;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((need - bucket.tokens) / self.per_sec))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bursts_then_throttles_then_refills() {
+        let bucket = TokenBucket::new(100);
+        for _ in 0..100 {
+            bucket.take(1).unwrap();
+        }
+        let wait = bucket.take(1).unwrap_err();
+        assert!(wait <= Duration::from_millis(10), "{:?}", wait);
+
+        std::thread::sleep(Duration::from_millis(30));
+        bucket.take(2).unwrap();
+        // Oversized requests wait for a full bucket rather than forever
+        assert!(bucket.take(1_000).unwrap_err() <= Duration::from_secs(1));
+    }
+}
@@ -0,0 +1,182 @@
+//! Read-only followers that tail a leader's JSONL log.
+//!
+//! A `Replica` opens the same file a leader `Database` writes to (typically
+//! on a shared directory) and applies newly appended records on each
+//! `catch_up()`. Records are applied in log order and counted by a sequence
+//! number, so a record is never applied twice. When the leader compacts,
+//! the log is replaced wholesale and the replica reloads from scratch.
+
+use crate::error::{Error, Result};
+use crate::{apply_query_options, query_matches, replay_record, storage, values_equal, QueryOptions};
+use parking_lot::{Mutex, RwLock};
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Position of a replica within the leader's current log file.
+#[derive(Debug, Default)]
+struct Cursor {
+    /// Byte offset just past the last applied record.
+    offset: u64,
+    /// Number of records applied from the current log file.
+    seq: u64,
+    /// `_meta` header of the log file being followed. Its nonce is new for
+    /// every file, so a changed header means the log was replaced.
+    header: Option<String>,
+    /// Inode of the log file being followed (compaction swaps it).
+    #[cfg(unix)]
+    ino: u64,
+}
+
+/// Read-only follower of a leader database's log file.
+pub struct Replica {
+    /// Path to the leader's JSONL data file.
+    path: PathBuf,
+    /// In-memory document store: _id → document.
+    docs: RwLock<HashMap<String, Value>>,
+    /// Set of deleted document IDs (tombstones).
+    deleted: RwLock<HashSet<String>>,
+    /// Read position; held for the duration of a catch-up.
+    cursor: Mutex<Cursor>,
+}
+
+impl Replica {
+    /// Open a follower of the log at `path` and apply everything in it.
+    ///
+    /// The leader must already have created the file.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let replica = Replica {
+            path,
+            docs: RwLock::new(HashMap::new()),
+            deleted: RwLock::new(HashSet::new()),
+            cursor: Mutex::new(Cursor::default()),
+        };
+        replica.catch_up()?;
+        Ok(replica)
+    }
+
+    /// Apply records appended since the last call. Returns how many were applied.
+    ///
+    /// A partially written trailing line is left for the next call. If the
+    /// leader compacted in the meantime, state is rebuilt from the new file
+    /// and every record in it is counted as applied.
+    pub fn catch_up(&self) -> Result<usize> {
+        let mut cursor = self.cursor.lock();
+
+        let meta = fs::metadata(&self.path).map_err(Error::io_err(&self.path, "stat leader log"))?;
+        let header = storage::read_header(&self.path)?;
+        #[cfg(unix)]
+        let ino = std::os::unix::fs::MetadataExt::ino(&meta);
+
+        #[allow(unused_mut)]
+        let mut replaced = meta.len() < cursor.offset || header != cursor.header;
+        #[cfg(unix)]
+        {
+            replaced |= ino != cursor.ino;
+        }
+
+        if replaced {
+            *cursor = Cursor {
+                header,
+                #[cfg(unix)]
+                ino,
+                ..Cursor::default()
+            };
+        }
+
+        let (records, offset) = storage::read_from(&self.path, cursor.offset)?;
+        let applied = records.len();
+        if replaced {
+            // Rebuild off to the side so readers never observe a half-loaded store
+            let mut docs = HashMap::new();
+            let mut deleted = HashSet::new();
+            for record in records {
+                replay_record(&mut docs, &mut deleted, record);
+            }
+            *self.docs.write() = docs;
+            *self.deleted.write() = deleted;
+        } else {
+            let mut docs = self.docs.write();
+            let mut deleted = self.deleted.write();
+            for record in records {
+                replay_record(&mut docs, &mut deleted, record);
+            }
+        }
+        cursor.offset = offset;
+        cursor.seq += applied as u64;
+        Ok(applied)
+    }
+
+    /// Number of records applied from the leader's current log file.
+    pub fn seq(&self) -> u64 {
+        self.cursor.lock().seq
+    }
+
+    /// Path of the log being followed.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    // ─── Reads ─────────────────────────────────────────────────────
+
+    /// Get a document by ID.
+    pub fn get(&self, id: &str) -> Result<Value> {
+        self.docs
+            .read()
+            .get(id)
+            .cloned()
+            .ok_or_else(|| Error::not_found(id))
+    }
+
+    /// Iterate all documents (returns a snapshot).
+    pub fn iter(&self) -> Vec<Value> {
+        self.docs.read().values().cloned().collect()
+    }
+
+    /// Number of active (non-deleted) documents.
+    pub fn len(&self) -> usize {
+        self.docs.read().len()
+    }
+
+    /// Check if the replica holds no documents.
+    pub fn is_empty(&self) -> bool {
+        self.docs.read().is_empty()
+    }
+
+    /// Check if a document exists.
+    pub fn contains(&self, id: &str) -> bool {
+        self.docs.read().contains_key(id)
+    }
+
+    /// Get list of soft-deleted document IDs seen in the log.
+    pub fn deleted_ids(&self) -> Vec<String> {
+        self.deleted.read().iter().cloned().collect()
+    }
+
+    /// Find all documents where `field` equals `value`.
+    pub fn find(&self, field: &str, value: &Value) -> Vec<Value> {
+        self.docs
+            .read()
+            .values()
+            .filter(|doc| doc.get(field).is_some_and(|v| values_equal(v, value)))
+            .cloned()
+            .collect()
+    }
+
+    /// Execute a JSON AST query. Returns all matching documents.
+    pub fn query(&self, ast: Value) -> Vec<Value> {
+        self.docs
+            .read()
+            .values()
+            .filter(|doc| query_matches(doc, &ast))
+            .cloned()
+            .collect()
+    }
+
+    /// Execute a JSON AST query with options (limit, sort, offset).
+    pub fn query_with(&self, ast: Value, opts: QueryOptions) -> Vec<Value> {
+        apply_query_options(self.query(ast), &opts)
+    }
+}
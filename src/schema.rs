@@ -0,0 +1,225 @@
+//! Optional typed field map validated on every write.
+//!
+//! A schema lists dot-notation field paths with the JSON type they must hold.
+//! Fields not listed are unconstrained. Schemas come from the `"schema"` key
+//! of `meta.json` or from `Database::with_schema`.
+//!
+//! ```json
+//! {"schema": {"year": {"type": "integer", "required": true}, "meta.tags": {"type": "array"}}}
+//! ```
+
+use crate::error::{Error, Result};
+use crate::field_get;
+use crate::storage;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// JSON type a field must hold.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FieldType {
+    String,
+    /// Any JSON number.
+    Number,
+    /// An integral number (`2021`, not `2021.0`).
+    Integer,
+    Boolean,
+    Array,
+    Object,
+}
+
+impl FieldType {
+    fn name(self) -> &'static str {
+        match self {
+            FieldType::String => "string",
+            FieldType::Number => "number",
+            FieldType::Integer => "integer",
+            FieldType::Boolean => "boolean",
+            FieldType::Array => "array",
+            FieldType::Object => "object",
+        }
+    }
+
+    fn matches(self, v: &Value) -> bool {
+        match self {
+            FieldType::String => v.is_string(),
+            FieldType::Number => v.is_number(),
+            FieldType::Integer => v.is_i64() || v.is_u64(),
+            FieldType::Boolean => v.is_boolean(),
+            FieldType::Array => v.is_array(),
+            FieldType::Object => v.is_object(),
+        }
+    }
+}
+
+/// Constraint on a single field.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FieldSpec {
+    /// Expected type.
+    #[serde(rename = "type")]
+    pub field_type: FieldType,
+    /// Reject documents without the field.
+    #[serde(default)]
+    pub required: bool,
+    /// Accept `null` in place of a value.
+    #[serde(default)]
+    pub nullable: bool,
+}
+
+/// Field path → constraint.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Schema {
+    pub fields: BTreeMap<String, FieldSpec>,
+}
+
+impl Schema {
+    /// Empty schema (accepts everything).
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Constrain an optional field. Returns self for chaining.
+    pub fn field(mut self, path: &str, field_type: FieldType) -> Self {
+        self.fields.insert(
+            path.to_string(),
+            FieldSpec {
+                field_type,
+                required: false,
+                nullable: false,
+            },
+        );
+        self
+    }
+
+    /// Constrain a required field. Returns self for chaining.
+    pub fn required(mut self, path: &str, field_type: FieldType) -> Self {
+        self.fields.insert(
+            path.to_string(),
+            FieldSpec {
+                field_type,
+                required: true,
+                nullable: false,
+            },
+        );
+        self
+    }
+
+    /// Check a document against every constraint. Fails on the first violation.
+    pub fn validate(&self, doc: &Value) -> Result<()> {
+        for (path, spec) in &self.fields {
+            match field_get(doc, path) {
+                None if spec.required => {
+                    return Err(Error::schema(path, "required field is missing"));
+                }
+                None => {}
+                Some(Value::Null) if spec.nullable => {}
+                Some(v) if !spec.field_type.matches(v) => {
+                    return Err(Error::schema(
+                        path,
+                        format!("expected {}, got {}", spec.field_type.name(), type_name(v)),
+                    ));
+                }
+                Some(_) => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Load the `"schema"` key from `meta.json` in `base_dir`, if any.
+    pub fn load(base_dir: &Path) -> Result<Option<Self>> {
+        let path = base_dir.join("meta.json");
+        let Some(meta) = storage::read_meta(base_dir)? else {
+            return Ok(None);
+        };
+        match meta.get("schema") {
+            None | Some(Value::Null) => Ok(None),
+            Some(schema) => serde_json::from_value(schema.clone())
+                .map(Some)
+                .map_err(|e| Error::corruption(&path, format!("invalid schema: {}", e))),
+        }
+    }
+}
+
+/// JSON type name of a value, for error messages.
+fn type_name(v: &Value) -> &'static str {
+    match v {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(n) if n.is_f64() => "number",
+        Value::Number(_) => "integer",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn validate_types_and_required() {
+        let schema = Schema::new()
+            .required("title", FieldType::String)
+            .field("year", FieldType::Integer)
+            .field("meta.score", FieldType::Number);
+
+        assert!(schema.validate(&json!({"title": "a"})).is_ok());
+        assert!(schema
+            .validate(&json!({"title": "a", "year": 2021, "meta": {"score": 0.5}}))
+            .is_ok());
+
+        let err = schema.validate(&json!({"year": 2021})).unwrap_err();
+        assert!(matches!(err, Error::SchemaViolation { ref field, .. } if field == "title"));
+
+        let err = schema.validate(&json!({"title": "a", "year": "2021"})).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "schema violation at 'year': expected integer, got string"
+        );
+        assert!(schema.validate(&json!({"title": "a", "year": 2021.5})).is_err());
+        assert!(schema.validate(&json!({"title": "a", "meta": {"score": "hi"}})).is_err());
+    }
+
+    #[test]
+    fn nullable_fields() {
+        let mut schema = Schema::new().field("parent", FieldType::String);
+        assert!(schema.validate(&json!({"parent": null})).is_err());
+
+        schema.fields.get_mut("parent").unwrap().nullable = true;
+        assert!(schema.validate(&json!({"parent": null})).is_ok());
+    }
+
+    #[test]
+    fn load_from_meta_json() {
+        let dir = TempDir::new().unwrap();
+        assert_eq!(Schema::load(dir.path()).unwrap(), None);
+
+        fs::write(dir.path().join("meta.json"), r#"{"version": 1}"#).unwrap();
+        assert_eq!(Schema::load(dir.path()).unwrap(), None);
+
+        fs::write(
+            dir.path().join("meta.json"),
+            r#"{"version": 1, "schema": {"year": {"type": "integer", "required": true}}}"#,
+        )
+        .unwrap();
+        let schema = Schema::load(dir.path()).unwrap().unwrap();
+        assert_eq!(schema, Schema::new().required("year", FieldType::Integer));
+
+        fs::write(
+            dir.path().join("meta.json"),
+            r#"{"schema": {"year": {"type": "date"}}}"#,
+        )
+        .unwrap();
+        assert!(matches!(
+            Schema::load(dir.path()),
+            Err(Error::Corruption { .. })
+        ));
+    }
+}
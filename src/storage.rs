@@ -7,11 +7,11 @@
 use crate::error::{Error, Result};
 use serde_json::Value;
 use std::fs::{self, File, OpenOptions};
-use std::io::{BufRead, BufReader, Write};
-use std::path::Path;
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
 
 /// Current storage format version.
-const STORAGE_VERSION: u64 = 1;
+pub const STORAGE_VERSION: u64 = 2;
 
 /// Meta header written as first line of every JSONL file.
 #[derive(serde::Serialize, serde::Deserialize, Debug)]
@@ -23,6 +23,11 @@ struct MetaHeader {
 struct MetaInner {
     version: u64,
     created: String,
+    /// Random per file, so a log replaced within the same second (e.g. by
+    /// two quick compactions) still has a different header. Followers
+    /// compare headers to notice the swap.
+    #[serde(default)]
+    nonce: u64,
 }
 
 /// Create the `_meta` header line.
@@ -31,6 +36,7 @@ fn meta_line() -> String {
         _meta: MetaInner {
             version: STORAGE_VERSION,
             created: chrono_free_timestamp(),
+            nonce: fastrand::u64(..),
         },
     };
     serde_json::to_string(&header).unwrap()
@@ -64,11 +70,27 @@ pub fn init_file(path: &Path) -> Result<()> {
 }
 
 /// Open an existing JSONL file for appending.
+///
+/// A crash mid-append can leave a last line without its newline. Reading
+/// skips it, but a record appended straight after it would be glued onto
+/// the torn line and lost on the next open, so the line is closed first.
 pub fn open_for_append(path: &Path) -> Result<File> {
-    OpenOptions::new()
+    let mut file = OpenOptions::new()
+        .read(true)
         .append(true)
         .open(path)
-        .map_err(Error::io_err(path, "open JSONL for append"))
+        .map_err(Error::io_err(path, "open JSONL for append"))?;
+    if file.seek(SeekFrom::End(0)).map_err(Error::io_err(path, "seek JSONL"))? > 0 {
+        let mut last = [0u8];
+        file.seek(SeekFrom::End(-1))
+            .and_then(|_| file.read_exact(&mut last))
+            .map_err(Error::io_err(path, "read JSONL tail"))?;
+        if last[0] != b'\n' {
+            file.write_all(b"\n")
+                .map_err(Error::io_err(path, "terminate torn line"))?;
+        }
+    }
+    Ok(file)
 }
 
 /// Append a document line to the file.
@@ -86,6 +108,19 @@ pub fn append_line_sync(file: &mut File, path: &Path, line: &str) -> Result<()>
     Ok(())
 }
 
+/// A log line that could not be parsed, kept for quarantine.
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct RejectedLine {
+    /// 1-based line number in the file.
+    pub line: usize,
+    /// Byte offset of the start of the line.
+    pub offset: u64,
+    /// The line as read (invalid UTF-8 replaced).
+    pub raw: String,
+    /// Why it was rejected.
+    pub error: String,
+}
+
 /// Read all documents from a JSONL file.
 /// Returns a vector of parsed JSON values (skips _meta header line).
 /// Last write wins: later entries for the same _id overwrite earlier ones.
@@ -95,23 +130,56 @@ pub fn append_line_sync(file: &mut File, path: &Path, line: &str) -> Result<()>
 /// lines are loaded. This trades a potentially lost last write for
 /// deterministic startup behavior.
 pub fn read_all(path: &Path) -> Result<Vec<Value>> {
+    read_all_with_rejects(path).map(|(docs, _)| docs)
+}
+
+/// Like `read_all`, but also returns the lines that were skipped.
+pub fn read_all_with_rejects(path: &Path) -> Result<(Vec<Value>, Vec<RejectedLine>)> {
     let file = File::open(path).map_err(Error::io_err(path, "open JSONL for read"))?;
-    let reader = BufReader::new(file);
+    let mut reader = BufReader::new(file);
     let mut docs = Vec::new();
-    let mut corrupted_lines = 0usize;
-
-    for (line_num, line_result) in reader.lines().enumerate() {
-        let line = match line_result {
+    let mut rejects = Vec::new();
+    let mut buf = Vec::new();
+    let mut next_offset = 0u64;
+
+    for line_num in 0.. {
+        buf.clear();
+        let offset = next_offset;
+        match reader.read_until(b'\n', &mut buf) {
+            Ok(0) => break,
+            Ok(n) => next_offset += n as u64,
+            Err(e) => {
+                // I/O error reading a line — nothing after it can be trusted
+                eprintln!(
+                    "ndb: skipping unreadable line {} in {}: {}",
+                    line_num,
+                    path.display(),
+                    e
+                );
+                rejects.push(RejectedLine {
+                    line: line_num + 1,
+                    offset,
+                    raw: String::from_utf8_lossy(&buf).into_owned(),
+                    error: e.to_string(),
+                });
+                break;
+            }
+        }
+        let line = match std::str::from_utf8(&buf) {
             Ok(l) => l,
             Err(e) => {
-                // I/O error reading a line — likely truncated at EOF
                 eprintln!(
                     "ndb: skipping unreadable line {} in {}: {}",
                     line_num,
                     path.display(),
                     e
                 );
-                corrupted_lines += 1;
+                rejects.push(RejectedLine {
+                    line: line_num + 1,
+                    offset,
+                    raw: String::from_utf8_lossy(&buf).trim_end().to_string(),
+                    error: e.to_string(),
+                });
                 continue;
             }
         };
@@ -128,7 +196,12 @@ pub fn read_all(path: &Path) -> Result<Vec<Value>> {
                     "ndb: corrupted meta header in {}, attempting recovery",
                     path.display()
                 );
-                corrupted_lines += 1;
+                rejects.push(RejectedLine {
+                    line: 1,
+                    offset: 0,
+                    raw: trimmed.to_string(),
+                    error: "corrupted meta header".to_string(),
+                });
             }
             continue;
         }
@@ -142,52 +215,239 @@ pub fn read_all(path: &Path) -> Result<Vec<Value>> {
                     path.display(),
                     e
                 );
-                corrupted_lines += 1;
+                rejects.push(RejectedLine {
+                    line: line_num + 1,
+                    offset,
+                    raw: trimmed.to_string(),
+                    error: e.to_string(),
+                });
             }
         }
     }
 
-    if corrupted_lines > 0 {
+    if !rejects.is_empty() {
         eprintln!(
             "ndb: recovered {} corrupted line(s) in {} ({} valid docs loaded)",
-            corrupted_lines,
+            rejects.len(),
             path.display(),
             docs.len()
         );
     }
 
-    Ok(docs)
+    Ok((docs, rejects))
+}
+
+/// Append rejected lines to a quarantine file, one JSON record per line.
+/// Creates the file and its parent directories if needed.
+pub fn append_quarantine(path: &Path, source: &Path, rejects: &[RejectedLine]) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(Error::io_err(parent, "create quarantine directory"))?;
+    }
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map_err(Error::io_err(path, "open quarantine file"))?;
+    let at = chrono_free_timestamp();
+    for reject in rejects {
+        let record = serde_json::json!({
+            "source": source.display().to_string(),
+            "quarantined": at,
+            "line": reject.line,
+            "offset": reject.offset,
+            "error": reject.error,
+            "raw": reject.raw,
+        });
+        writeln!(file, "{}", serde_json::to_string(&record)?)
+            .map_err(Error::io_err(path, "write quarantine record"))?;
+    }
+    file.sync_all().map_err(Error::io_err(path, "fsync quarantine file"))?;
+    Ok(())
+}
+
+/// Read the records appended after byte `offset`.
+///
+/// Only complete (newline-terminated) lines are consumed, so a write still
+/// in flight is picked up by the next call. Returns the parsed records and
+/// the offset to resume from. The `_meta` header is skipped when reading
+/// from the start of the file; malformed lines are skipped as in `read_all`.
+pub fn read_from(path: &Path, offset: u64) -> Result<(Vec<Value>, u64)> {
+    let mut file = File::open(path).map_err(Error::io_err(path, "open JSONL for tail read"))?;
+    file.seek(SeekFrom::Start(offset))
+        .map_err(Error::io_err(path, "seek JSONL"))?;
+    let mut buf = Vec::new();
+    file.read_to_end(&mut buf)
+        .map_err(Error::io_err(path, "read JSONL tail"))?;
+
+    let complete = match buf.iter().rposition(|&b| b == b'\n') {
+        Some(pos) => pos + 1,
+        None => return Ok((Vec::new(), offset)),
+    };
+
+    let mut records = Vec::new();
+    let mut line_start = offset;
+    for (i, raw) in buf[..complete].split(|&b| b == b'\n').enumerate() {
+        let at = line_start;
+        line_start += raw.len() as u64 + 1;
+        let line = String::from_utf8_lossy(raw);
+        let trimmed = line.trim();
+        if trimmed.is_empty() || (offset == 0 && i == 0 && trimmed.contains("\"_meta\"")) {
+            continue;
+        }
+        match serde_json::from_str::<Value>(trimmed) {
+            Ok(val) => records.push(val),
+            Err(e) => eprintln!(
+                "ndb: skipping corrupted line at byte {} in {}: {}",
+                at,
+                path.display(),
+                e
+            ),
+        }
+    }
+
+    Ok((records, offset + complete as u64))
+}
+
+/// Fsync the directory holding `path`, so a rename or create inside it
+/// survives power loss. A no-op where directories cannot be opened as
+/// files (Windows, whose metadata journal covers renames already).
+pub fn sync_dir(path: &Path) -> Result<()> {
+    #[cfg(unix)]
+    {
+        let dir = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        File::open(dir)
+            .and_then(|d| d.sync_all())
+            .map_err(Error::io_err(dir, "fsync directory"))?;
+    }
+    #[cfg(not(unix))]
+    let _ = path;
+    Ok(())
+}
+
+/// Read `meta.json` in `base_dir`, if it exists.
+pub fn read_meta(base_dir: &Path) -> Result<Option<Value>> {
+    let path = base_dir.join("meta.json");
+    if !path.exists() {
+        return Ok(None);
+    }
+    let text = fs::read_to_string(&path).map_err(Error::io_err(&path, "read meta.json"))?;
+    serde_json::from_str(&text)
+        .map(Some)
+        .map_err(|e| Error::corruption(&path, format!("invalid meta.json: {}", e)))
+}
+
+/// Replace `meta.json` in `base_dir` through a synced temp file and rename.
+pub fn write_meta(base_dir: &Path, meta: &Value) -> Result<()> {
+    let path = base_dir.join("meta.json");
+    let tmp_path = base_dir.join("meta.json.tmp");
+    let written = (|| {
+        let mut file = File::create(&tmp_path).map_err(Error::io_err(&tmp_path, "create meta.json temp file"))?;
+        serde_json::to_writer_pretty(&mut file, meta)?;
+        file.sync_all().map_err(Error::io_err(&tmp_path, "fsync meta.json temp file"))
+    })();
+    if let Err(e) = written {
+        let _ = fs::remove_file(&tmp_path);
+        return Err(e);
+    }
+    fs::rename(&tmp_path, &path).map_err(Error::io_err(&path, "replace meta.json"))?;
+    sync_dir(&path)
+}
+
+/// Read the `_meta` header line of a JSONL file, if present.
+pub fn read_header(path: &Path) -> Result<Option<String>> {
+    let file = File::open(path).map_err(Error::io_err(path, "open JSONL for read"))?;
+    let mut line = String::new();
+    BufReader::new(file)
+        .read_line(&mut line)
+        .map_err(Error::io_err(path, "read meta header"))?;
+    let trimmed = line.trim();
+    Ok((trimmed.contains("\"_meta\"")).then(|| trimmed.to_string()))
 }
 
 /// Rewrite a JSONL file with only the given documents.
 /// Writes to a temp file first, then atomic rename.
 pub fn rewrite_atomic(path: &Path, docs: &[&Value]) -> Result<()> {
+    let tmp_path = write_temp(path, docs, |_| Ok(()))?;
+
+    // Atomic rename
+    fs::rename(&tmp_path, path).map_err(Error::io_err(path, "atomic rename after compaction"))?;
+    sync_dir(path)
+}
+
+/// Write the meta header and `docs` to `path`'s temp file and sync it.
+/// Calls `check` with the number of docs written after each one; an error
+/// from `check` (or any write) removes the temp file.
+pub fn write_temp(
+    path: &Path,
+    docs: &[&Value],
+    mut check: impl FnMut(usize) -> Result<()>,
+) -> Result<PathBuf> {
     let tmp_path = path.with_extension("jsonl.tmp");
 
-    {
+    let written = (|| {
         let mut tmp_file = File::create(&tmp_path)
             .map_err(Error::io_err(&tmp_path, "create temp file for compaction"))?;
         // Write meta header
         writeln!(tmp_file, "{}", meta_line())
             .map_err(Error::io_err(&tmp_path, "write meta header"))?;
         // Write all active docs
-        for doc in docs {
+        for (i, doc) in docs.iter().enumerate() {
             let line = serde_json::to_string(doc)?;
             writeln!(tmp_file, "{}", line)
                 .map_err(Error::io_err(&tmp_path, "write doc during compaction"))?;
+            check(i + 1)?;
         }
         tmp_file
             .flush()
             .map_err(Error::io_err(&tmp_path, "flush temp file"))?;
         tmp_file
             .sync_all()
-            .map_err(Error::io_err(&tmp_path, "fsync temp file"))?;
+            .map_err(Error::io_err(&tmp_path, "fsync temp file"))
+    })();
+    match written {
+        Ok(()) => Ok(tmp_path),
+        Err(e) => {
+            let _ = fs::remove_file(&tmp_path);
+            Err(e)
+        }
     }
+}
 
-    // Atomic rename
-    fs::rename(&tmp_path, path).map_err(Error::io_err(path, "atomic rename after compaction"))?;
+/// Append everything in `path` from byte `offset` on to `tmp_path`, sync it,
+/// and rename it over `path`.
+///
+/// Used by compaction to keep records written while the temp file was being
+/// built. The caller must stop appends to `path` for the duration. On error
+/// the temp file is removed and `path` is untouched.
+pub fn swap_with_tail(path: &Path, tmp_path: &Path, offset: u64) -> Result<()> {
+    let copied = (|| {
+        let mut src = File::open(path).map_err(Error::io_err(path, "open JSONL for tail copy"))?;
+        src.seek(SeekFrom::Start(offset))
+            .map_err(Error::io_err(path, "seek JSONL"))?;
+        let mut tmp_file = OpenOptions::new()
+            .append(true)
+            .open(tmp_path)
+            .map_err(Error::io_err(tmp_path, "reopen temp file"))?;
+        let carried = std::io::copy(&mut src, &mut tmp_file)
+            .map_err(Error::io_err(tmp_path, "copy log tail"))?;
+        if carried > 0 {
+            tmp_file
+                .sync_all()
+                .map_err(Error::io_err(tmp_path, "fsync temp file"))?;
+        }
+        Ok(())
+    })();
+    if let Err(e) = copied {
+        let _ = fs::remove_file(tmp_path);
+        return Err(e);
+    }
 
-    Ok(())
+    // Atomic rename
+    fs::rename(tmp_path, path).map_err(Error::io_err(path, "atomic rename after compaction"))?;
+    sync_dir(path)
 }
 
 /// Append a single document to the persistent trash file.
@@ -247,6 +507,17 @@ mod tests {
         assert!(init_file(&path).is_err());
     }
 
+    #[test]
+    fn sync_dir_handles_nested_and_bare_paths() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("nested/test.jsonl");
+        init_file(&path).unwrap();
+        sync_dir(&path).unwrap();
+        sync_dir(Path::new("test.jsonl")).unwrap();
+        #[cfg(unix)]
+        assert!(sync_dir(&dir.path().join("missing/test.jsonl")).is_err());
+    }
+
     #[test]
     fn append_and_read() {
         let dir = TempDir::new().unwrap();
@@ -262,6 +533,16 @@ mod tests {
         assert_eq!(docs[0]["name"], "test");
     }
 
+    #[test]
+    fn headers_differ_within_the_same_second() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("test.jsonl");
+        init_file(&path).unwrap();
+        let first = read_header(&path).unwrap();
+        let tmp = write_temp(&path, &[], |_| Ok(())).unwrap();
+        assert_ne!(read_header(&tmp).unwrap(), first);
+    }
+
     #[test]
     fn read_skips_meta_header() {
         let dir = TempDir::new().unwrap();
@@ -304,6 +585,7 @@ mod tests {
     // ─── Phase 6: Corruption Recovery Tests ──────────────────────────
 
     #[test]
+    #[allow(clippy::write_literal)]
     fn read_all_skips_truncated_line() {
         let dir = TempDir::new().unwrap();
         let path = dir.path().join("truncated.jsonl");
@@ -327,6 +609,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::write_literal)]
     fn read_all_handles_empty_lines() {
         let dir = TempDir::new().unwrap();
         let path = dir.path().join("gaps.jsonl");
@@ -357,6 +640,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::write_literal)]
     fn read_all_handles_partial_last_line() {
         let dir = TempDir::new().unwrap();
         let path = dir.path().join("partial.jsonl");
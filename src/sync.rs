@@ -0,0 +1,143 @@
+//! Background log syncing for `Persistence::Scheduled` and `Persistence::GroupCommit`.
+//!
+//! Writers append to the log without syncing and bump a write sequence
+//! number. A background thread wakes every interval and, if anything was
+//! written since the last sync, fsyncs the log once for all of it. Under
+//! `GroupCommit` each writer then blocks in `wait` until a sync covers its
+//! write, so many concurrent writes share one fsync.
+
+use parking_lot::{Condvar, Mutex};
+use std::fs::File;
+use std::io;
+use std::time::Duration;
+
+/// Shortest interval the sync thread waits between wakeups. A zero interval
+/// (e.g. `groupCommitMs: 0` from Node) would otherwise spin a core.
+pub(crate) const MIN_INTERVAL: Duration = Duration::from_millis(1);
+
+#[derive(Debug, Default)]
+struct SyncState {
+    /// Sequence number of the last record appended.
+    written: u64,
+    /// Highest sequence number known to be on disk.
+    synced: u64,
+    /// First failed sync. Sticky: once a sync has failed the page cache can
+    /// no longer be trusted, so every later wait fails too.
+    error: Option<(io::ErrorKind, String)>,
+}
+
+/// Write/sync sequence numbers shared between writers and the sync thread.
+#[derive(Debug, Default)]
+pub(crate) struct Syncer {
+    state: Mutex<SyncState>,
+    synced_cv: Condvar,
+}
+
+impl Syncer {
+    /// Record one appended record. Call while holding the file handle lock,
+    /// so the sync thread never sees a sequence number ahead of the file.
+    pub(crate) fn written(&self) {
+        self.state.lock().written += 1;
+    }
+
+    /// Sequence number of the last appended record.
+    pub(crate) fn last_written(&self) -> u64 {
+        self.state.lock().written
+    }
+
+    /// True when records were appended since the last sync.
+    pub(crate) fn is_dirty(&self) -> bool {
+        let state = self.state.lock();
+        state.written > state.synced
+    }
+
+    /// Mark everything up to `seq` as on disk and wake waiting writers.
+    pub(crate) fn synced_through(&self, seq: u64) {
+        let mut state = self.state.lock();
+        state.synced = state.synced.max(seq);
+        self.synced_cv.notify_all();
+    }
+
+    /// Record a failed sync and fail every waiting writer.
+    pub(crate) fn failed(&self, e: &io::Error) {
+        let mut state = self.state.lock();
+        state.error.get_or_insert_with(|| (e.kind(), e.to_string()));
+        self.synced_cv.notify_all();
+    }
+
+    /// The sticky sync error, if a sync has failed.
+    pub(crate) fn error(&self) -> Option<io::Error> {
+        let state = self.state.lock();
+        state.error.as_ref().map(|(kind, message)| io::Error::new(*kind, message.clone()))
+    }
+
+    /// Block until every record appended so far is on disk.
+    pub(crate) fn wait(&self) -> io::Result<()> {
+        let mut state = self.state.lock();
+        let target = state.written;
+        loop {
+            if let Some((kind, ref message)) = state.error {
+                return Err(io::Error::new(kind, message.clone()));
+            }
+            if state.synced >= target {
+                return Ok(());
+            }
+            self.synced_cv.wait(&mut state);
+        }
+    }
+
+    /// One tick of the sync thread: fsync `file` if anything is unsynced.
+    ///
+    /// `target` must have been read under the file handle lock that `file`
+    /// was cloned from.
+    pub(crate) fn sync(&self, file: &File, target: u64) -> io::Result<()> {
+        match file.sync_data() {
+            Ok(()) => {
+                self.synced_through(target);
+                Ok(())
+            }
+            Err(e) => {
+                self.failed(&e);
+                Err(e)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    #[test]
+    fn wait_blocks_until_synced() {
+        let syncer = Arc::new(Syncer::default());
+        assert!(syncer.wait().is_ok());
+
+        syncer.written();
+        syncer.written();
+        assert!(syncer.is_dirty());
+
+        let waiter = {
+            let syncer = syncer.clone();
+            std::thread::spawn(move || syncer.wait())
+        };
+        std::thread::sleep(Duration::from_millis(20));
+        assert!(!waiter.is_finished());
+
+        syncer.synced_through(syncer.last_written());
+        assert!(waiter.join().unwrap().is_ok());
+        assert!(!syncer.is_dirty());
+    }
+
+    #[test]
+    fn failure_is_sticky() {
+        let syncer = Syncer::default();
+        syncer.written();
+        syncer.failed(&io::Error::other("disk gone"));
+        syncer.synced_through(1);
+        let err = syncer.wait().unwrap_err();
+        assert_eq!(err.to_string(), "disk gone");
+    }
+}
@@ -0,0 +1,346 @@
+//! Integrity verification (fsck) for a database's log and in-memory state.
+//!
+//! `Database::verify` walks the whole log and collects every problem it
+//! finds into a `VerifyReport` instead of stopping at the first one, so a
+//! restored backup can be judged as a whole before it is trusted.
+
+use crate::bucket::FileRef;
+use crate::error::{Error, Result};
+use crate::replay_record;
+use crate::storage::STORAGE_VERSION;
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+
+
+/// How much `Database::verify` checks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum VerifyLevel {
+    /// Log structure only: header, JSON syntax, record shape, patch targets.
+    Quick,
+    /// `Quick`, plus replayed state vs. memory, secondary indexes,
+    /// file-bucket references and the document trash file.
+    #[default]
+    Full,
+}
+
+/// Category of a problem found by `verify`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IssueKind {
+    /// First line is not a `_meta` header, or its version is unreadable or unsupported.
+    BadHeader,
+    /// A line is not a JSON object.
+    CorruptLine,
+    /// The last line has no trailing newline (an interrupted append).
+    TornTail,
+    /// A document or batch entry has no string `_id`.
+    MissingId,
+    /// A record carries an `_op` this version does not know.
+    UnknownOp,
+    /// A patch targets a document that does not exist at that point in the
+    /// log; replay skips it, so the write is lost.
+    OrphanPatch,
+    /// The same `_id` appears twice within one batch record.
+    DuplicateId,
+    /// Replaying the log does not reproduce the in-memory document.
+    StateMismatch,
+    /// A secondary index is missing a document or points at a stale one.
+    IndexMismatch,
+    /// A referenced file is missing from its bucket.
+    MissingFile,
+    /// A line of the document trash file is not valid JSON.
+    CorruptTrash,
+}
+
+/// One problem found by `verify`.
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct Issue {
+    pub kind: IssueKind,
+    /// 1-based line in the file the issue was found in, if any.
+    pub line: Option<usize>,
+    /// Document the issue concerns, if any.
+    pub id: Option<String>,
+    /// Human-readable description.
+    pub detail: String,
+}
+
+/// Outcome of `Database::verify`.
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize)]
+pub struct VerifyReport {
+    /// Records read from the log (excluding the header).
+    pub records: usize,
+    /// Live documents after replaying the log.
+    pub documents: usize,
+    /// Every problem found, in discovery order.
+    pub issues: Vec<Issue>,
+}
+
+impl VerifyReport {
+    /// True when no issues were found.
+    pub fn is_ok(&self) -> bool {
+        self.issues.is_empty()
+    }
+
+    /// Number of issues of the given kind.
+    pub fn count(&self, kind: IssueKind) -> usize {
+        self.issues.iter().filter(|i| i.kind == kind).count()
+    }
+
+    pub(crate) fn push(&mut self, kind: IssueKind, line: Option<usize>, id: Option<&str>, detail: impl Into<String>) {
+        self.issues.push(Issue {
+            kind,
+            line,
+            id: id.map(str::to_string),
+            detail: detail.into(),
+        });
+    }
+}
+
+/// Result of scanning a log: the report so far plus the replayed state.
+pub(crate) struct LogScan {
+    pub(crate) report: VerifyReport,
+    pub(crate) docs: HashMap<String, Value>,
+}
+
+/// Read the log at `path` line by line, checking structure and replaying it.
+pub(crate) fn scan_log(path: &Path) -> Result<LogScan> {
+    let file = File::open(path).map_err(Error::io_err(path, "open JSONL for verify"))?;
+    let mut reader = BufReader::new(file);
+    let mut report = VerifyReport::default();
+    let mut docs = HashMap::new();
+    let mut deleted = HashSet::new();
+
+    let mut buf = Vec::new();
+    let mut line_no = 0;
+    loop {
+        buf.clear();
+        let n = reader
+            .read_until(b'\n', &mut buf)
+            .map_err(Error::io_err(path, "read JSONL for verify"))?;
+        if n == 0 {
+            break;
+        }
+        line_no += 1;
+        let line = String::from_utf8_lossy(&buf);
+        let trimmed = line.trim();
+        let torn = !buf.ends_with(b"\n");
+
+        if line_no == 1 {
+            check_header(&mut report, trimmed);
+            continue;
+        }
+        if trimmed.is_empty() {
+            continue;
+        }
+        let record = match serde_json::from_str::<Value>(trimmed) {
+            Ok(v) if v.is_object() => v,
+            Ok(_) => {
+                report.push(IssueKind::CorruptLine, Some(line_no), None, "record is not a JSON object");
+                continue;
+            }
+            Err(e) if torn => {
+                report.push(IssueKind::TornTail, Some(line_no), None, format!("incomplete final line: {}", e));
+                continue;
+            }
+            Err(e) => {
+                report.push(IssueKind::CorruptLine, Some(line_no), None, e.to_string());
+                continue;
+            }
+        };
+        if torn {
+            // Complete JSON but never newline-terminated: the next append would corrupt it
+            report.push(IssueKind::TornTail, Some(line_no), None, "final line has no trailing newline");
+        }
+
+        report.records += 1;
+        check_record(&mut report, &docs, line_no, &record);
+        replay_record(&mut docs, &mut deleted, record);
+    }
+
+    if line_no == 0 {
+        report.push(IssueKind::BadHeader, None, None, "log is empty");
+    }
+    report.documents = docs.len();
+    Ok(LogScan { report, docs })
+}
+
+fn check_header(report: &mut VerifyReport, line: &str) {
+    let header: Value = match serde_json::from_str(line) {
+        Ok(v) => v,
+        Err(e) => {
+            report.push(IssueKind::BadHeader, Some(1), None, format!("unparseable header: {}", e));
+            return;
+        }
+    };
+    match header.get("_meta").and_then(|m| m.get("version")).and_then(Value::as_u64) {
+        None => report.push(IssueKind::BadHeader, Some(1), None, "missing _meta.version"),
+        Some(v) if v > STORAGE_VERSION => report.push(
+            IssueKind::BadHeader,
+            Some(1),
+            None,
+            format!("format version {} is newer than supported version {}", v, STORAGE_VERSION),
+        ),
+        Some(_) => {}
+    }
+}
+
+fn check_record(report: &mut VerifyReport, docs: &HashMap<String, Value>, line: usize, record: &Value) {
+    let op = record.get("_op").and_then(Value::as_str);
+    if op == Some("batch") {
+        let entries = match record.get("docs").and_then(Value::as_array) {
+            Some(entries) => entries,
+            None => {
+                report.push(IssueKind::CorruptLine, Some(line), None, "batch record without a docs array");
+                return;
+            }
+        };
+        let mut seen = HashSet::new();
+        for entry in entries {
+            match entry.get("_id").and_then(Value::as_str) {
+                Some(id) if !seen.insert(id) => {
+                    report.push(IssueKind::DuplicateId, Some(line), Some(id), "duplicate _id within batch");
+                }
+                Some(_) => {}
+                None => report.push(IssueKind::MissingId, Some(line), None, "batch entry without _id"),
+            }
+        }
+        return;
+    }
+
+    let id = match record.get("_id").and_then(Value::as_str) {
+        Some(id) => id,
+        None => {
+            report.push(IssueKind::MissingId, Some(line), None, "record without _id");
+            return;
+        }
+    };
+    if record.get("_deleted").is_some() {
+        return;
+    }
+    match op {
+        None | Some("idem") => {}
+        Some("set" | "remove" | "array_push") if docs.contains_key(id) => {}
+        Some(patch @ ("set" | "remove" | "array_push")) => {
+            report.push(
+                IssueKind::OrphanPatch,
+                Some(line),
+                Some(id),
+                format!("{} patch for a document that does not exist", patch),
+            );
+        }
+        Some(other) => {
+            report.push(IssueKind::UnknownOp, Some(line), Some(id), format!("unknown _op '{}'", other));
+        }
+    }
+}
+
+/// Collect file references from a document: embedded `{bucket, id, ext}`
+/// objects and `bucket:hash.ext` strings whose hash is hex.
+pub(crate) fn collect_file_refs(value: &Value, refs: &mut Vec<FileRef>) {
+    match value {
+        Value::String(s) => {
+            if let Some(r) = FileRef::from_compact(s) {
+                if !r.bucket.is_empty() && r.id.len() >= 8 && r.id.chars().all(|c| c.is_ascii_hexdigit()) {
+                    refs.push(r);
+                }
+            }
+        }
+        Value::Array(items) => items.iter().for_each(|v| collect_file_refs(v, refs)),
+        Value::Object(map) => {
+            let field = |k: &str| map.get(k).and_then(Value::as_str);
+            if let (Some(bucket), Some(id), Some(ext)) = (field("bucket"), field("id"), field("ext")) {
+                refs.push(FileRef {
+                    bucket: bucket.to_string(),
+                    id: id.to_string(),
+                    ext: ext.to_string(),
+                });
+                return;
+            }
+            map.values().for_each(|v| collect_file_refs(v, refs));
+        }
+        _ => {}
+    }
+}
+
+/// Check that every line of the document trash file parses.
+pub(crate) fn scan_trash(report: &mut VerifyReport, path: &Path) -> Result<()> {
+    if !path.exists() {
+        return Ok(());
+    }
+    let file = File::open(path).map_err(Error::io_err(path, "open trash for verify"))?;
+    for (i, line) in BufReader::new(file).lines().enumerate() {
+        let line = line.map_err(Error::io_err(path, "read trash for verify"))?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        if let Err(e) = serde_json::from_str::<Value>(&line) {
+            report.push(IssueKind::CorruptTrash, Some(i + 1), None, e.to_string());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    fn scan(contents: &str) -> VerifyReport {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("v.jsonl");
+        fs::write(&path, contents).unwrap();
+        scan_log(&path).unwrap().report
+    }
+
+    const HEADER: &str = "{\"_meta\":{\"version\":1,\"created\":\"0\"}}\n";
+
+    #[test]
+    fn clean_log_has_no_issues() {
+        let report = scan(&format!(
+            "{}{}\n{}\n{}\n",
+            HEADER,
+            r#"{"_id":"a","n":1}"#,
+            r#"{"_id":"a","_op":"set","path":"n","value":2}"#,
+            r#"{"_op":"batch","docs":[{"_id":"b"},{"_id":"c"}]}"#,
+        ));
+        assert!(report.is_ok(), "{:?}", report.issues);
+        assert_eq!(report.records, 3);
+        assert_eq!(report.documents, 3);
+    }
+
+    #[test]
+    fn collects_every_issue() {
+        let report = scan(&format!(
+            "{}{}\n{}\n{}\n{}\n{}\n{}",
+            HEADER,
+            "{not json",
+            r#"{"n":1}"#,
+            r#"{"_id":"ghost","_op":"set","path":"n","value":2}"#,
+            r#"{"_id":"a","_op":"frobnicate"}"#,
+            r#"{"_op":"batch","docs":[{"_id":"x"},{"_id":"x"}]}"#,
+            r#"{"_id":"b","#,
+        ));
+        assert_eq!(report.count(IssueKind::CorruptLine), 1);
+        assert_eq!(report.count(IssueKind::MissingId), 1);
+        assert_eq!(report.count(IssueKind::OrphanPatch), 1);
+        assert_eq!(report.count(IssueKind::UnknownOp), 1);
+        assert_eq!(report.count(IssueKind::DuplicateId), 1);
+        assert_eq!(report.count(IssueKind::TornTail), 1);
+        assert_eq!(report.issues[0].line, Some(2));
+        assert_eq!(report.issues.len(), 6);
+    }
+
+    #[test]
+    fn header_checks() {
+        assert_eq!(scan("").count(IssueKind::BadHeader), 1);
+        assert_eq!(scan("{\"_id\":\"a\"}\n").count(IssueKind::BadHeader), 1);
+        assert_eq!(
+            scan("{\"_meta\":{\"version\":9,\"created\":\"0\"}}\n").count(IssueKind::BadHeader),
+            1
+        );
+    }
+}
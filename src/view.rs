@@ -0,0 +1,115 @@
+//! Consistent read views.
+//!
+//! `Database::snapshot_view` pins the document store under its read lock so
+//! a sequence of reads sees one state. Writers block until the view is
+//! dropped; `flush` only reads the store and keeps running.
+
+use crate::error::{Error, Result};
+use crate::{apply_query_options, query_matches, values_equal, Database, QueryOptions};
+use parking_lot::RwLockReadGuard;
+use serde_json::Value;
+use std::collections::HashMap;
+
+/// Read-only handle on the database state at the moment it was taken.
+///
+/// Keep it short-lived: every write waits for it. Read through the view
+/// itself; calling `Database` methods on the same thread while holding it
+/// can deadlock.
+pub struct SnapshotView<'a> {
+    docs: RwLockReadGuard<'a, HashMap<String, Value>>,
+}
+
+impl Database {
+    /// Pin the current state for a sequence of consistent reads.
+    pub fn snapshot_view(&self) -> SnapshotView<'_> {
+        SnapshotView {
+            docs: self.docs.read(),
+        }
+    }
+}
+
+impl SnapshotView<'_> {
+    /// Get a document by ID.
+    pub fn get(&self, id: &str) -> Result<Value> {
+        self.docs.get(id).cloned().ok_or_else(|| Error::not_found(id))
+    }
+
+    /// Check if a document exists.
+    pub fn contains(&self, id: &str) -> bool {
+        self.docs.contains_key(id)
+    }
+
+    /// Number of documents.
+    pub fn len(&self) -> usize {
+        self.docs.len()
+    }
+
+    /// True when there are no documents.
+    pub fn is_empty(&self) -> bool {
+        self.docs.is_empty()
+    }
+
+    /// All documents.
+    pub fn iter(&self) -> Vec<Value> {
+        self.docs.values().cloned().collect()
+    }
+
+    /// Documents whose top-level `field` equals `value`.
+    pub fn find(&self, field: &str, value: &Value) -> Vec<Value> {
+        self.docs
+            .values()
+            .filter(|doc| doc.get(field).is_some_and(|v| values_equal(v, value)))
+            .cloned()
+            .collect()
+    }
+
+    /// Execute a JSON AST query. Always scans: secondary indexes may already
+    /// reflect writes waiting behind the view.
+    pub fn query(&self, ast: &Value) -> Vec<Value> {
+        self.docs
+            .values()
+            .filter(|doc| query_matches(doc, ast))
+            .cloned()
+            .collect()
+    }
+
+    /// Execute a JSON AST query with options (limit, sort, offset).
+    pub fn query_with(&self, ast: &Value, opts: &QueryOptions) -> Vec<Value> {
+        apply_query_options(self.query(ast), opts)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    #[test]
+    fn view_holds_state_until_dropped() {
+        let db = Arc::new(Database::open_in_memory().unwrap());
+        let id = db.insert(json!({"n": 1, "kind": "a"})).unwrap();
+
+        let view = db.snapshot_view();
+        let writer = {
+            let db = Arc::clone(&db);
+            let id = id.clone();
+            std::thread::spawn(move || {
+                db.update(&id, json!({"n": 2, "kind": "b"})).unwrap();
+                db.insert(json!({"n": 3})).unwrap();
+            })
+        };
+        std::thread::sleep(Duration::from_millis(20));
+
+        assert_eq!(view.get(&id).unwrap()["n"], 1);
+        assert_eq!(view.len(), 1);
+        assert_eq!(view.find("kind", &json!("a")).len(), 1);
+        assert_eq!(view.query(&json!({"n": {"$gte": 1}})).len(), 1);
+        drop(view);
+
+        writer.join().unwrap();
+        assert_eq!(db.len(), 2);
+        assert_eq!(db.snapshot_view().get(&id).unwrap()["n"], 2);
+    }
+}
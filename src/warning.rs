@@ -0,0 +1,107 @@
+//! Degradations that do not fail the call that hit them.
+//!
+//! Side effects such as archiving a deleted document, moving its files to
+//! the trash or the background TTL purge are best effort: their failure
+//! must not fail the delete or restore that triggered them. Instead of
+//! being dropped, they are recorded here and drained with
+//! `Database::take_warnings`.
+
+use parking_lot::Mutex;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Warnings kept until drained; older ones are dropped first.
+const KEEP: usize = 256;
+
+/// Category of a warning.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum WarningKind {
+    /// A deleted document could not be written to the trash file, so
+    /// `restore` will not find it.
+    TrashArchive,
+    /// A bucket file of a deleted or purged document could not be moved to
+    /// the trash or removed.
+    FileCleanup,
+    /// A bucket file of a restored document could not be brought back.
+    FileRestore,
+    /// A background TTL purge of the trash failed.
+    TrashPurge,
+    /// Leftover temp files from an interrupted write could not be removed.
+    TempCleanup,
+    /// A background fsync failed; the next write reports the error.
+    BackgroundSync,
+}
+
+/// A recorded degradation.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct Warning {
+    pub kind: WarningKind,
+    /// Human-readable description, including the underlying error.
+    pub message: String,
+    /// Unix seconds when it happened.
+    pub at: u64,
+}
+
+/// Bounded log of warnings, shared with background threads.
+#[derive(Debug, Default)]
+pub(crate) struct Warnings {
+    recent: Mutex<VecDeque<Warning>>,
+    total: AtomicU64,
+}
+
+impl Warnings {
+    pub(crate) fn push(&self, kind: WarningKind, message: impl Into<String>) {
+        let message = message.into();
+        #[cfg(feature = "tracing")]
+        tracing::warn!(?kind, %message, "ndb warning");
+        let at = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let mut recent = self.recent.lock();
+        if recent.len() == KEEP {
+            recent.pop_front();
+        }
+        recent.push_back(Warning { kind, message, at });
+        self.total.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Record `result`'s error, if any, under `kind`.
+    pub(crate) fn check<T, E: std::fmt::Display>(&self, kind: WarningKind, what: &str, result: Result<T, E>) {
+        if let Err(e) = result {
+            self.push(kind, format!("{}: {}", what, e));
+        }
+    }
+
+    pub(crate) fn take(&self) -> Vec<Warning> {
+        self.recent.lock().drain(..).collect()
+    }
+
+    /// Warnings recorded since open, drained or not.
+    pub(crate) fn total(&self) -> u64 {
+        self.total.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_the_most_recent_and_counts_all() {
+        let warnings = Warnings::default();
+        for n in 0..KEEP + 3 {
+            warnings.push(WarningKind::FileCleanup, n.to_string());
+        }
+        warnings.check(WarningKind::TrashPurge, "purge", Ok::<(), String>(()));
+        assert_eq!(warnings.total(), KEEP as u64 + 3);
+
+        let taken = warnings.take();
+        assert_eq!(taken.len(), KEEP);
+        assert_eq!(taken[0].message, "3");
+        assert!(warnings.take().is_empty());
+        assert_eq!(warnings.total(), KEEP as u64 + 3);
+    }
+}
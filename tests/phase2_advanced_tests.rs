@@ -2,7 +2,7 @@
 //!
 //! Tests update, iteration, compaction, trash, and persistence modes.
 
-use ndb::{Database, Persistence, TrashMode};
+use ndb::{Database, Durability, Error, FieldType, Limits, Persistence, QueryOptions, Schema, SortDir, TrashMode, WarningKind};
 use serde_json::json;
 use tempfile::TempDir;
 
@@ -123,6 +123,85 @@ fn compact_creates_trash_file() {
     assert_eq!(trash_files.len(), 1);
 }
 
+#[test]
+fn idempotent_writes_apply_once_across_restarts() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("idem.jsonl");
+    let db = Database::open(&path).unwrap();
+
+    let id = db.insert_idempotent("req-1", json!({"n": 1})).unwrap();
+    assert_eq!(db.insert_idempotent("req-1", json!({"n": 2})).unwrap(), id);
+    assert_eq!(db.len(), 1);
+    assert_eq!(db.get(&id).unwrap()["n"], 1);
+    assert!(db.get(&id).unwrap().get("_idem").is_none());
+    assert!(db.insert_idempotent("", json!({})).is_err());
+    drop(db);
+
+    // Keys are replayed from the log
+    let db = Database::open(&path).unwrap();
+    assert!(db.get(&id).unwrap().get("_idem").is_none());
+    assert_eq!(db.insert_idempotent("req-1", json!({"n": 3})).unwrap(), id);
+    assert_eq!(db.len(), 1);
+
+    // A retried delete neither fails nor removes the re-created document
+    db.delete_idempotent("req-2", &id).unwrap();
+    db.delete_idempotent("req-2", &id).unwrap();
+    let other = db.insert(json!({"n": 4})).unwrap();
+    db.delete_idempotent("req-2", &other).unwrap();
+    assert!(db.contains(&other));
+    assert!(matches!(db.delete_idempotent("req-3", &id), Err(Error::NotFound { .. })));
+
+    // Compaction carries live keys over into the rewritten log
+    db.compact().unwrap();
+    assert_eq!(db.insert_idempotent("req-1", json!({})).unwrap(), id);
+    drop(db);
+    let db = Database::open(&path).unwrap();
+    assert_eq!(db.insert_idempotent("req-1", json!({})).unwrap(), id);
+    db.delete_idempotent("req-2", &other).unwrap();
+    assert!(db.contains(&other));
+    assert_eq!(db.len(), 1);
+    assert!(db.verify(ndb::VerifyLevel::Full).unwrap().is_ok());
+
+    // Expired keys are not carried over
+    let db = db.with_idempotency_window(std::time::Duration::ZERO);
+    std::thread::sleep(std::time::Duration::from_millis(1100));
+    db.compact().unwrap();
+    drop(db);
+    let db = Database::open(&path).unwrap();
+    assert_ne!(db.insert_idempotent("req-1", json!({})).unwrap(), id);
+}
+
+#[test]
+fn failed_trash_archive_is_reported_as_warning() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("warn.jsonl");
+    let db = Database::open(&path).unwrap();
+    assert!(db.take_warnings().is_empty());
+
+    // A directory where the trash file should be makes archiving fail
+    std::fs::create_dir_all(dir.path().join("_trash").join("docs").join("warn.jsonl")).unwrap();
+    let id = db.insert(json!({"n": 1})).unwrap();
+    db.delete(&id).unwrap();
+    assert!(db.get(&id).is_err());
+
+    let warnings = db.take_warnings();
+    assert_eq!(warnings.len(), 1);
+    assert_eq!(warnings[0].kind, WarningKind::TrashArchive);
+    assert!(warnings[0].message.contains(&id));
+    assert!(db.take_warnings().is_empty());
+    assert_eq!(db.metrics().warnings, 1);
+
+    // Batch deletes share the same side effects
+    let id = db.insert(json!({"n": 2})).unwrap();
+    let mut batch = db.begin_batch();
+    batch.delete(&id);
+    batch.commit().unwrap();
+    let warnings = db.take_warnings();
+    assert_eq!(warnings.len(), 1);
+    assert_eq!(warnings[0].kind, WarningKind::TrashArchive);
+    assert!(warnings[0].message.contains(&id));
+}
+
 #[test]
 fn restore_deleted_document() {
     let dir = TempDir::new().unwrap();
@@ -183,6 +262,73 @@ fn scheduled_persistence_mode() {
     assert_eq!(db2.get(&id).unwrap()["scheduled"], true);
 }
 
+#[test]
+fn durability_overrides_persistence_per_call() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("durability.jsonl");
+    let db = Database::open(&path).unwrap();
+    let fsyncs = |db: &Database| db.metrics().fsync_latency.count;
+
+    // Lazy database: only the synced call fsyncs
+    db.insert(json!({"n": 1})).unwrap();
+    assert_eq!(fsyncs(&db), 0);
+    db.insert_with(json!({"n": 2}), Durability::Synced).unwrap();
+    assert_eq!(fsyncs(&db), 1);
+
+    // Immediate database: a buffered backfill skips the fsync
+    let db = db.with_persistence(Persistence::Immediate);
+    let before = fsyncs(&db);
+    db.insert_batch_with((0..10).map(|n| json!({"n": n})).collect(), Durability::Buffered)
+        .unwrap();
+    assert_eq!(fsyncs(&db), before);
+    db.insert(json!({"n": 3})).unwrap();
+    assert_eq!(fsyncs(&db), before + 1);
+    assert_eq!(db.len(), 13);
+
+    // Group commit: overridden calls do not wait for the next sync
+    let db = db.with_persistence(Persistence::GroupCommit(std::time::Duration::from_secs(60)));
+    let started = std::time::Instant::now();
+    db.insert_with(json!({"n": 4}), Durability::Buffered).unwrap();
+    db.insert_with(json!({"n": 5}), Durability::Synced).unwrap();
+    assert!(started.elapsed() < std::time::Duration::from_secs(10));
+    drop(db);
+    assert_eq!(Database::open(&path).unwrap().len(), 15);
+}
+
+#[test]
+fn persistence_switches_on_open_database() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("switch.jsonl");
+    let db = std::sync::Arc::new(
+        Database::open(&path)
+            .unwrap()
+            .with_persistence(Persistence::GroupCommit(std::time::Duration::from_secs(60))),
+    );
+
+    // A group commit writer waits for the next sync, a minute away
+    let writer = {
+        let db = std::sync::Arc::clone(&db);
+        std::thread::spawn(move || db.insert(json!({"n": 1})).unwrap())
+    };
+    std::thread::sleep(std::time::Duration::from_millis(50));
+    assert!(!writer.is_finished());
+
+    // Switching syncs what was written, which releases the waiting writer
+    let started = std::time::Instant::now();
+    db.set_persistence(Persistence::Lazy).unwrap();
+    let first = writer.join().unwrap();
+    assert!(started.elapsed() < std::time::Duration::from_secs(10));
+    assert_eq!(db.persistence(), Persistence::Lazy);
+
+    db.set_persistence(Persistence::Immediate).unwrap();
+    let second = db.insert(json!({"n": 2})).unwrap();
+    drop(db);
+
+    let db = Database::open(&path).unwrap();
+    assert!(db.contains(&first));
+    assert!(db.contains(&second));
+}
+
 #[test]
 fn trash_mode_off() {
     let dir = TempDir::new().unwrap();
@@ -198,3 +344,274 @@ fn trash_mode_off() {
     db.compact().unwrap();
     assert_eq!(db.len(), 0);
 }
+
+// ─── Schema Validation ───────────────────────────────────────────────
+
+fn schema_db() -> (Database, TempDir) {
+    let (db, dir) = setup();
+    let db = db.with_schema(
+        Schema::new()
+            .required("title", FieldType::String)
+            .field("year", FieldType::Integer)
+            .field("tags", FieldType::Array),
+    );
+    (db, dir)
+}
+
+#[test]
+fn schema_rejects_bad_inserts_without_writing() {
+    let (db, dir) = schema_db();
+
+    assert!(matches!(
+        db.insert(json!({"title": "ok", "year": "2021"})),
+        Err(Error::SchemaViolation { .. })
+    ));
+    assert!(db.insert(json!({"year": 2021})).is_err());
+    assert!(db
+        .insert_batch(vec![json!({"title": "a"}), json!({"title": 5})])
+        .is_err());
+    assert!(db.is_empty());
+
+    db.insert(json!({"title": "ok", "year": 2021})).unwrap();
+    drop(db);
+    let db = Database::open(dir.path().join("phase2.jsonl")).unwrap();
+    assert_eq!(db.len(), 1);
+}
+
+#[test]
+fn schema_checks_updates_and_patches() {
+    let (db, _dir) = schema_db();
+    let id = db.insert(json!({"title": "a", "tags": []})).unwrap();
+
+    assert!(db.update(&id, json!({"year": 1})).is_err());
+    assert!(db.set(&id, "year", json!("1999")).is_err());
+    assert!(db.remove(&id, "title").is_err());
+    assert!(db.array_push(&id, "title", json!("x")).is_err());
+
+    // Rejected patches leave the document untouched
+    assert_eq!(db.get(&id).unwrap(), json!({"_id": id, "_version": 1, "title": "a", "tags": []}));
+
+    db.set(&id, "year", json!(1999)).unwrap();
+    db.array_push(&id, "tags", json!("x")).unwrap();
+    assert_eq!(db.get(&id).unwrap()["tags"], json!(["x"]));
+}
+
+#[test]
+fn schema_loaded_from_meta_json() {
+    let dir = TempDir::new().unwrap();
+    std::fs::write(
+        dir.path().join("meta.json"),
+        r#"{"version": 1, "schema": {"year": {"type": "integer"}}}"#,
+    )
+    .unwrap();
+    let db = Database::open(dir.path().join("data.jsonl")).unwrap();
+
+    assert!(db.schema().is_some());
+    assert!(db.insert(json!({"year": "2021"})).is_err());
+    db.insert(json!({"year": 2021})).unwrap();
+}
+
+// ─── Metadata ────────────────────────────────────────────────────────
+
+#[test]
+fn metadata_is_kept_in_meta_json() {
+    let dir = TempDir::new().unwrap();
+    let meta_path = dir.path().join("meta.json");
+    std::fs::write(&meta_path, r#"{"version": 1, "schema": {"year": {"type": "integer"}}}"#).unwrap();
+    let db = Database::open(dir.path().join("data.jsonl")).unwrap();
+    assert_eq!(db.metadata(), json!({}));
+
+    let meta = json!({"model": "text-embedding-3-small", "dims": 1536});
+    db.set_metadata(meta.clone()).unwrap();
+    assert_eq!(db.metadata(), meta);
+    assert!(matches!(db.set_metadata(json!([1])), Err(Error::InvalidArgument { .. })));
+    drop(db);
+
+    // Other keys survive, and the metadata comes back on open
+    let file: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(&meta_path).unwrap()).unwrap();
+    assert_eq!(file["schema"]["year"]["type"], "integer");
+    assert!(!dir.path().join("meta.json.tmp").exists());
+    let db = Database::open(dir.path().join("data.jsonl")).unwrap();
+    assert_eq!(db.metadata()["model"], "text-embedding-3-small");
+    assert!(db.schema().is_some());
+
+    let mem = Database::open_in_memory().unwrap();
+    mem.set_metadata(json!({"a": 1})).unwrap();
+    assert_eq!(mem.metadata()["a"], 1);
+}
+
+// ─── Write Limits ────────────────────────────────────────────────────
+
+#[test]
+fn limits_reject_oversized_docs_and_ids() {
+    let (db, _dir) = setup();
+    let db = db.with_limits(Limits {
+        max_doc_bytes: Some(200),
+        max_id_len: Some(20),
+        ..Limits::default()
+    });
+
+    let err = db
+        .insert(json!({"title": "t", "body": "x".repeat(300)}))
+        .unwrap_err();
+    assert!(
+        matches!(err, Error::LimitExceeded { ref field, limit: 200, actual } if field == "body" && actual > 300),
+        "{}",
+        err
+    );
+    assert!(matches!(
+        db.insert_with_prefix("a_very_long_prefix", json!({})),
+        Err(Error::LimitExceeded { ref field, limit: 20, .. }) if field == "_id"
+    ));
+    assert!(db.is_empty());
+
+    // Patches are checked against the patched document
+    let id = db.insert(json!({"body": "short"})).unwrap();
+    assert!(db.set(&id, "body", json!("x".repeat(300))).is_err());
+    assert_eq!(db.get(&id).unwrap()["body"], "short");
+}
+
+#[test]
+fn write_rate_limit_throttles_and_recovers() {
+    let db = Database::open_in_memory().unwrap().with_limits(Limits {
+        max_writes_per_sec: Some(50),
+        ..Limits::default()
+    });
+
+    let ids = db.insert_batch((0..40).map(|n| json!({"n": n})).collect()).unwrap();
+    for id in &ids[..10] {
+        db.set(id, "n", json!(-1)).unwrap();
+    }
+    let retry_after = match db.insert(json!({"n": 99})) {
+        Err(Error::Throttled { retry_after }) => retry_after,
+        other => panic!("expected Throttled, got {:?}", other),
+    };
+    assert_eq!(db.len(), 40);
+
+    std::thread::sleep(retry_after);
+    db.insert(json!({"n": 99})).unwrap();
+}
+
+#[test]
+fn log_quota_rejects_growth_but_allows_deletes() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("quota.jsonl");
+    let db = Database::open(&path).unwrap().with_limits(Limits {
+        max_log_bytes: Some(1024),
+        ..Limits::default()
+    });
+
+    let mut ids = Vec::new();
+    let err = loop {
+        match db.insert(json!({"body": "x".repeat(100)})) {
+            Ok(id) => ids.push(id),
+            Err(e) => break e,
+        }
+    };
+    match err {
+        Error::LimitExceeded { ref field, limit, actual } => {
+            assert_eq!(field, "_log");
+            assert_eq!(limit, 1024);
+            assert!(actual > 1024);
+        }
+        other => panic!("expected LimitExceeded, got {:?}", other),
+    }
+    assert!(std::fs::metadata(&path).unwrap().len() <= 1024);
+
+    // Deletes still go through, and compaction makes room again
+    let len_before = db.len();
+    for id in &ids[..4] {
+        db.delete(id).unwrap();
+    }
+    assert_eq!(db.len(), len_before - 4);
+    db.compact().unwrap();
+    db.insert(json!({"body": "fits again"})).unwrap();
+}
+
+#[test]
+fn log_quota_rejection_leaves_updates_and_patches_unapplied() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("quota.jsonl");
+    let db = Database::open(&path).unwrap();
+    db.create_index("status").unwrap();
+    let id = db.insert(json!({"status": "open", "tags": ["a"]})).unwrap();
+    let before = db.get(&id).unwrap();
+    let log_len = std::fs::metadata(&path).unwrap().len();
+    let db = db.with_limits(Limits {
+        max_log_bytes: Some(log_len),
+        ..Limits::default()
+    });
+
+    let rejected = |result: ndb::Result<()>| {
+        assert!(matches!(result, Err(Error::LimitExceeded { ref field, .. }) if field == "_log"));
+    };
+    rejected(db.update(&id, json!({"status": "closed"})));
+    rejected(db.set(&id, "status", json!("closed")));
+    rejected(db.array_push(&id, "tags", json!("b")));
+    rejected(db.remove(&id, "tags"));
+
+    assert_eq!(db.get(&id).unwrap(), before);
+    assert_eq!(db.find("status", &json!("open")).len(), 1);
+    assert!(db.find("status", &json!("closed")).is_empty());
+
+    drop(db);
+    assert_eq!(Database::open(&path).unwrap().get(&id).unwrap(), before);
+}
+
+#[test]
+fn failed_tombstone_append_leaves_document_live() {
+    let (db, dir) = setup();
+    let path = dir.path().join("phase2.jsonl");
+    db.create_index("status").unwrap();
+    let id = db.insert(json!({"status": "open"})).unwrap();
+    // Compaction closes the log handle; a directory in its place makes the reopen fail
+    db.compact().unwrap();
+    std::fs::remove_file(&path).unwrap();
+    std::fs::create_dir(&path).unwrap();
+
+    assert!(matches!(db.delete(&id), Err(Error::Io { .. })));
+    assert!(db.contains(&id));
+    assert_eq!(db.find("status", &json!("open")).len(), 1);
+    assert!(db.deleted_ids().is_empty());
+    assert!(!dir.path().join("_trash").join("docs").join("phase2.jsonl").exists());
+}
+
+#[test]
+fn namespaces_scope_queries_counts_and_delete_all() {
+    let (db, dir) = setup();
+    db.create_index("kind").unwrap();
+    let acme = db.namespace("acme").unwrap();
+    let globex = db.namespace("globex").unwrap();
+    for n in 0..5 {
+        acme.insert(json!({"kind": "note", "n": n})).unwrap();
+    }
+    let other = globex.insert(json!({"kind": "note", "n": 0})).unwrap();
+    db.insert(json!({"kind": "note"})).unwrap();
+
+    assert_eq!(acme.len(), 5);
+    assert_eq!(globex.len(), 1);
+    assert_eq!(acme.query(&json!({"kind": "note"})).len(), 5);
+    assert_eq!(acme.query(&json!({"n": {"$lt": 2}})).len(), 2);
+    let opts = QueryOptions {
+        limit: Some(2),
+        sort_by: Some(("n".to_string(), SortDir::Desc)),
+        ..QueryOptions::default()
+    };
+    let page = acme.query_with(&json!({}), &opts);
+    assert_eq!(page.iter().map(|d| d["n"].as_i64().unwrap()).collect::<Vec<_>>(), vec![4, 3]);
+    assert!(matches!(acme.get(&other), Err(Error::NotFound { .. })));
+    assert_eq!(db.metrics().queries, 3);
+    let op = ndb::OperationHandle::default();
+    op.cancel();
+    assert!(matches!(acme.query_cancellable(&json!({}), &QueryOptions::default(), &op), Err(Error::Cancelled { .. })));
+
+    assert_eq!(acme.delete_all().unwrap(), 5);
+    assert!(acme.is_empty());
+    assert_eq!(db.len(), 2);
+    assert_eq!(db.deleted_ids().len(), 5);
+
+    drop(db);
+    let db = Database::open(dir.path().join("phase2.jsonl")).unwrap();
+    assert_eq!(db.namespace("acme").unwrap().len(), 0);
+    assert_eq!(db.namespace("globex").unwrap().get(&other).unwrap()["n"], 0);
+}
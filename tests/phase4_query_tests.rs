@@ -2,7 +2,7 @@
 //!
 //! Tests single field queries, opt-in indexing, and JSON AST query evaluator.
 
-use ndb::{Database, QueryOptions, SortDir};
+use ndb::{Aggregation, Database, QueryOptions, SortDir};
 use serde_json::json;
 use tempfile::TempDir;
 
@@ -252,6 +252,241 @@ fn query_not() {
     assert_eq!(results.len(), 3); // active users
 }
 
+#[test]
+fn query_field_level_not() {
+    let (db, _dir) = setup();
+    populate_db(&db);
+    db.insert(json!({"name": "frank", "deprecated": true})).unwrap();
+    db.insert(json!({"name": "gina", "deprecated": false})).unwrap();
+
+    // Missing field fails the inner condition, so $not matches it
+    let results = db.query(json!({"deprecated": {"$not": {"$eq": true}}}));
+    assert_eq!(results.len(), 6);
+    assert!(results.iter().all(|d| d["name"] != "frank"));
+
+    let results = db.query(json!({"age": {"$not": {"$gte": 30}}}));
+    // bob(25), diana(28), frank, gina (no age)
+    assert_eq!(results.len(), 4);
+
+    // Double negation on a missing field
+    let results = db.query(json!({"avatar": {"$not": {"$not": {"$exists": true}}}}));
+    assert!(results.is_empty());
+}
+
+#[test]
+fn query_is_null() {
+    let (db, _dir) = setup();
+    db.insert(json!({"name": "a", "parent": null})).unwrap();
+    db.insert(json!({"name": "b", "parent": "a"})).unwrap();
+    db.insert(json!({"name": "c"})).unwrap();
+
+    let null_parent = db.query(json!({"parent": {"$isNull": true}}));
+    assert_eq!(null_parent.len(), 1);
+    assert_eq!(null_parent[0]["name"], "a");
+
+    // $isNull: false matches both non-null values and missing fields
+    let not_null = db.query(json!({"parent": {"$isNull": false}}));
+    assert_eq!(not_null.len(), 2);
+
+    // Present-and-non-null
+    let set = db.query(json!({"parent": {"$exists": true, "$isNull": false}}));
+    assert_eq!(set.len(), 1);
+    assert_eq!(set[0]["name"], "b");
+}
+
+#[test]
+fn query_missing_field_fails_comparisons() {
+    let (db, _dir) = setup();
+    db.insert(json!({"name": "a", "age": 1})).unwrap();
+    db.insert(json!({"name": "b"})).unwrap();
+
+    assert_eq!(db.query(json!({"age": {"$ne": 1}})).len(), 0);
+    assert_eq!(db.query(json!({"age": {"$nin": [1]}})).len(), 0);
+    assert_eq!(db.query(json!({"age": {"$exists": false, "$gt": 0}})).len(), 0);
+}
+
+fn populate_tagged(db: &Database) {
+    db.insert(json!({"name": "a", "tags": ["rust", "db"]})).unwrap();
+    db.insert(json!({"name": "b", "tags": ["rust"]})).unwrap();
+    db.insert(json!({"name": "c", "tags": ["js", "db", 3]})).unwrap();
+    db.insert(json!({"name": "d", "tags": []})).unwrap();
+    db.insert(json!({"name": "e", "tags": "rust"})).unwrap(); // not an array
+    db.insert(json!({"name": "f"})).unwrap();
+}
+
+fn names(docs: Vec<serde_json::Value>) -> Vec<String> {
+    let mut names: Vec<String> = docs
+        .iter()
+        .map(|d| d["name"].as_str().unwrap().to_string())
+        .collect();
+    names.sort();
+    names
+}
+
+#[test]
+fn query_contains() {
+    let (db, _dir) = setup();
+    populate_tagged(&db);
+
+    assert_eq!(names(db.query(json!({"tags": {"$contains": "rust"}}))), ["a", "b"]);
+    assert_eq!(names(db.query(json!({"tags": {"$contains": 3.0}}))), ["c"]);
+    // Plain equality still compares the whole value
+    assert_eq!(names(db.query(json!({"tags": "rust"}))), ["e"]);
+}
+
+#[test]
+fn query_contains_any_and_all() {
+    let (db, _dir) = setup();
+    populate_tagged(&db);
+
+    assert_eq!(
+        names(db.query(json!({"tags": {"$containsAny": ["js", "db"]}}))),
+        ["a", "c"]
+    );
+    assert_eq!(
+        names(db.query(json!({"tags": {"$containsAll": ["rust", "db"]}}))),
+        ["a"]
+    );
+    // Empty sets: any never matches, all always matches (arrays only)
+    assert!(db.query(json!({"tags": {"$containsAny": []}})).is_empty());
+    assert_eq!(db.query(json!({"tags": {"$containsAll": []}})).len(), 4);
+}
+
+#[test]
+fn query_size() {
+    let (db, _dir) = setup();
+    populate_tagged(&db);
+
+    assert_eq!(names(db.query(json!({"tags": {"$size": 0}}))), ["d"]);
+    assert_eq!(names(db.query(json!({"tags": {"$size": {"$gte": 2}}}))), ["a", "c"]);
+    assert_eq!(
+        names(db.query(json!({"tags": {"$size": {"$gt": 0}, "$contains": "rust"}}))),
+        ["a", "b"]
+    );
+}
+
+fn populate_paths(db: &Database) {
+    db.insert(json!({"name": "a", "path": "src/lib.rs"})).unwrap();
+    db.insert(json!({"name": "b", "path": "src/bin/ndb.rs"})).unwrap();
+    db.insert(json!({"name": "c", "path": "SRC/README.md"})).unwrap();
+    db.insert(json!({"name": "d", "path": 42})).unwrap();
+    db.insert(json!({"name": "e"})).unwrap();
+}
+
+#[test]
+fn query_starts_with_ends_with() {
+    let (db, _dir) = setup();
+    populate_paths(&db);
+
+    assert_eq!(names(db.query(json!({"path": {"$startsWith": "src/"}}))), ["a", "b"]);
+    assert_eq!(names(db.query(json!({"path": {"$endsWith": ".rs"}}))), ["a", "b"]);
+    assert_eq!(
+        names(db.query(json!({"path": {"$startsWith": "src/", "$endsWith": ".md"}}))),
+        Vec::<String>::new()
+    );
+}
+
+#[test]
+fn query_string_ops_case_insensitive() {
+    let (db, _dir) = setup();
+    populate_paths(&db);
+
+    assert_eq!(
+        names(db.query(json!({"path": {"$startsWith": "src/", "$caseInsensitive": true}}))),
+        ["a", "b", "c"]
+    );
+    assert_eq!(
+        names(db.query(json!({"path": {"$endsWith": ".MD", "$caseInsensitive": true}}))),
+        ["c"]
+    );
+}
+
+#[cfg(feature = "regex")]
+#[test]
+fn query_regex() {
+    let (db, _dir) = setup();
+    populate_paths(&db);
+
+    assert_eq!(names(db.query(json!({"path": {"$regex": r"^src/.*\.rs$"}}))), ["a", "b"]);
+    assert_eq!(
+        names(db.query(json!({"path": {"$regex": "readme", "$caseInsensitive": true}}))),
+        ["c"]
+    );
+    // Invalid pattern matches nothing
+    assert!(db.query(json!({"path": {"$regex": "("}})).is_empty());
+}
+
+#[cfg(not(feature = "regex"))]
+#[test]
+fn query_regex_without_feature_matches_nothing() {
+    let (db, _dir) = setup();
+    populate_paths(&db);
+    assert!(db.query(json!({"path": {"$regex": "src"}})).is_empty());
+}
+
+#[test]
+fn query_array_index_paths() {
+    let (db, _dir) = setup();
+    db.insert(json!({"name": "a", "items": [{"sku": "x1", "qty": 2}, {"sku": "y1", "qty": 5}]}))
+        .unwrap();
+    db.insert(json!({"name": "b", "items": [{"sku": "y1", "qty": 1}]})).unwrap();
+    db.insert(json!({"name": "c", "items": []})).unwrap();
+    db.insert(json!({"name": "d", "matrix": [[1, 2], [3, 4]]})).unwrap();
+    db.insert(json!({"name": "e", "items": {"0": {"sku": "x1"}}})).unwrap();
+
+    assert_eq!(names(db.query(json!({"items.0.sku": "y1"}))), ["b"]);
+    assert_eq!(names(db.query(json!({"items.1.qty": {"$gte": 5}}))), ["a"]);
+    assert_eq!(names(db.query(json!({"matrix.1.0": 3}))), ["d"]);
+
+    // Out of range and non-numeric segments on arrays resolve to missing
+    assert_eq!(names(db.query(json!({"items.0": {"$exists": false}}))), ["c", "d"]);
+    assert!(db.query(json!({"items.first.sku": "x1"})).is_empty());
+
+    // Object keys that look like numbers still work on objects
+    assert_eq!(names(db.query(json!({"items.0.sku": "x1"}))), ["a", "e"]);
+}
+
+fn populate_stores(db: &Database) {
+    db.insert(json!({"name": "berlin", "loc": [52.5200, 13.4050]})).unwrap();
+    db.insert(json!({"name": "potsdam", "loc": [52.3906, 13.0645]})).unwrap();
+    db.insert(json!({"name": "munich", "loc": [48.1351, 11.5820]})).unwrap();
+    db.insert(json!({"name": "fiji", "loc": [-17.7134, 178.0650]})).unwrap();
+    db.insert(json!({"name": "samoa", "loc": [-13.7590, -172.1046]})).unwrap();
+    db.insert(json!({"name": "bad", "loc": "52.52,13.40"})).unwrap();
+}
+
+#[test]
+fn query_geo_radius() {
+    let (db, _dir) = setup();
+    populate_stores(&db);
+
+    // Potsdam is ~27 km from Berlin centre, Munich ~500 km
+    let near = db.query(json!({"loc": {"$geoRadius": {"lat": 52.52, "lon": 13.405, "meters": 30_000}}}));
+    assert_eq!(names(near), ["berlin", "potsdam"]);
+    let tight = db.query(json!({"loc": {"$geoRadius": {"lat": 52.52, "lon": 13.405, "meters": 20_000}}}));
+    assert_eq!(names(tight), ["berlin"]);
+
+    // Malformed operand matches nothing
+    assert!(db.query(json!({"loc": {"$geoRadius": {"lat": 52.52}}})).is_empty());
+}
+
+#[test]
+fn query_geo_box() {
+    let (db, _dir) = setup();
+    populate_stores(&db);
+
+    let germany = db.query(json!({"loc": {"$geoBox": {
+        "minLat": 47.0, "minLon": 5.0, "maxLat": 55.0, "maxLon": 15.0
+    }}}));
+    assert_eq!(names(germany), ["berlin", "munich", "potsdam"]);
+
+    // minLon > maxLon wraps across the antimeridian
+    let pacific = db.query(json!({"loc": {"$geoBox": {
+        "minLat": -20.0, "minLon": 170.0, "maxLat": -10.0, "maxLon": -170.0
+    }}}));
+    assert_eq!(names(pacific), ["fiji", "samoa"]);
+}
+
 #[test]
 fn query_nested_combinators() {
     let (db, _dir) = setup();
@@ -344,3 +579,271 @@ fn query_multiple_conditions() {
     // active AND age >= 28: alice(30), diana(28)
     assert_eq!(results.len(), 2);
 }
+
+// ─── Grouped Queries ────────────────────────────────────────────────
+
+fn populate_chunks(db: &Database) {
+    for (doc, chunk, score) in [
+        ("a", 0, 0.9),
+        ("a", 1, 0.4),
+        ("a", 2, 0.8),
+        ("a", 3, 0.7),
+        ("b", 0, 0.95),
+        ("b", 1, 0.1),
+        ("c", 0, 0.5),
+    ] {
+        db.insert(json!({"meta": {"doc_id": doc}, "chunk": chunk, "score": score}))
+            .unwrap();
+    }
+    db.insert(json!({"chunk": 9, "score": 1.0})).unwrap(); // no doc_id
+}
+
+#[test]
+fn query_grouped_top_n_per_group() {
+    let (db, _dir) = setup();
+    populate_chunks(&db);
+
+    let groups = db.query_grouped(
+        json!({}),
+        "meta.doc_id",
+        2,
+        QueryOptions {
+            sort_by: Some(("score".to_string(), SortDir::Desc)),
+            ..Default::default()
+        },
+    );
+
+    let keys: Vec<_> = groups.iter().map(|g| g.key.clone()).collect();
+    assert_eq!(keys, vec![json!("b"), json!("a"), json!("c")]);
+    let a_chunks: Vec<_> = groups[1].docs.iter().map(|d| d["chunk"].clone()).collect();
+    assert_eq!(a_chunks, vec![json!(0), json!(2)]);
+    assert_eq!(groups[2].docs.len(), 1);
+}
+
+#[test]
+fn query_grouped_pages_over_groups() {
+    let (db, _dir) = setup();
+    populate_chunks(&db);
+
+    let groups = db.query_grouped(
+        json!({"score": {"$gte": 0.5}}),
+        "meta.doc_id",
+        3,
+        QueryOptions {
+            limit: Some(1),
+            offset: Some(1),
+            sort_by: Some(("score".to_string(), SortDir::Desc)),
+        },
+    );
+    assert_eq!(groups.len(), 1);
+    assert_eq!(groups[0].key, "a");
+    assert_eq!(groups[0].docs.len(), 3);
+}
+
+#[test]
+fn query_grouped_numeric_keys_compare_by_value() {
+    let (db, _dir) = setup();
+    db.insert(json!({"g": 1})).unwrap();
+    db.insert(json!({"g": 1.0})).unwrap();
+    db.insert(json!({"g": "1"})).unwrap();
+
+    let groups = db.query_grouped(json!({}), "g", 10, QueryOptions::default());
+    assert_eq!(groups.len(), 2);
+}
+
+// ─── Batch Queries ──────────────────────────────────────────────────
+
+#[test]
+fn query_batch_matches_individual_queries() {
+    let (db, _dir) = setup();
+    populate_db(&db);
+
+    let by_score = QueryOptions {
+        sort_by: Some(("score".to_string(), SortDir::Desc)),
+        ..Default::default()
+    };
+    let queries = vec![
+        (json!({"status": "active"}), by_score.clone()),
+        (json!({"age": {"$gt": 100}}), QueryOptions::default()),
+        (json!({}), QueryOptions { limit: Some(2), ..by_score.clone() }),
+    ];
+
+    let results = db.query_batch(queries.clone());
+    assert_eq!(results.len(), 3);
+    for ((ast, opts), got) in queries.into_iter().zip(&results) {
+        assert_eq!(got, &db.query_with(ast, opts));
+    }
+    assert_eq!(results[0].len(), 3);
+    assert!(results[1].is_empty());
+    assert_eq!(results[2][0]["name"], "eve");
+}
+
+#[test]
+fn query_batch_empty() {
+    let (db, _dir) = setup();
+    populate_db(&db);
+    assert!(db.query_batch(Vec::new()).is_empty());
+}
+
+// ─── Index-Assisted Queries ─────────────────────────────────────────
+
+fn sorted_ids(docs: Vec<serde_json::Value>) -> Vec<String> {
+    let mut ids: Vec<String> = docs
+        .iter()
+        .map(|d| d["_id"].as_str().unwrap().to_string())
+        .collect();
+    ids.sort();
+    ids
+}
+
+#[test]
+fn aggregate_fields_over_matches() {
+    let (db, _dir) = setup();
+    populate_db(&db);
+    db.insert(json!({"name": "zed", "age": "unknown", "status": "active", "meta": {"source": "web"}})).unwrap();
+    let all = json!({});
+    let active = json!({"status": "active"});
+
+    assert_eq!(db.aggregate(all.clone(), "age", Aggregation::Count), json!(6));
+    assert_eq!(db.aggregate(active.clone(), "score", Aggregation::Count), json!(3));
+    assert_eq!(db.aggregate(all.clone(), "age", Aggregation::Min), json!(25));
+    assert_eq!(db.aggregate(all.clone(), "age", Aggregation::Max), json!(40));
+    assert_eq!(db.aggregate(all.clone(), "name", Aggregation::Max), json!("zed"));
+    assert_eq!(db.aggregate(active.clone(), "score", Aggregation::Avg), json!((150.0 + 80.0 + 95.0) / 3.0));
+    assert_eq!(db.aggregate(all.clone(), "missing", Aggregation::Avg), json!(null));
+    assert_eq!(db.aggregate(all.clone(), "meta.source", Aggregation::Count), json!(1));
+
+    assert_eq!(
+        db.aggregate(all, "status", Aggregation::Distinct),
+        json!([
+            {"value": "active", "count": 4},
+            {"value": "inactive", "count": 2},
+        ])
+    );
+}
+
+#[test]
+fn query_restricts_and_excludes_ids() {
+    let (db, _dir) = setup();
+    let ids = populate_db(&db);
+
+    // Candidate set from another system, minus the query document itself
+    let within = json!({"_id": {"$in": [ids[0], ids[1], ids[2], "missing"]}});
+    let similar = db.query(json!({"$and": [within, {"_id": {"$nin": [ids[0]]}}, {"status": "active"}]}));
+    assert_eq!(names(similar), ["bob"]);
+
+    assert_eq!(names(db.query(json!({"_id": ids[2]}))), ["charlie"]);
+    assert!(db.query(json!({"_id": {"$in": []}})).is_empty());
+    let others = db.query(json!({"_id": {"$nin": [ids[0], ids[1]]}}));
+    assert_eq!(others.len(), ids.len() - 2);
+}
+
+#[test]
+fn query_text_matches_words_with_and_without_index() {
+    let (db, _dir) = setup();
+    db.insert(json!({"name": "a", "body": "Rust is a systems language."})).unwrap();
+    db.insert(json!({"name": "b", "body": "The rust-belt, rusty and RUST"})).unwrap();
+    db.insert(json!({"name": "c", "body": ["tagged", "Rust"]})).unwrap();
+    let d = db.insert(json!({"name": "d", "body": 42})).unwrap();
+    db.insert(json!({"name": "e", "title": "rust"})).unwrap();
+
+    let queries = [
+        ("rust", vec!["a", "b", "c"]),
+        ("SYSTEMS, rust!", vec!["a"]),
+        ("rusty belt", vec!["b"]),
+        ("rus", vec![]),
+        ("", vec!["a", "b", "c", "d"]),
+    ];
+    for (q, want) in &queries {
+        assert_eq!(names(db.query(json!({"body": {"$text": q}}))), *want, "scan {:?}", q);
+    }
+
+    db.create_text_index("body").unwrap();
+    for (q, want) in &queries {
+        assert_eq!(names(db.query(json!({"body": {"$text": q}}))), *want, "indexed {:?}", q);
+    }
+
+    // The index follows writes, and equality lookups ignore it
+    db.set(&d, "body", json!("rust at last")).unwrap();
+    assert_eq!(names(db.query(json!({"body": {"$text": "rust"}}))), ["a", "b", "c", "d"]);
+    assert_eq!(names(db.find("body", &json!("rust at last"))), ["d"]);
+    assert!(db.find("body", &json!("rust")).is_empty());
+    assert!(db.verify(ndb::VerifyLevel::Full).unwrap().is_ok());
+}
+
+#[test]
+fn indexed_query_matches_full_scan() {
+    let (db, _dir) = setup();
+    populate_db(&db);
+    db.insert(json!({"name": "frank", "age": 30.0, "status": "active"})).unwrap();
+    db.insert(json!({"name": "gina", "status": null})).unwrap();
+
+    let queries = vec![
+        json!({"status": "active"}),
+        json!({"status": {"$eq": "inactive"}, "age": {"$gt": 36}}),
+        json!({"status": {"$in": ["inactive", null]}}),
+        json!({"age": 30}),
+        json!({"age": {"$in": [25, 30]}}),
+        json!({"$or": [{"status": "inactive"}, {"name": "bob"}]}),
+        json!({"$or": [{"status": "inactive"}, {"age": {"$lt": 26}}]}),
+        json!({"$and": [{"status": "active"}, {"age": {"$gte": 28}}]}),
+        json!([{"status": "active"}, {"name": "alice"}]),
+        json!({"$not": {"status": "active"}}),
+        json!({"status": {"$ne": "active"}}),
+    ];
+
+    let expected: Vec<_> = queries.iter().map(|q| sorted_ids(db.query(q.clone()))).collect();
+
+    db.create_index("status").unwrap();
+    db.create_btree_index("age").unwrap();
+    db.create_index("name").unwrap();
+
+    for (q, want) in queries.into_iter().zip(expected) {
+        assert_eq!(sorted_ids(db.query(q.clone())), want, "query {}", q);
+    }
+}
+
+#[test]
+fn indexes_follow_patches_and_restore() {
+    let (db, _dir) = setup();
+    let ids = populate_db(&db);
+    db.create_index("status").unwrap();
+
+    db.set(&ids[0], "status", json!("banned")).unwrap();
+    db.remove(&ids[1], "status").unwrap();
+    db.array_push(&ids[2], "status", json!("x")).unwrap(); // replaces the string with ["x"]
+
+    assert_eq!(db.query(json!({"status": "banned"})).len(), 1);
+    assert_eq!(db.find("status", &json!("banned")).len(), 1);
+    assert_eq!(db.query(json!({"status": "active"})).len(), 1); // diana
+    assert_eq!(db.find("status", &json!("inactive")).len(), 1); // eve
+
+    db.delete(&ids[3]).unwrap();
+    assert!(db.query(json!({"status": "active"})).is_empty());
+    db.restore(&ids[3]).unwrap();
+    assert_eq!(db.query(json!({"status": "active"})).len(), 1);
+}
+
+#[test]
+fn query_explain_reports_plan_and_counts() {
+    let (db, _dir) = setup();
+    populate_db(&db);
+    let ast = json!({"status": "active", "score": {"$gt": 90}});
+    let opts = QueryOptions {
+        limit: Some(1),
+        ..QueryOptions::default()
+    };
+
+    let (results, plan) = db.query_explain(ast.clone(), opts.clone());
+    assert_eq!(results.len(), 1);
+    assert!(!plan.indexed);
+    assert_eq!((plan.documents, plan.scanned), (5, 5));
+    assert_eq!((plan.matched, plan.returned), (2, 1));
+    assert!((plan.selectivity() - 0.4).abs() < 1e-9);
+
+    db.create_index("status").unwrap();
+    let (indexed_results, plan) = db.query_explain(ast, opts);
+    assert_eq!(indexed_results.len(), 1);
+    assert!(plan.indexed);
+    assert_eq!((plan.documents, plan.scanned, plan.matched), (5, 3, 2));
+}
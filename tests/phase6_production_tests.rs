@@ -1,10 +1,13 @@
 //! Integration tests for nDB Phase 6: Production Polish
 //!
 //! Tests corruption recovery, crash simulation, edge cases,
-//! concurrent reads, and persistence guarantees.
+//! concurrent reads, persistence guarantees, and random workloads
+//! checked against an in-memory model.
 
-use ndb::{Database, Persistence, QueryOptions, SortDir};
+use ndb::{Database, Error, IssueKind, Persistence, QueryOptions, Replica, SortDir, VerifyLevel};
+use proptest::prelude::*;
 use serde_json::json;
+use std::collections::HashMap;
 use std::fs;
 use std::io::Write;
 use tempfile::TempDir;
@@ -65,9 +68,270 @@ fn open_handles_garbage_file() {
     assert_eq!(db.len(), 0);
 }
 
+#[test]
+fn open_with_recovery_quarantines_corrupt_lines() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("corrupted.jsonl");
+    let mut file = fs::File::create(&path).unwrap();
+    writeln!(file, r#"{{"_meta":{{"version":1,"created":"0"}}}}"#).unwrap();
+    writeln!(file, r#"{{"_id":"good1","v":1}}"#).unwrap();
+    writeln!(file, r#"{{"_id":"broken","v":2"#).unwrap();
+    writeln!(file, r#"{{"_id":"good2","v":3}}"#).unwrap();
+    writeln!(file, "not json at all").unwrap();
+    drop(file);
+
+    let (db, report) = Database::open_with_recovery(&path).unwrap();
+    assert!(!report.is_clean());
+    assert_eq!(report.quarantined.iter().map(|r| r.line).collect::<Vec<_>>(), vec![3, 5]);
+    assert_eq!(report.quarantined[1].raw, "not json at all");
+    assert_eq!(db.len(), 2);
+
+    // Lost lines are kept, with where they came from
+    let quarantine = report.quarantine_path.unwrap();
+    assert_eq!(quarantine, dir.path().join("_quarantine").join("corrupted.jsonl"));
+    let kept: Vec<serde_json::Value> = fs::read_to_string(&quarantine)
+        .unwrap()
+        .lines()
+        .map(|l| serde_json::from_str(l).unwrap())
+        .collect();
+    assert_eq!(kept.len(), 2);
+    assert_eq!(kept[0]["raw"], r#"{"_id":"broken","v":2"#);
+    assert_eq!(kept[0]["line"], 3);
+
+    // The log was rewritten clean and stays writable
+    db.insert(json!({"v": 4})).unwrap();
+    drop(db);
+    let (db, report) = Database::open_with_recovery(&path).unwrap();
+    assert!(report.is_clean());
+    assert_eq!(report.quarantine_path, None);
+    assert_eq!(db.len(), 3);
+    assert_eq!(fs::read_to_string(&quarantine).unwrap().lines().count(), 2);
+}
+
+#[test]
+fn open_strict_refuses_corrupt_lines_with_offset() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("strict.jsonl");
+    let header = r#"{"_meta":{"version":1,"created":"0"}}"#;
+    let good = r#"{"_id":"good1","v":1}"#;
+    fs::write(&path, format!("{}\n{}\n{{\"_id\":\"broken\"\n", header, good)).unwrap();
+    let before = fs::read(&path).unwrap();
+
+    match Database::open_strict(&path) {
+        Err(Error::Corruption { file, message }) => {
+            assert_eq!(file, path);
+            let offset = header.len() + good.len() + 2;
+            assert!(message.contains(&format!("line 3 at byte {}", offset)), "{}", message);
+        }
+        other => panic!("expected Corruption, got {:?}", other.map(|db| db.len())),
+    }
+    assert_eq!(fs::read(&path).unwrap(), before);
+
+    // Lenient open still loads the readable part and reports the offset
+    let (db, report) = Database::open_with_recovery(&path).unwrap();
+    assert_eq!(db.len(), 1);
+    assert_eq!(report.quarantined[0].offset, (header.len() + good.len() + 2) as u64);
+    drop(db);
+    assert_eq!(Database::open_strict(&path).unwrap().len(), 1);
+}
+
+#[test]
+fn open_with_recovery_clean_log_is_untouched() {
+    let (db, dir) = setup();
+    let id = db.insert(json!({"v": 1})).unwrap();
+    db.delete(&id).unwrap();
+    drop(db);
+
+    let path = dir.path().join("phase6.jsonl");
+    let before = fs::read_to_string(&path).unwrap();
+    let (db, report) = Database::open_with_recovery(&path).unwrap();
+    assert!(report.is_clean());
+    assert_eq!(db.deleted_ids(), vec![id]);
+    assert_eq!(fs::read_to_string(&path).unwrap(), before);
+    assert!(!dir.path().join("_quarantine").exists());
+}
+
+/// Replay `bytes` as a log through every reader: open, recovery, point in
+/// time, verify and compaction. Any of them may fail, none may panic.
+fn replay_untrusted(path: &std::path::Path, bytes: &[u8]) {
+    fs::write(path, bytes).unwrap();
+    let _ = Database::open_at(path, 3);
+    if let Ok(db) = Database::open(path) {
+        let _ = db.verify(VerifyLevel::Full);
+        for id in db.iter().iter().filter_map(|d| d["_id"].as_str().map(String::from)) {
+            let _ = db.set(&id, "tags.0", json!(1));
+            let _ = db.array_push(&id, "tags", json!(1));
+        }
+        let _ = db.compact();
+    }
+    fs::write(path, bytes).unwrap();
+    let _ = Database::open_with_recovery(path);
+}
+
+/// Random JSON biased towards the keys and paths the log format uses.
+fn log_like_value(rng: &mut fastrand::Rng, depth: u32) -> serde_json::Value {
+    const KEYS: [&str; 10] = ["_id", "_op", "_version", "_deleted", "path", "field", "value", "docs", "tags", "o"];
+    const STRINGS: [&str; 10] = ["a", "", ".", "a..b", "tags.0.0", "tags.99999999999999999999", "o.k.-1", "set", "remove", "batch"];
+    match rng.u8(0..8) {
+        0 => json!(null),
+        1 => json!(rng.bool()),
+        2 => json!(rng.i64(..)),
+        3 => json!(rng.f64() * 1e300),
+        4 if depth < 3 => (0..rng.usize(0..3)).map(|_| log_like_value(rng, depth + 1)).collect(),
+        5 if depth < 3 => (0..rng.usize(0..4))
+            .map(|_| (KEYS[rng.usize(..KEYS.len())].to_string(), log_like_value(rng, depth + 1)))
+            .collect::<serde_json::Map<_, _>>()
+            .into(),
+        _ => json!(STRINGS[rng.usize(..STRINGS.len())]),
+    }
+}
+
+#[test]
+fn mutated_logs_never_panic() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("mutated.jsonl");
+    let db = Database::open(&path).unwrap();
+    let a = db.insert(json!({"n": 1, "tags": ["x"], "o": {"k": [1, 2]}})).unwrap();
+    let b = db.insert(json!({"s": "hello"})).unwrap();
+    db.set(&a, "o.k.0", json!(9)).unwrap();
+    db.array_push(&a, "tags", json!("z")).unwrap();
+    db.remove(&a, "tags.0").unwrap();
+    db.delete(&b).unwrap();
+    db.insert_batch(vec![json!({"q": 1}), json!({"q": 2})]).unwrap();
+    db.flush().unwrap();
+    drop(db);
+    let seed = fs::read(&path).unwrap();
+
+    // Byte-level damage to a real log: flips, cuts, splices of JSON tokens
+    const TOKENS: [&str; 8] = ["{", "}", "[", "\"", "\n", "99999999999999999999", "\"_id\"", "\\u0000"];
+    let mut rng = fastrand::Rng::with_seed(7);
+    for _ in 0..500 {
+        let mut bytes = seed.clone();
+        for _ in 0..rng.usize(1..5) {
+            let at = rng.usize(..=bytes.len());
+            match rng.u8(0..4) {
+                0 if at < bytes.len() => bytes[at] = rng.u8(..),
+                1 => bytes.truncate(at),
+                2 => {
+                    let end = rng.usize(at..=bytes.len());
+                    bytes.drain(at..end);
+                }
+                _ => {
+                    bytes.splice(at..at, TOKENS[rng.usize(..TOKENS.len())].bytes());
+                }
+            }
+        }
+        replay_untrusted(&path, &bytes);
+    }
+
+    // Well-formed JSON records with the wrong shapes and types
+    for _ in 0..500 {
+        let mut text = String::new();
+        for _ in 0..rng.usize(1..8) {
+            let mut record = match log_like_value(&mut rng, 2) {
+                serde_json::Value::Object(map) => map,
+                _ => serde_json::Map::new(),
+            };
+            let id = if rng.bool() { json!(a) } else { log_like_value(&mut rng, 2) };
+            record.insert("_id".into(), id);
+            let op = ["set", "remove", "array_push", "batch", "unknown"][rng.usize(..5)];
+            if rng.bool() {
+                record.insert("_op".into(), json!(op));
+            }
+            for key in ["path", "field", "value", "docs", "_deleted", "_version"] {
+                if rng.u8(0..3) == 0 {
+                    record.insert(key.into(), log_like_value(&mut rng, 1));
+                }
+            }
+            text.push_str(&serde_json::Value::Object(record).to_string());
+            text.push('\n');
+        }
+        replay_untrusted(&path, text.as_bytes());
+    }
+}
+
+// ─── Format Versioning ───────────────────────────────────────────────
+
+#[test]
+fn open_refuses_newer_format() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("future.jsonl");
+    fs::write(&path, "{\"_meta\":{\"version\":99,\"created\":\"0\"}}\n{\"_id\":\"a\"}\n").unwrap();
+
+    match Database::open(&path) {
+        Err(Error::UnsupportedVersion { found, supported, .. }) => {
+            assert_eq!(found, 99);
+            assert_eq!(supported, ndb::storage::STORAGE_VERSION);
+        }
+        other => panic!("expected UnsupportedVersion, got {:?}", other.map(|_| ())),
+    }
+    assert!(matches!(
+        ndb::migrate::upgrade(&path, true),
+        Err(Error::UnsupportedVersion { .. })
+    ));
+}
+
+#[test]
+fn upgrade_headerless_log() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("legacy.jsonl");
+    let legacy = "{\"_id\":\"a\",\"n\":1}\n{\"_id\":\"a\",\"_op\":\"set\",\"path\":\"n\",\"value\":2}\n{\"_id\":\"b\"}\n{\"_id\":\"b\",\"_deleted\":1}\n";
+    fs::write(&path, legacy).unwrap();
+
+    // Dry run reports the plan and writes nothing
+    let plan = ndb::migrate::upgrade(&path, true).unwrap();
+    assert_eq!((plan.from, plan.to, plan.records), (0, 2, 4));
+    assert_eq!(plan.steps, vec!["add _meta header", "allow batch and idempotency records"]);
+    assert_eq!(plan.backup, None);
+    assert_eq!(fs::read_to_string(&path).unwrap(), legacy);
+
+    let (db, report) = Database::open_with_migrations(&path).unwrap();
+    assert!(!report.is_noop());
+    assert_eq!(db.get("a").unwrap()["n"], 2);
+    assert_eq!(db.deleted_ids(), vec!["b".to_string()]);
+    assert_eq!(ndb::migrate::log_version(&path).unwrap(), 2);
+    assert_eq!(fs::read_to_string(report.backup.unwrap()).unwrap(), legacy);
+
+    // History is kept record for record, not compacted
+    assert_eq!(db.log_seq().unwrap(), 4);
+    drop(db);
+    let (_, again) = Database::open_with_migrations(&path).unwrap();
+    assert!(again.is_noop());
+}
+
+#[test]
+fn upgrade_refuses_unreadable_lines() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("legacy.jsonl");
+    fs::write(&path, "{\"_id\":\"a\"}\n{broken\n").unwrap();
+
+    assert!(matches!(
+        ndb::migrate::upgrade(&path, false),
+        Err(Error::Corruption { .. })
+    ));
+    assert_eq!(fs::read_to_string(&path).unwrap(), "{\"_id\":\"a\"}\n{broken\n");
+}
+
+#[test]
+fn open_upgrades_older_log_before_writing_batches() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("v1.jsonl");
+    let v1 = "{\"_meta\":{\"version\":1,\"created\":\"0\"}}\n{\"_id\":\"a\",\"n\":1}\n";
+    fs::write(&path, v1).unwrap();
+
+    let db = Database::open(&path).unwrap();
+    assert_eq!(ndb::migrate::log_version(&path).unwrap(), ndb::storage::STORAGE_VERSION);
+    assert_eq!(fs::read_to_string(dir.path().join("v1.jsonl.v1.bak")).unwrap(), v1);
+    assert_eq!(db.get("a").unwrap()["n"], 1);
+    db.insert_batch(vec![json!({"n": 2}), json!({"n": 3})]).unwrap();
+    drop(db);
+    assert_eq!(Database::open(&path).unwrap().len(), 3);
+}
+
 // ─── Crash Simulation ────────────────────────────────────────────────
 
 #[test]
+#[allow(clippy::write_literal)]
 fn crash_during_write_preserves_previous_data() {
     let dir = TempDir::new().unwrap();
     let path = dir.path().join("crash.jsonl");
@@ -108,6 +372,94 @@ fn crash_with_multiple_partial_lines() {
     assert!(db.get("c").is_ok());
 }
 
+fn snapshot(db: &Database) -> Vec<String> {
+    let mut docs: Vec<String> = db.iter().iter().map(|d| d.to_string()).collect();
+    docs.sort();
+    docs
+}
+
+#[test]
+fn crash_at_every_byte_recovers_a_prefix_of_writes() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("cut.jsonl");
+
+    // Run a workload, recording the log length and state after each write
+    let db = Database::open(&path).unwrap();
+    let mut points = vec![(fs::metadata(&path).unwrap().len(), snapshot(&db))];
+    let mut record = |db: &Database| {
+        db.flush().unwrap();
+        points.push((fs::metadata(&path).unwrap().len(), snapshot(db)));
+    };
+    let a = db.insert(json!({"name": "a", "tags": []})).unwrap();
+    record(&db);
+    let b = db.insert(json!({"name": "b", "n": 1})).unwrap();
+    record(&db);
+    db.set(&a, "nested.x", json!(1)).unwrap();
+    record(&db);
+    db.array_push(&a, "tags", json!("t")).unwrap();
+    record(&db);
+    db.remove(&b, "n").unwrap();
+    record(&db);
+    db.delete(&b).unwrap();
+    record(&db);
+    db.update(&a, json!({"name": "a2"})).unwrap();
+    record(&db);
+    drop(db);
+    let full = fs::read(&path).unwrap();
+    let header = points[0].0 as usize;
+
+    // Cut the log at every byte past the header. A record survives once its
+    // JSON is complete, even if the trailing newline was lost.
+    for cut in header..=full.len() {
+        fs::write(&path, &full[..cut]).unwrap();
+        let expected = &points
+            .iter()
+            .rev()
+            .find(|(end, _)| cut as u64 >= end - 1 || *end as usize == header)
+            .unwrap()
+            .1;
+        let db = Database::open(&path).unwrap();
+        assert_eq!(&snapshot(&db), expected, "cut at byte {}", cut);
+
+        // Writes after recovery are not lost to the torn tail
+        let id = db.insert(json!({"after": cut})).unwrap();
+        db.flush().unwrap();
+        drop(db);
+        let db = Database::open(&path).unwrap();
+        assert_eq!(db.get(&id).unwrap()["after"], cut, "cut at byte {}", cut);
+        assert_eq!(db.len(), expected.len() + 1, "cut at byte {}", cut);
+    }
+}
+
+#[test]
+fn leftover_temp_files_are_removed_on_open() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("tmp.jsonl");
+    let db = Database::open(&path).unwrap();
+    let id = db.insert(json!({"v": 1})).unwrap();
+    db.flush().unwrap();
+    drop(db);
+
+    // A compaction and a metadata write that died before their renames
+    let log_tmp = path.with_extension("jsonl.tmp");
+    let partial = r#"{"_id":"ghost","v"#;
+    fs::write(&log_tmp, partial).unwrap();
+    fs::write(dir.path().join("meta.json.tmp"), "{").unwrap();
+
+    let (db, report) = Database::open_with_recovery(&path).unwrap();
+    assert!(report.is_clean());
+    assert_eq!(report.removed_temp_files.len(), 2);
+    assert_eq!(report.reclaimed_bytes, partial.len() as u64 + 1);
+    assert!(!log_tmp.exists());
+    assert_eq!(db.len(), 1);
+    assert!(db.get("ghost").is_err());
+    db.compact().unwrap();
+    drop(db);
+    let db = Database::open(&path).unwrap();
+    assert_eq!(db.get(&id).unwrap()["v"], 1);
+    assert_eq!(db.len(), 1);
+}
+
 // ─── Persistence Guarantees ──────────────────────────────────────────
 
 #[test]
@@ -177,6 +529,88 @@ fn flush_then_drop_preserves_all() {
     }
 }
 
+#[test]
+fn group_commit_shares_fsyncs_between_writers() {
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("group.jsonl");
+
+    {
+        let db = Arc::new(
+            Database::open(&path)
+                .unwrap()
+                .with_persistence(Persistence::GroupCommit(Duration::from_millis(5))),
+        );
+        let handles: Vec<_> = (0..8)
+            .map(|t| {
+                let db = Arc::clone(&db);
+                thread::spawn(move || {
+                    for i in 0..10 {
+                        db.insert(json!({"t": t, "i": i})).unwrap();
+                    }
+                })
+            })
+            .collect();
+        for h in handles {
+            h.join().unwrap();
+        }
+
+        // Every write returned only after a covering sync, and writers shared them
+        let m = db.metrics();
+        assert_eq!(m.inserts, 80);
+        assert!(m.fsync_latency.count >= 1);
+        assert!(m.fsync_latency.count < 80, "{} fsyncs", m.fsync_latency.count);
+    }
+
+    assert_eq!(Database::open(&path).unwrap().len(), 80);
+}
+
+#[test]
+fn zero_sync_interval_is_clamped() {
+    use std::time::Duration;
+
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("zero.jsonl");
+    let db = Database::open(&path)
+        .unwrap()
+        .with_persistence(Persistence::GroupCommit(Duration::ZERO));
+    db.insert(json!({"n": 1})).unwrap();
+    db.set_persistence(Persistence::Scheduled(Duration::ZERO)).unwrap();
+    db.insert(json!({"n": 2})).unwrap();
+    db.flush().unwrap();
+    drop(db);
+    assert_eq!(Database::open(&path).unwrap().len(), 2);
+}
+
+#[test]
+fn scheduled_persistence_syncs_in_background() {
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("scheduled.jsonl");
+
+    let db = Database::open(&path)
+        .unwrap()
+        .with_persistence(Persistence::Scheduled(Duration::from_millis(5)));
+    db.insert(json!({"n": 1})).unwrap();
+    // Scheduled writes do not wait for the sync
+    assert_eq!(db.metrics().fsync_latency.count, 0);
+
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while db.metrics().fsync_latency.count == 0 {
+        assert!(Instant::now() < deadline, "background sync never ran");
+        thread::sleep(Duration::from_millis(5));
+    }
+    // Nothing new to sync: the thread stays idle
+    let synced = db.metrics().fsync_latency.count;
+    thread::sleep(Duration::from_millis(30));
+    assert_eq!(db.metrics().fsync_latency.count, synced);
+}
+
 // ─── Edge Cases ──────────────────────────────────────────────────────
 
 #[test]
@@ -430,6 +864,117 @@ fn compact_reduces_file_size_significantly() {
     assert_eq!(db.len(), 10);
 }
 
+// ─── Delete Durability ───────────────────────────────────────────────
+
+#[test]
+fn delete_survives_reopen_without_compaction() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("del_reopen.jsonl");
+
+    let (kept, deleted) = {
+        let db = Database::open(&path).unwrap();
+        let kept = db.insert(json!({"keep": true})).unwrap();
+        let deleted = db.insert(json!({"keep": false})).unwrap();
+        db.delete(&deleted).unwrap();
+        db.flush().unwrap();
+        (kept, deleted)
+    };
+
+    let db2 = Database::open(&path).unwrap();
+    assert_eq!(db2.len(), 1);
+    assert!(db2.get(&kept).is_ok());
+    assert!(db2.get(&deleted).is_err());
+    assert!(db2.deleted_ids().contains(&deleted));
+}
+
+#[test]
+fn delete_of_compacted_doc_survives_reopen() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("del_compacted.jsonl");
+
+    let id = {
+        let db = Database::open(&path).unwrap();
+        let id = db.insert(json!({"base": true})).unwrap();
+        // Bake the doc into the compacted base, then delete it from the tail
+        db.compact().unwrap();
+        db.delete(&id).unwrap();
+        db.flush().unwrap();
+        id
+    };
+
+    let db2 = Database::open(&path).unwrap();
+    assert!(db2.get(&id).is_err());
+    assert_eq!(db2.len(), 0);
+}
+
+#[test]
+fn delete_then_compact_then_reopen() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("del_compact_reopen.jsonl");
+
+    let id = {
+        let db = Database::open(&path).unwrap();
+        let id = db.insert(json!({"v": 1})).unwrap();
+        db.compact().unwrap();
+        db.delete(&id).unwrap();
+        db.compact().unwrap();
+        id
+    };
+
+    let content = fs::read_to_string(&path).unwrap();
+    assert!(!content.contains(&id), "compacted log must not carry the deleted doc");
+
+    let db2 = Database::open(&path).unwrap();
+    assert!(db2.get(&id).is_err());
+}
+
+#[test]
+fn delete_survives_torn_write_after_tombstone() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("del_torn.jsonl");
+
+    let id = {
+        let db = Database::open(&path).unwrap();
+        let id = db.insert(json!({"v": 1})).unwrap();
+        db.compact().unwrap();
+        db.delete(&id).unwrap();
+        db.flush().unwrap();
+        id
+    };
+
+    // Simulate a crash while appending the next record
+    let mut file = fs::OpenOptions::new().append(true).open(&path).unwrap();
+    write!(file, r#"{{"_id":"next","v":"#).unwrap();
+    drop(file);
+
+    let db2 = Database::open(&path).unwrap();
+    assert!(db2.get(&id).is_err());
+    assert_eq!(db2.len(), 0);
+}
+
+#[test]
+fn deleted_doc_restorable_after_compaction_and_reopen() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("del_restore.jsonl");
+
+    let id = {
+        let db = Database::open(&path).unwrap();
+        let id = db.insert(json!({"payload": "keep me"})).unwrap();
+        db.delete(&id).unwrap();
+        db.compact().unwrap();
+        id
+    };
+
+    // The tombstone is gone from the log, but the trash archive still holds the doc
+    let db2 = Database::open(&path).unwrap();
+    assert!(db2.get(&id).is_err());
+    db2.restore(&id).unwrap();
+    drop(db2);
+
+    let db3 = Database::open(&path).unwrap();
+    assert_eq!(db3.get(&id).unwrap()["payload"], "keep me");
+}
+
 // ─── Query Edge Cases ────────────────────────────────────────────────
 
 #[test]
@@ -577,3 +1122,580 @@ fn bucket_store_many_files() {
     let files = bucket.list().unwrap();
     assert_eq!(files.len(), 100);
 }
+
+// ─── Replication ─────────────────────────────────────────────────────
+
+#[test]
+fn replica_loads_existing_log() {
+    let (db, dir) = setup();
+    let a = db.insert(json!({"name": "a"})).unwrap();
+    db.insert(json!({"name": "b"})).unwrap();
+    db.flush().unwrap();
+
+    let replica = Replica::open(dir.path().join("phase6.jsonl")).unwrap();
+    assert_eq!(replica.len(), 2);
+    assert_eq!(replica.get(&a).unwrap()["name"], "a");
+    assert_eq!(replica.seq(), 2);
+}
+
+#[test]
+fn replica_catch_up_applies_only_new_records() {
+    let (db, dir) = setup();
+    let a = db.insert(json!({"n": 1, "tags": []})).unwrap();
+    db.flush().unwrap();
+    let replica = Replica::open(dir.path().join("phase6.jsonl")).unwrap();
+
+    db.set(&a, "n", json!(2)).unwrap();
+    db.array_push(&a, "tags", json!("x")).unwrap();
+    let b = db.insert(json!({"n": 10})).unwrap();
+    db.delete(&b).unwrap();
+    db.flush().unwrap();
+
+    assert_eq!(replica.catch_up().unwrap(), 4);
+    assert_eq!(replica.seq(), 5);
+    assert_eq!(replica.get(&a).unwrap(), db.get(&a).unwrap());
+    assert!(!replica.contains(&b));
+    assert_eq!(replica.deleted_ids(), vec![b]);
+
+    // Nothing new: nothing re-applied
+    assert_eq!(replica.catch_up().unwrap(), 0);
+    assert_eq!(replica.seq(), 5);
+    assert_eq!(replica.get(&a).unwrap()["tags"], json!(["x"]));
+}
+
+#[test]
+fn replica_waits_for_partial_line() {
+    let (db, dir) = setup();
+    let path = dir.path().join("phase6.jsonl");
+    db.insert(json!({"v": 1})).unwrap();
+    db.flush().unwrap();
+    let replica = Replica::open(&path).unwrap();
+
+    let mut file = fs::OpenOptions::new().append(true).open(&path).unwrap();
+    write!(file, r#"{{"_id":"late","v":"#).unwrap();
+    file.flush().unwrap();
+    assert_eq!(replica.catch_up().unwrap(), 0);
+    assert!(!replica.contains("late"));
+
+    writeln!(file, "2}}").unwrap();
+    file.flush().unwrap();
+    assert_eq!(replica.catch_up().unwrap(), 1);
+    assert_eq!(replica.get("late").unwrap()["v"], 2);
+}
+
+#[test]
+fn replica_reloads_after_leader_compaction() {
+    let (db, dir) = setup();
+    let keep = db.insert(json!({"v": "keep"})).unwrap();
+    let drop = db.insert(json!({"v": "drop"})).unwrap();
+    db.flush().unwrap();
+    let replica = Replica::open(dir.path().join("phase6.jsonl")).unwrap();
+
+    db.delete(&drop).unwrap();
+    db.update(&keep, json!({"v": "kept"})).unwrap();
+    db.compact().unwrap();
+    db.insert(json!({"v": "after"})).unwrap();
+    db.flush().unwrap();
+
+    replica.catch_up().unwrap();
+    assert_eq!(replica.len(), 2);
+    assert_eq!(replica.get(&keep).unwrap()["v"], "kept");
+    assert!(!replica.contains(&drop));
+    assert_eq!(replica.seq(), 2);
+}
+
+#[test]
+fn replica_reloads_after_back_to_back_compactions() {
+    let (db, dir) = setup();
+    let a = db.insert(json!({"v": "a1"})).unwrap();
+    let b = db.insert(json!({"v": "b1"})).unwrap();
+    db.flush().unwrap();
+    let replica = Replica::open(dir.path().join("phase6.jsonl")).unwrap();
+
+    // Both rewrites land in the same second, so without inode numbers only
+    // the header nonce tells the second file from the first
+    db.update(&a, json!({"v": "a2"})).unwrap();
+    db.compact().unwrap();
+    replica.catch_up().unwrap();
+    db.update(&b, json!({"v": "b2"})).unwrap();
+    db.compact().unwrap();
+    db.insert(json!({"v": "c"})).unwrap();
+    db.flush().unwrap();
+
+    replica.catch_up().unwrap();
+    assert_eq!(replica.len(), 3);
+    assert_eq!(replica.get(&a).unwrap()["v"], "a2");
+    assert_eq!(replica.get(&b).unwrap()["v"], "b2");
+}
+
+#[test]
+fn replica_serves_queries() {
+    let (db, dir) = setup();
+    for i in 0..10 {
+        db.insert(json!({"n": i, "even": i % 2 == 0})).unwrap();
+    }
+    db.insert_batch(vec![json!({"n": 100, "even": true})]).unwrap();
+    db.flush().unwrap();
+
+    let replica = Replica::open(dir.path().join("phase6.jsonl")).unwrap();
+    assert_eq!(replica.find("even", &json!(true)).len(), 6);
+    let top = replica.query_with(
+        json!({"even": true}),
+        QueryOptions {
+            limit: Some(2),
+            offset: None,
+            sort_by: Some(("n".to_string(), SortDir::Desc)),
+        },
+    );
+    let ns: Vec<_> = top.iter().map(|d| d["n"].clone()).collect();
+    assert_eq!(ns, vec![json!(100), json!(8)]);
+}
+
+#[test]
+fn replica_open_missing_log_fails() {
+    let dir = TempDir::new().unwrap();
+    assert!(Replica::open(dir.path().join("nope.jsonl")).is_err());
+}
+
+// ─── Point-in-Time Recovery ──────────────────────────────────────────
+
+#[test]
+fn open_at_reads_state_as_of_seq() {
+    let (db, dir) = setup();
+    let path = dir.path().join("phase6.jsonl");
+    let a = db.insert(json!({"v": 1})).unwrap();
+    db.set(&a, "v", json!(2)).unwrap();
+    db.delete(&a).unwrap();
+
+    assert_eq!(db.log_seq().unwrap(), 3);
+    assert_eq!(Database::open_at(&path, 0).unwrap().len(), 0);
+    assert_eq!(Database::open_at(&path, 1).unwrap().get(&a).unwrap()["v"], 1);
+    assert_eq!(Database::open_at(&path, 2).unwrap().get(&a).unwrap()["v"], 2);
+    assert!(Database::open_at(&path, 3).unwrap().get(&a).is_err());
+    assert!(Database::open_at(&path, 4).is_err());
+}
+
+#[test]
+fn open_at_does_not_write_to_log() {
+    let (db, dir) = setup();
+    let path = dir.path().join("phase6.jsonl");
+    db.insert(json!({"v": 1})).unwrap();
+
+    let view = Database::open_at(&path, 1).unwrap();
+    view.insert(json!({"v": 2})).unwrap();
+    assert_eq!(view.len(), 2);
+    assert_eq!(db.log_seq().unwrap(), 1);
+}
+
+#[test]
+fn restore_to_undoes_bulk_delete() {
+    let (db, dir) = setup();
+    let ids: Vec<String> = (0..5)
+        .map(|i| db.insert(json!({"n": i})).unwrap())
+        .collect();
+    db.create_index("n").unwrap();
+    let checkpoint = db.log_seq().unwrap();
+
+    for id in &ids[1..] {
+        db.delete(id).unwrap();
+    }
+    db.update(&ids[0], json!({"n": 99})).unwrap();
+    let late = db.insert(json!({"n": 7})).unwrap();
+
+    assert_eq!(db.restore_to(checkpoint).unwrap(), 6);
+    assert_eq!(db.len(), 5);
+    assert_eq!(db.get(&ids[0]).unwrap()["n"], 0);
+    assert!(!db.contains(&late));
+    assert_eq!(db.find("n", &json!(3)).len(), 1);
+    assert!(db.find("n", &json!(99)).is_empty());
+
+    // The rollback is durable and itself part of history
+    drop(db);
+    let db = Database::open(dir.path().join("phase6.jsonl")).unwrap();
+    assert_eq!(db.len(), 5);
+    assert!(!db.contains(&late));
+    assert_eq!(db.restore_to(checkpoint).unwrap(), 0);
+}
+
+#[test]
+fn documents_rolled_back_by_restore_to_survive_compaction_in_trash() {
+    let (db, _dir) = setup();
+    db.insert(json!({"n": 1})).unwrap();
+    let checkpoint = db.log_seq().unwrap();
+    let late = db.insert(json!({"n": 2})).unwrap();
+
+    assert_eq!(db.restore_to(checkpoint).unwrap(), 1);
+    assert!(!db.contains(&late));
+    db.compact().unwrap();
+
+    db.restore(&late).unwrap();
+    assert_eq!(db.get(&late).unwrap()["n"], 2);
+}
+
+#[test]
+fn restore_to_appends_one_record_or_nothing() {
+    let (db, dir) = setup();
+    let keep = db.insert(json!({"n": 1})).unwrap();
+    let checkpoint = db.log_seq().unwrap();
+    db.update(&keep, json!({"n": 2})).unwrap();
+    let late = db.insert(json!({"n": 3})).unwrap();
+    let before = db.log_seq().unwrap();
+
+    // Over the log quota the whole rollback is refused
+    let path = dir.path().join("phase6.jsonl");
+    let db = db.with_limits(ndb::Limits {
+        max_log_bytes: Some(fs::metadata(&path).unwrap().len()),
+        ..ndb::Limits::default()
+    });
+    assert!(matches!(db.restore_to(checkpoint), Err(Error::LimitExceeded { .. })));
+    assert_eq!(db.get(&keep).unwrap()["n"], 2);
+    assert!(db.contains(&late));
+    assert_eq!(db.log_seq().unwrap(), before);
+
+    let db = db.with_limits(ndb::Limits::default());
+    assert_eq!(db.restore_to(checkpoint).unwrap(), 2);
+    assert_eq!(db.log_seq().unwrap(), before + 1);
+    assert_eq!(db.get(&keep).unwrap()["n"], 1);
+    assert!(!db.contains(&late));
+}
+
+#[test]
+fn restore_to_rejects_in_memory_and_out_of_range() {
+    let mem = Database::open_in_memory().unwrap();
+    assert!(mem.restore_to(0).is_err());
+
+    let (db, _dir) = setup();
+    db.insert(json!({"v": 1})).unwrap();
+    assert!(db.restore_to(5).is_err());
+    assert_eq!(db.len(), 1);
+}
+
+// ─── Integrity Verification ──────────────────────────────────────────
+
+#[test]
+fn health_reports_unusable_log_and_meta() {
+    let (db, dir) = setup();
+    db.insert(json!({"v": 1})).unwrap();
+    db.health().unwrap();
+    Database::open_in_memory().unwrap().health().unwrap();
+
+    fs::write(dir.path().join("meta.json"), "{not json").unwrap();
+    assert!(matches!(db.health(), Err(Error::Corruption { .. })));
+    fs::remove_file(dir.path().join("meta.json")).unwrap();
+
+    fs::remove_file(dir.path().join("phase6.jsonl")).unwrap();
+    assert!(matches!(db.health(), Err(Error::Io { .. })));
+}
+
+#[test]
+fn verify_clean_database() {
+    let (db, _dir) = setup();
+    db.create_index("n").unwrap();
+    let a = db.insert(json!({"n": 1})).unwrap();
+    db.insert_batch(vec![json!({"n": 2}), json!({"n": 3})]).unwrap();
+    db.set(&a, "n", json!(10)).unwrap();
+    db.delete(&a).unwrap();
+
+    let report = db.verify(VerifyLevel::Full).unwrap();
+    assert!(report.is_ok(), "{:?}", report.issues);
+    assert_eq!(report.records, 4);
+    assert_eq!(report.documents, 2);
+}
+
+#[test]
+fn verify_reports_every_log_problem() {
+    let (db, dir) = setup();
+    db.insert(json!({"n": 1})).unwrap();
+    let path = dir.path().join("phase6.jsonl");
+    let mut file = fs::OpenOptions::new().append(true).open(&path).unwrap();
+    writeln!(file, "{{garbage").unwrap();
+    writeln!(file, r#"{{"_id":"ghost","_op":"set","path":"n","value":1}}"#).unwrap();
+    write!(file, r#"{{"_id":"torn","n""#).unwrap();
+    drop(file);
+
+    let quick = db.verify(VerifyLevel::Quick).unwrap();
+    assert_eq!(quick.count(IssueKind::CorruptLine), 1);
+    assert_eq!(quick.count(IssueKind::OrphanPatch), 1);
+    assert_eq!(quick.count(IssueKind::TornTail), 1);
+    assert_eq!(quick.issues[0].line, Some(3));
+    assert_eq!(quick.issues.len(), 3);
+}
+
+#[test]
+fn verify_full_detects_log_memory_divergence() {
+    let (db, dir) = setup();
+    let id = db.insert(json!({"n": 1})).unwrap();
+    let path = dir.path().join("phase6.jsonl");
+    let mut file = fs::OpenOptions::new().append(true).open(&path).unwrap();
+    writeln!(file, r#"{{"_id":"{}","n":2}}"#, id).unwrap();
+    writeln!(file, r#"{{"_id":"stranger","n":3}}"#).unwrap();
+    drop(file);
+
+    assert!(db.verify(VerifyLevel::Quick).unwrap().is_ok());
+    let full = db.verify(VerifyLevel::Full).unwrap();
+    assert_eq!(full.count(IssueKind::StateMismatch), 2);
+}
+
+#[test]
+fn verify_full_detects_missing_files() {
+    let (db, dir) = setup();
+    let meta = db.bucket("docs").store("a.txt", b"hello", "text/plain").unwrap();
+    db.insert(json!({"attachment": meta._file.to_string_compact()})).unwrap();
+    db.insert(json!({"file": {"bucket": "docs", "id": meta._file.id, "ext": "txt"}})).unwrap();
+    db.insert(json!({"url": "https://example.com/page.html"})).unwrap();
+    assert!(db.verify(VerifyLevel::Full).unwrap().is_ok());
+
+    fs::remove_file(dir.path().join("_files").join("docs").join(meta._file.filename())).unwrap();
+    let report = db.verify(VerifyLevel::Full).unwrap();
+    assert_eq!(report.count(IssueKind::MissingFile), 2);
+}
+
+// ─── Metrics ─────────────────────────────────────────────────────────
+
+#[test]
+fn metrics_count_operations() {
+    let (db, _dir) = setup();
+    let db = db.with_persistence(Persistence::Immediate);
+    let a = db.insert(json!({"n": 1})).unwrap();
+    let b = db.insert(json!({"n": 2})).unwrap();
+    db.insert_batch(vec![json!({"n": 3}), json!({"n": 4})]).unwrap();
+    db.update(&a, json!({"n": 10})).unwrap();
+    db.set(&a, "tag", json!("x")).unwrap();
+    db.delete(&b).unwrap();
+    db.query(json!({"n": {"$gt": 1}}));
+    db.query_batch(vec![
+        (json!({"n": 3}), QueryOptions::default()),
+        (json!({"n": 4}), QueryOptions::default()),
+    ]);
+    db.flush().unwrap();
+    db.compact().unwrap();
+
+    let m = db.metrics();
+    assert_eq!(m.documents, 3);
+    assert_eq!(m.deleted, 1);
+    assert_eq!(m.inserts, 4);
+    assert_eq!(m.updates, 1);
+    assert_eq!(m.patches, 1);
+    assert_eq!(m.deletes, 1);
+    assert_eq!(m.queries, 3);
+    assert_eq!(m.flushes, 1);
+    assert_eq!(m.compactions, 1);
+    assert_eq!(m.write_latency.count, 6);
+    assert_eq!(m.query_latency.count, 2);
+    // One fsync per Immediate append plus the explicit flush
+    assert_eq!(m.fsync_latency.count, 7);
+    assert_eq!(m.compaction_duration.count, 1);
+    assert!(m.log_bytes > 0);
+}
+
+#[test]
+fn metrics_show_when_compaction_pays_off() {
+    let (db, dir) = setup();
+    let a = db.insert(json!({"n": 1})).unwrap();
+    let b = db.insert(json!({"n": 2})).unwrap();
+    db.update(&a, json!({"n": 10})).unwrap();
+    db.delete(&b).unwrap();
+
+    let m = db.metrics();
+    assert_eq!(m.log_records, 4);
+    assert_eq!(m.garbage_ratio(), 0.75);
+    assert!(m.trash_bytes > 0);
+    assert_eq!((m.last_flush, m.last_compaction), (None, None));
+
+    db.flush().unwrap();
+    db.compact().unwrap();
+    let m = db.metrics();
+    assert_eq!(m.log_records, 1);
+    assert_eq!(m.garbage_ratio(), 0.0);
+    assert!(m.last_flush.is_some() && m.last_compaction.is_some());
+    drop(db);
+
+    // Reopening counts the replayed records
+    let db = Database::open(dir.path().join("phase6.jsonl")).unwrap();
+    db.set(&a, "n", json!(11)).unwrap();
+    assert_eq!(db.metrics().log_records, 2);
+}
+
+#[test]
+fn metrics_failed_writes_are_not_counted() {
+    let db = Database::open_in_memory().unwrap();
+    assert!(db.update("missing", json!({})).is_err());
+    assert!(db.delete("missing").is_err());
+
+    let m = db.metrics();
+    assert_eq!(m.updates, 0);
+    assert_eq!(m.deletes, 0);
+    assert_eq!(m.log_bytes, 0);
+}
+
+#[test]
+fn memory_usage_tracks_documents_and_indexes() {
+    let db = Database::open_in_memory().unwrap();
+    assert_eq!(db.memory_usage().total(), 0);
+
+    let text = "x".repeat(10_000);
+    let id = db.insert(json!({"body": text, "tag": "a"})).unwrap();
+    let m = db.memory_usage();
+    assert!(m.documents > 10_000 && m.documents < 11_000, "{:?}", m);
+    assert_eq!(m.indexes, 0);
+
+    db.create_index("tag").unwrap();
+    assert!(db.memory_usage().indexes > 0);
+
+    db.delete(&id).unwrap();
+    let m = db.memory_usage();
+    assert_eq!(m.documents, 0);
+    assert_eq!(m.indexes, 0);
+    assert!(m.other > 0, "deleted ID is still tracked");
+}
+
+#[test]
+fn index_status_lists_kind_and_size() {
+    let db = Database::open_in_memory().unwrap();
+    db.insert(json!({"tag": "a", "n": 1})).unwrap();
+    db.insert(json!({"tag": "b"})).unwrap();
+    db.create_index("tag").unwrap();
+    db.create_btree_index("n").unwrap();
+
+    let status = db.index_status();
+    assert_eq!(status.len(), 2);
+    assert_eq!((status[0].field.as_str(), status[0].kind, status[0].entries), ("n", "btree", 1));
+    assert_eq!((status[1].field.as_str(), status[1].kind, status[1].entries), ("tag", "hash", 2));
+    let heap: usize = status.iter().map(|s| s.heap_bytes).sum();
+    assert_eq!(heap, db.memory_usage().indexes);
+}
+
+#[test]
+fn metrics_prometheus_exposition() {
+    let (db, _dir) = setup();
+    db.create_index("n").unwrap();
+    db.insert(json!({"n": 1})).unwrap();
+
+    let text = db.metrics().to_prometheus();
+    assert!(text.contains("ndb_documents 1\n"));
+    assert!(text.contains("ndb_indexes 1\n"));
+    assert!(text.contains("ndb_inserts_total 1\n"));
+    assert!(text.contains("ndb_write_duration_seconds_count 1\n"));
+    for line in text.lines().filter(|l| !l.starts_with('#')) {
+        let (name, value) = line.rsplit_once(' ').unwrap();
+        assert!(name.starts_with("ndb_"), "{}", line);
+        assert!(value.parse::<f64>().is_ok(), "{}", line);
+    }
+}
+
+// ─── Model Tests ─────────────────────────────────────────────────────
+
+/// One step of a random workload. Slots pick among ids inserted so far.
+#[derive(Debug, Clone)]
+enum Op {
+    Insert(i64),
+    Update(usize, i64),
+    Delete(usize),
+    Flush,
+    Compact,
+    Reopen,
+}
+
+fn op_strategy() -> impl Strategy<Value = Op> {
+    prop_oneof![
+        4 => (0..5i64).prop_map(Op::Insert),
+        2 => (any::<usize>(), 0..5i64).prop_map(|(slot, n)| Op::Update(slot, n)),
+        2 => any::<usize>().prop_map(Op::Delete),
+        1 => Just(Op::Flush),
+        1 => Just(Op::Compact),
+        1 => Just(Op::Reopen),
+    ]
+}
+
+fn open_indexed(path: &std::path::Path) -> Database {
+    let db = Database::open(path).unwrap();
+    if !db.has_index("n") {
+        db.create_index("n").unwrap();
+    }
+    if !db.has_index("r") {
+        db.create_btree_index("r").unwrap();
+    }
+    db
+}
+
+fn id_set(docs: Vec<serde_json::Value>) -> Vec<String> {
+    let mut ids: Vec<String> = docs.iter().map(|d| d["_id"].as_str().unwrap().to_string()).collect();
+    ids.sort();
+    ids
+}
+
+/// Compare every read path against the model. `n` is hash indexed, `r`
+/// BTree indexed and `m` unindexed; all three hold the same number.
+fn check_against_model(db: &Database, ids: &[String], model: &HashMap<String, i64>) {
+    assert_eq!(db.len(), model.len());
+    for id in ids {
+        match model.get(id) {
+            Some(n) => assert_eq!(db.get(id).unwrap()["m"], *n),
+            None => assert!(matches!(db.get(id), Err(Error::NotFound { .. }))),
+        }
+    }
+    for n in 0..5i64 {
+        let mut want: Vec<String> = model.iter().filter(|(_, v)| **v == n).map(|(id, _)| id.clone()).collect();
+        want.sort();
+        assert_eq!(id_set(db.find("n", &json!(n))), want);
+        assert_eq!(id_set(db.find("m", &json!(n))), want);
+
+        let mut want: Vec<String> = model.iter().filter(|(_, v)| **v >= n).map(|(id, _)| id.clone()).collect();
+        want.sort();
+        assert_eq!(id_set(db.query(json!({"r": {"$gte": n}}))), want);
+        assert_eq!(id_set(db.query(json!({"m": {"$gte": n}}))), want);
+    }
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(128))]
+
+    #[test]
+    fn random_workloads_match_a_hashmap_model(ops in prop::collection::vec(op_strategy(), 1..40)) {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("model.jsonl");
+        let mut db = open_indexed(&path);
+        let mut ids: Vec<String> = Vec::new();
+        let mut model: HashMap<String, i64> = HashMap::new();
+
+        for op in ops {
+            match op {
+                Op::Insert(n) => {
+                    let id = db.insert(json!({"n": n, "r": n, "m": n})).unwrap();
+                    ids.push(id.clone());
+                    model.insert(id, n);
+                }
+                Op::Update(slot, n) if !ids.is_empty() => {
+                    let id = &ids[slot % ids.len()];
+                    let result = db.update(id, json!({"n": n, "r": n, "m": n}));
+                    match model.get_mut(id) {
+                        Some(v) => {
+                            result.unwrap();
+                            *v = n;
+                        }
+                        None => prop_assert!(result.is_err()),
+                    }
+                }
+                Op::Delete(slot) if !ids.is_empty() => {
+                    let id = &ids[slot % ids.len()];
+                    let result = db.delete(id);
+                    match model.remove(id) {
+                        Some(_) => result.unwrap(),
+                        None => prop_assert!(result.is_err()),
+                    }
+                }
+                Op::Update(..) | Op::Delete(_) => {}
+                Op::Flush => db.flush().unwrap(),
+                Op::Compact => db.compact().unwrap(),
+                Op::Reopen => {
+                    drop(db);
+                    db = open_indexed(&path);
+                }
+            }
+            check_against_model(&db, &ids, &model);
+        }
+
+        drop(db);
+        check_against_model(&open_indexed(&path), &ids, &model);
+    }
+}